@@ -0,0 +1,381 @@
+//! `#[derive(DruidResult)]` generating the aggregations, dimensions and scan columns of a query
+//! from the struct its results are deserialized into. See `druid_io::DruidResult`.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Lit, Meta,
+    NestedMeta, PathArguments, Type,
+};
+
+const AGGREGATIONS: &[&str] = &[
+    "count",
+    "long_sum",
+    "double_sum",
+    "float_sum",
+    "long_max",
+    "double_max",
+    "float_max",
+    "long_min",
+    "double_min",
+    "float_min",
+    "long_first",
+    "double_first",
+    "float_first",
+    "long_last",
+    "double_last",
+    "float_last",
+    "string_first",
+    "string_last",
+    "long_any",
+    "double_any",
+    "float_any",
+    "string_any",
+];
+
+const DEFAULT_MAX_STRING_BYTES: usize = 1024;
+
+/// Implements `druid_io::DruidResult` for a struct with named fields.
+///
+/// Every field needs a `#[druid(...)]` attribute saying where its value comes from:
+/// - `#[druid(dimension)]` or `#[druid(dimension = "page")]`, output type follows the field type
+/// - an aggregation, e.g. `#[druid(count)]`, `#[druid(long_sum = "added")]`,
+///   `#[druid(string_first = "user", max_string_bytes = 256)]`; without a column name
+///   the field name is aggregated
+/// - `#[druid(timestamp)]` for the `__time` column of scan results
+/// - `#[druid(skip)]` for fields the query provides otherwise, e.g. post aggregations
+///
+/// Output names follow serde's `rename` and `rename_all` attributes.
+#[proc_macro_derive(DruidResult, attributes(druid))]
+pub fn derive_druid_result(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+enum Source {
+    Dimension(Option<String>),
+    Aggregation {
+        kind: String,
+        column: Option<String>,
+        max_string_bytes: Option<usize>,
+    },
+    Timestamp,
+    Skip,
+}
+
+struct ResultField<'a> {
+    name: String,
+    ty: &'a Type,
+    source: Source,
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "DruidResult can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "DruidResult can only be derived for structs",
+            ))
+        }
+    };
+    let rename_all = serde_value(&input.attrs, "rename_all")?;
+
+    let mut result_fields = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let name = match serde_value(&field.attrs, "rename")? {
+            Some(name) => name,
+            None => {
+                let ident = ident.to_string();
+                let ident = ident.trim_start_matches("r#");
+                match &rename_all {
+                    Some(rule) => rename(ident, rule).ok_or_else(|| {
+                        Error::new(
+                            input.span(),
+                            format!("unsupported rename_all rule `{}`", rule),
+                        )
+                    })?,
+                    None => ident.to_string(),
+                }
+            }
+        };
+        result_fields.push(ResultField {
+            name,
+            ty: &field.ty,
+            source: field_source(field)?,
+        });
+    }
+
+    let mut aggregations = vec![];
+    let mut dimensions = vec![];
+    let mut columns = vec![];
+    for field in &result_fields {
+        let name = &field.name;
+        match &field.source {
+            Source::Skip => continue,
+            Source::Timestamp => {}
+            Source::Dimension(dimension) => {
+                let dimension = dimension.as_ref().unwrap_or(name);
+                let output_type = format_ident!("{}", output_type(field.ty));
+                dimensions.push(quote! {
+                    ::druid_io::query::definitions::Dimension::Default {
+                        dimension: #dimension.to_string(),
+                        output_name: #name.to_string(),
+                        output_type: ::druid_io::query::definitions::OutputType::#output_type,
+                    }
+                });
+            }
+            Source::Aggregation {
+                kind,
+                column,
+                max_string_bytes,
+            } => {
+                let variant = format_ident!("{}", rename(kind, "PascalCase").unwrap_or_default());
+                let column = column.as_ref().unwrap_or(name);
+                let aggregation = match kind.as_str() {
+                    "count" => quote! { #variant { name: #name.to_string() } },
+                    "string_first" | "string_last" => {
+                        let max_string_bytes = max_string_bytes.unwrap_or(DEFAULT_MAX_STRING_BYTES);
+                        quote! {
+                            #variant {
+                                name: #name.to_string(),
+                                field_name: #column.to_string(),
+                                max_string_bytes: #max_string_bytes,
+                            }
+                        }
+                    }
+                    _ => quote! {
+                        #variant { name: #name.to_string(), field_name: #column.to_string() }
+                    },
+                };
+                aggregations.push(quote! {
+                    ::druid_io::query::definitions::Aggregation::#aggregation
+                });
+            }
+        }
+        columns.push(name);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::druid_io::DruidResult for #ident #type_generics #where_clause {
+            fn aggregations() -> Vec<::druid_io::query::definitions::Aggregation> {
+                vec![#(#aggregations),*]
+            }
+            fn dimensions() -> Vec<::druid_io::query::definitions::Dimension> {
+                vec![#(#dimensions),*]
+            }
+            fn columns() -> Vec<String> {
+                vec![#(#columns.to_string()),*]
+            }
+        }
+    })
+}
+
+fn field_source(field: &syn::Field) -> Result<Source, Error> {
+    let mut source = None;
+    let mut max_string_bytes = None;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("druid"))
+    {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new(meta.span(), "expected #[druid(...)]")),
+        };
+        for nested in list.nested {
+            let (path, value) = match &nested {
+                NestedMeta::Meta(Meta::Path(path)) => (path, None),
+                NestedMeta::Meta(Meta::NameValue(name_value)) => {
+                    (&name_value.path, Some(&name_value.lit))
+                }
+                _ => return Err(Error::new(nested.span(), "unsupported druid attribute")),
+            };
+            let key = path
+                .get_ident()
+                .map(|ident| ident.to_string())
+                .unwrap_or_default();
+            let parsed = match (key.as_str(), value) {
+                ("max_string_bytes", Some(Lit::Int(int))) => {
+                    max_string_bytes = Some(int.base10_parse()?);
+                    continue;
+                }
+                ("timestamp", None) => Source::Timestamp,
+                ("skip", None) => Source::Skip,
+                ("dimension", None) => Source::Dimension(None),
+                ("dimension", Some(Lit::Str(str))) => Source::Dimension(Some(str.value())),
+                ("count", None) => Source::Aggregation {
+                    kind: key.clone(),
+                    column: None,
+                    max_string_bytes: None,
+                },
+                (kind, value) if kind != "count" && AGGREGATIONS.contains(&kind) => {
+                    let column = match value {
+                        Some(Lit::Str(str)) => Some(str.value()),
+                        None => None,
+                        Some(lit) => {
+                            return Err(Error::new(lit.span(), "expected column name string"))
+                        }
+                    };
+                    Source::Aggregation {
+                        kind: key.clone(),
+                        column,
+                        max_string_bytes: None,
+                    }
+                }
+                _ => {
+                    return Err(Error::new(
+                        nested.span(),
+                        format!("unsupported druid attribute `{}`", key),
+                    ))
+                }
+            };
+            if source.replace(parsed).is_some() {
+                return Err(Error::new(
+                    nested.span(),
+                    "field can have only one druid source",
+                ));
+            }
+        }
+    }
+    match source {
+        Some(Source::Aggregation { kind, column, .. }) => Ok(Source::Aggregation {
+            kind,
+            column,
+            max_string_bytes,
+        }),
+        Some(source) => Ok(source),
+        None => Err(Error::new(
+            field.span(),
+            "field needs #[druid(dimension)], an aggregation like #[druid(long_sum = \"column\")], \
+             #[druid(timestamp)] or #[druid(skip)]",
+        )),
+    }
+}
+
+/// Value of `#[serde(key = "value")]` or `#[serde(key(deserialize = "value"))]`.
+fn serde_value(attrs: &[Attribute], key: &str) -> Result<Option<String>, Error> {
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("serde")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            _ => continue,
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(name_value)) if name_value.path.is_ident(key) => {
+                    if let Lit::Str(str) = name_value.lit {
+                        return Ok(Some(str.value()));
+                    }
+                }
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident(key) => {
+                    for nested in list.nested {
+                        if let NestedMeta::Meta(Meta::NameValue(name_value)) = nested {
+                            if let (true, Lit::Str(str)) =
+                                (name_value.path.is_ident("deserialize"), name_value.lit)
+                            {
+                                return Ok(Some(str.value()));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Applies serde's `rename_all` rule to a snake_case name.
+fn rename(name: &str, rule: &str) -> Option<String> {
+    let words = name.split('_').filter(|word| !word.is_empty());
+    let capitalized = |word: &str| {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+            None => String::new(),
+        }
+    };
+    Some(match rule {
+        "lowercase" => name.to_lowercase(),
+        "UPPERCASE" => name.to_uppercase(),
+        "snake_case" => name.to_string(),
+        "SCREAMING_SNAKE_CASE" => name.to_uppercase(),
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.replace('_', "-").to_uppercase(),
+        "PascalCase" => words.map(capitalized).collect(),
+        "camelCase" => {
+            let pascal: String = words.map(capitalized).collect();
+            let mut chars = pascal.chars();
+            match chars.next() {
+                Some(first) => first.to_lowercase().collect::<String>() + chars.as_str(),
+                None => String::new(),
+            }
+        }
+        _ => return None,
+    })
+}
+
+/// Druid output type of a dimension read into the field type, `Option`s are unwrapped.
+fn output_type(ty: &Type) -> &'static str {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last(),
+        _ => None,
+    };
+    let segment = match segment {
+        Some(segment) => segment,
+        None => return "STRING",
+    };
+    if segment.ident == "Option" {
+        if let PathArguments::AngleBracketed(arguments) = &segment.arguments {
+            if let Some(GenericArgument::Type(inner)) = arguments.args.first() {
+                return output_type(inner);
+            }
+        }
+    }
+    match segment.ident.to_string().as_str() {
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+        | "usize" => "LONG",
+        "f32" | "f64" => "FLOAT",
+        _ => "STRING",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rename() {
+        assert_eq!(rename("sum_added", "camelCase").unwrap(), "sumAdded");
+        assert_eq!(rename("sum_added", "PascalCase").unwrap(), "SumAdded");
+        assert_eq!(rename("sum_added", "kebab-case").unwrap(), "sum-added");
+        assert_eq!(rename("long_sum", "PascalCase").unwrap(), "LongSum");
+        assert!(rename("sum_added", "Title Case").is_none());
+    }
+
+    #[test]
+    fn test_output_type() {
+        let ty: Type = syn::parse_str("Option<i64>").unwrap();
+        assert_eq!(output_type(&ty), "LONG");
+        let ty: Type = syn::parse_str("std::string::String").unwrap();
+        assert_eq!(output_type(&ty), "STRING");
+        let ty: Type = syn::parse_str("f64").unwrap();
+        assert_eq!(output_type(&ty), "FLOAT");
+    }
+}
@@ -0,0 +1,301 @@
+//! Synchronous client for code that doesn't run an async runtime, e.g. CLI tools.
+//!
+//! Available with the `blocking` feature. [`BlockingDruidClient`] has the methods of
+//! [`DruidClient`], each one waiting for the query to complete on a runtime owned by the
+//! client. Calls are serialized on that runtime, and the client must not be used from
+//! within another runtime.
+//!
+//! ```rust,no_run
+//! use druid_io::blocking::BlockingDruidClient;
+//! use druid_io::query::sql::SqlQuery;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug)]
+//! struct Page {
+//!     page: String,
+//! }
+//!
+//! let client = BlockingDruidClient::new(vec!["localhost:8082".to_string()]).unwrap();
+//! let pages = client
+//!     .sql::<Page>(&SqlQuery::new("SELECT page FROM wikipedia LIMIT 10"))
+//!     .unwrap();
+//! ```
+use crate::client::{ClientResult, DruidClient, DruidClientError};
+use crate::interceptor::Interceptor;
+use crate::metrics::Metrics;
+use crate::query::response::{
+    ExplainPlan, GroupByResponse, MetadataResponse, ScanResponse, SearchResponse,
+    SegmentMetadataResponse, SqlColumn, SqlResponse, TimeBoundaryResponse, TimeseriesResponse,
+    TopNResponse,
+};
+use crate::query::sql::SqlQuery;
+use crate::query::{
+    group_by::GroupBy, scan::Scan, search::Search, segment_metadata::SegmentMetadata,
+    time_boundary::TimeBoundary, timeseries::Timeseries, top_n::TopN, DataSource, Query,
+};
+use crate::schema::{TableInfo, TableSchema};
+use crate::system::{SysFilter, SysSegment, SysServer, SysServerSegment, SysSupervisor, SysTask};
+use crate::transport::Transport;
+use crate::typed::TypedQuery;
+use futures::stream::{BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::runtime::Runtime;
+
+pub struct BlockingDruidClient {
+    client: DruidClient,
+    runtime: Mutex<Runtime>,
+}
+
+impl BlockingDruidClient {
+    pub fn new(nodes: Vec<String>) -> ClientResult<Self> {
+        BlockingDruidClient::from_client(DruidClient::new(nodes))
+    }
+
+    /// Runs the queries of an already configured client.
+    pub fn from_client(client: DruidClient) -> ClientResult<Self> {
+        let runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .map_err(|source| DruidClientError::Runtime { source })?;
+        Ok(BlockingDruidClient {
+            client,
+            runtime: Mutex::new(runtime),
+        })
+    }
+
+    /// See [`DruidClient::with_transport`].
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.client = self.client.with_transport(transport);
+        self
+    }
+
+    /// See [`DruidClient::with_interceptor`].
+    pub fn with_interceptor<I: Interceptor + 'static>(mut self, interceptor: I) -> Self {
+        self.client = self.client.with_interceptor(interceptor);
+        self
+    }
+
+    /// See [`DruidClient::with_metrics`].
+    pub fn with_metrics<M: Metrics + 'static>(mut self, metrics: M) -> Self {
+        self.client = self.client.with_metrics(metrics);
+        self
+    }
+
+    /// The async client the queries are run with.
+    pub fn client(&self) -> &DruidClient {
+        &self.client
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        block_on(&self.runtime, future)
+    }
+
+    pub fn query<T: DeserializeOwned + std::fmt::Debug + Serialize>(
+        &self,
+        query: &Query,
+    ) -> ClientResult<Vec<T>> {
+        self.block_on(self.client.query(query))
+    }
+
+    pub fn top_n<T: DeserializeOwned + std::fmt::Debug + Serialize>(
+        &self,
+        query: &TopN,
+    ) -> ClientResult<Vec<TopNResponse<T>>> {
+        self.block_on(self.client.top_n(query))
+    }
+
+    pub fn search<T: DeserializeOwned + std::fmt::Debug + Serialize>(
+        &self,
+        query: &Search,
+    ) -> ClientResult<Vec<SearchResponse>> {
+        self.block_on(self.client.search::<T>(query))
+    }
+
+    pub fn group_by<T: DeserializeOwned + std::fmt::Debug + Serialize>(
+        &self,
+        query: &GroupBy,
+    ) -> ClientResult<Vec<GroupByResponse<T>>> {
+        self.block_on(self.client.group_by(query))
+    }
+
+    pub fn scan<T: DeserializeOwned + std::fmt::Debug + Serialize>(
+        &self,
+        query: &Scan,
+    ) -> ClientResult<Vec<ScanResponse<T>>> {
+        self.block_on(self.client.scan(query))
+    }
+
+    pub fn time_boundary<T: DeserializeOwned + std::fmt::Debug + Serialize>(
+        &self,
+        query: &TimeBoundary,
+    ) -> ClientResult<Vec<TimeBoundaryResponse>> {
+        self.block_on(self.client.time_boundary::<T>(query))
+    }
+
+    pub fn segment_metadata(
+        &self,
+        query: &SegmentMetadata,
+    ) -> ClientResult<Vec<SegmentMetadataResponse>> {
+        self.block_on(self.client.segment_metadata(query))
+    }
+
+    pub fn timeseries<T: DeserializeOwned + std::fmt::Debug + Serialize>(
+        &self,
+        query: &Timeseries,
+    ) -> ClientResult<Vec<TimeseriesResponse<T>>> {
+        self.block_on(self.client.timeseries(query))
+    }
+
+    pub fn datasource_metadata(
+        &self,
+        data_source: DataSource,
+    ) -> ClientResult<Vec<MetadataResponse<HashMap<String, String>>>> {
+        self.block_on(self.client.datasource_metadata(data_source))
+    }
+
+    pub fn sql<T: DeserializeOwned>(&self, query: &SqlQuery) -> ClientResult<SqlResponse<T>> {
+        self.block_on(self.client.sql(query))
+    }
+
+    /// See [`DruidClient::sql_stream`], rows are received as the iterator advances.
+    pub fn sql_stream<T: DeserializeOwned + Send + 'static>(
+        &self,
+        query: &SqlQuery,
+    ) -> ClientResult<SqlRowIter<'_, T>> {
+        let stream = self.block_on(self.client.sql_stream(query))?;
+        Ok(SqlRowIter {
+            columns: stream.columns,
+            rows: stream.rows,
+            runtime: &self.runtime,
+        })
+    }
+
+    pub fn explain(&self, query: &SqlQuery) -> ClientResult<ExplainPlan> {
+        self.block_on(self.client.explain(query))
+    }
+
+    pub fn execute<Q: TypedQuery>(&self, query: &Q) -> ClientResult<Q::Response> {
+        self.block_on(self.client.execute(query))
+    }
+
+    pub fn execute_checked<Q: TypedQuery>(&self, query: &Q) -> ClientResult<Q::Response> {
+        self.block_on(self.client.execute_checked(query))
+    }
+
+    pub fn sys_segments(&self, filter: SysFilter) -> ClientResult<Vec<SysSegment>> {
+        self.block_on(self.client.sys_segments(filter))
+    }
+
+    pub fn sys_server_segments(&self, filter: SysFilter) -> ClientResult<Vec<SysServerSegment>> {
+        self.block_on(self.client.sys_server_segments(filter))
+    }
+
+    pub fn sys_servers(&self, filter: SysFilter) -> ClientResult<Vec<SysServer>> {
+        self.block_on(self.client.sys_servers(filter))
+    }
+
+    pub fn sys_tasks(&self, filter: SysFilter) -> ClientResult<Vec<SysTask>> {
+        self.block_on(self.client.sys_tasks(filter))
+    }
+
+    pub fn sys_supervisors(&self, filter: SysFilter) -> ClientResult<Vec<SysSupervisor>> {
+        self.block_on(self.client.sys_supervisors(filter))
+    }
+
+    /// See [`DruidClient::tables`].
+    pub fn tables(&self, schema: Option<&str>) -> ClientResult<Vec<TableInfo>> {
+        self.block_on(self.client.tables(schema))
+    }
+
+    pub fn table_schema(&self, data_source: &str) -> ClientResult<TableSchema> {
+        self.block_on(self.client.table_schema(data_source))
+    }
+
+    pub fn schema_of(&self, schema: &str, table: &str) -> ClientResult<TableSchema> {
+        self.block_on(self.client.schema_of(schema, table))
+    }
+}
+
+fn block_on<F: Future>(runtime: &Mutex<Runtime>, future: F) -> F::Output {
+    // a panic while running a query leaves the runtime usable
+    let mut runtime = runtime
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    runtime.block_on(future)
+}
+
+/// Rows of [`BlockingDruidClient::sql_stream`].
+pub struct SqlRowIter<'a, T> {
+    /// Empty unless the query asked for a `header`.
+    pub columns: Vec<SqlColumn>,
+    rows: BoxStream<'static, ClientResult<T>>,
+    runtime: &'a Mutex<Runtime>,
+}
+
+impl<T> Iterator for SqlRowIter<'_, T> {
+    type Item = ClientResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        block_on(self.runtime, self.rows.next())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::definitions::{Aggregation, Dimension, Granularity};
+    use crate::query::sql::SqlResultFormat;
+    use crate::transport::stub::StubTransport;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct Page {
+        page: String,
+    }
+
+    #[test]
+    fn test_blocking_queries() {
+        let client = BlockingDruidClient::new(vec!["localhost:8082".into()])
+            .unwrap()
+            .with_transport(
+                StubTransport::new()
+                    .on_path(
+                        "/druid/v2/sql/",
+                        200,
+                        "{\"page\":\"Rust\"}\n{\"page\":\"Druid\"}\n\n",
+                    )
+                    .otherwise(
+                        200,
+                        r#"[{"timestamp": "2015-09-12T00:00:00.000Z", "result": [{"page": "Rust"}]}]"#,
+                    ),
+            );
+        let top_n = TopN {
+            data_source: DataSource::table("wikipedia"),
+            dimension: Dimension::default("page"),
+            threshold: 1,
+            metric: "count".into(),
+            aggregations: vec![Aggregation::count("count")],
+            post_aggregations: vec![],
+            intervals: vec!["2015-09-12/2015-09-13".into()],
+            filter: None,
+            granularity: Granularity::All,
+            context: Default::default(),
+        };
+        let result = client.top_n::<Page>(&top_n).unwrap();
+        assert_eq!(result[0].result[0].page, "Rust");
+
+        let query =
+            SqlQuery::new("SELECT page FROM wikipedia").result_format(SqlResultFormat::ObjectLines);
+        let pages: Vec<String> = client
+            .sql_stream::<Page>(&query)
+            .unwrap()
+            .map(|page| page.unwrap().page)
+            .collect();
+        assert_eq!(pages, vec!["Rust", "Druid"]);
+    }
+}
@@ -1,21 +1,38 @@
-use crate::connection::{BrokersPool, SelectionStategy, StaticPool};
+use crate::connection::{BrokersPool, SelectionStategy, StaticPool, NODE_UNAVAILABLE};
+use crate::interceptor::{Interceptor, QueryObserver, SqlRow};
+use crate::metrics::Metrics;
 use crate::query::response::GroupByResponse;
 use crate::query::response::MetadataResponse;
 use crate::query::response::ScanResponse;
 use crate::query::response::SearchResponse;
 use crate::query::response::SegmentMetadataResponse;
 use crate::query::response::TimeBoundaryResponse;
+use crate::query::response::{ExplainPlan, SqlColumn, SqlResponse};
 use crate::query::response::{TimeseriesResponse, TopNResponse};
+use crate::query::sql::{ExplainRow, SqlQuery, SqlResultFormat, SqlRowDecoder};
 use crate::query::timeseries::Timeseries;
 use crate::query::{
     group_by::GroupBy, scan::Scan, search::Search, segment_metadata::SegmentMetadata,
     time_boundary::TimeBoundary, top_n::TopN, DataSource,
 };
 use crate::query::{DataSourceMetadata, Query};
-use reqwest::Client;
+use crate::serialization::OrderedObject;
+#[cfg(feature = "tracing")]
+use crate::trace;
+use crate::transport::{BodyStream, Endpoint, Method, ReqwestTransport, Transport};
+use crate::transport::{TransportRequest, TransportResponse};
+use csv_core::ReadRecordResult;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::collections::HashMap;
+#[cfg(feature = "testing")]
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -31,51 +48,248 @@ pub enum DruidClientError {
     ParsingError { source: serde_json::Error },
     #[error("couldn't deserialize json to object")]
     ParsingResponseError { source: serde_json::Error }, // todo: original json but with manageable size
+    #[error("couldn't deserialize csv row to object")]
+    ParsingCsvResponseError { source: csv::Error },
+    #[error("sql query has {placeholders} placeholders but {parameters} bound parameters")]
+    SqlParametersMismatch {
+        placeholders: usize,
+        parameters: usize,
+    },
+    #[error("couldn't find native query in the plan: {plan}")]
+    UnexpectedPlan { plan: String },
+    #[error("result type `{row_type}` has no fields for query outputs {missing:?}")]
+    RowTypeMismatch {
+        row_type: &'static str,
+        missing: Vec<String>,
+    },
+    #[error("task `{task_id}` didn't complete in {waited:?}")]
+    TaskTimeout { task_id: String, waited: Duration },
+    #[error("deletion of `{data_source}` segments wasn't confirmed with the plan's token")]
+    DeletionNotConfirmed { data_source: String },
+    #[error("segments of `{data_source}` changed since the deletion was planned")]
+    DeletionPlanOutdated { data_source: String },
+    #[error("can't delete segments of `{data_source}`: {reason}")]
+    DeletionNotSupported {
+        data_source: String,
+        reason: &'static str,
+    },
+    #[error("no unused segments of `{data_source}` to kill in {interval}")]
+    NothingToKill {
+        data_source: String,
+        interval: String,
+    },
+    #[cfg(feature = "testing")]
+    #[error("{feature} isn't supported by the in-memory engine")]
+    UnsupportedInMemory { feature: String },
+    #[error("couldn't parse timestamp `{value}`")]
+    InvalidTimestamp { value: String },
+    #[error("couldn't parse interval `{interval}`")]
+    InvalidInterval { interval: String },
+    #[cfg(feature = "testing")]
+    #[error("couldn't access fixtures at {path:?}")]
+    FixtureIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[cfg(feature = "testing")]
+    #[error("no recorded response for {method:?} {path} {body}")]
+    UnmatchedFixture {
+        method: Method,
+        path: String,
+        body: String,
+    },
+    #[cfg(feature = "blocking")]
+    #[error("couldn't start the runtime of the blocking client")]
+    Runtime { source: std::io::Error },
+    #[error("transport error")]
+    Transport {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
     #[error("Server responded with an error")]
     ServerError { response: String },
     #[error("unknown data store error")]
     Unknown,
 }
-type ClientResult<T> = Result<T, DruidClientError>;
+
+impl DruidClientError {
+    /// Short classification of the error for logs and metrics, e.g. `connection`, `parsing`
+    /// or, for errors reported by Druid, the kind of failure like `query_timeout`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DruidClientError::HttpConnection { .. } | DruidClientError::Transport { .. } => {
+                "connection"
+            }
+            DruidClientError::ServerError { response } => server_error_kind(response),
+            DruidClientError::ParsingError { .. } => "serialization",
+            DruidClientError::ParsingResponseError { .. }
+            | DruidClientError::ParsingCsvResponseError { .. }
+            | DruidClientError::InvalidHeader { .. }
+            | DruidClientError::UnexpectedPlan { .. } => "parsing",
+            DruidClientError::SqlParametersMismatch { .. }
+            | DruidClientError::RowTypeMismatch { .. }
+            | DruidClientError::InvalidTimestamp { .. }
+            | DruidClientError::InvalidInterval { .. } => "invalid_query",
+            DruidClientError::TaskTimeout { .. } => "task_timeout",
+            DruidClientError::DeletionNotConfirmed { .. }
+            | DruidClientError::DeletionPlanOutdated { .. }
+            | DruidClientError::DeletionNotSupported { .. }
+            | DruidClientError::NothingToKill { .. } => "deletion_rejected",
+            #[cfg(feature = "testing")]
+            DruidClientError::UnsupportedInMemory { .. } => "unsupported",
+            #[cfg(feature = "testing")]
+            DruidClientError::FixtureIo { .. } | DruidClientError::UnmatchedFixture { .. } => {
+                "fixture"
+            }
+            #[cfg(feature = "blocking")]
+            DruidClientError::Runtime { .. } => "runtime",
+            DruidClientError::Redaction(_) | DruidClientError::Unknown => "unknown",
+        }
+    }
+}
+
+// the `error` field of Druid's error responses, see
+// https://druid.apache.org/docs/latest/querying/querying.html#query-errors
+fn server_error_kind(response: &str) -> &'static str {
+    let error = serde_json::from_str::<serde_json::Value>(response)
+        .ok()
+        .and_then(|response| response.get("error")?.as_str().map(str::to_string));
+    match error.as_deref() {
+        Some("Query timeout") => "query_timeout",
+        Some("Query interrupted") => "query_interrupted",
+        Some("Query cancelled") => "query_cancelled",
+        Some("Query capacity exceeded") => "query_capacity_exceeded",
+        Some("Resource limit exceeded") => "resource_limit_exceeded",
+        Some("Unsupported operation") => "unsupported_operation",
+        Some("Truncated response context") => "truncated_response_context",
+        Some("Plan validation failed") | Some("SQL parse failed") => "invalid_query",
+        _ => "server",
+    }
+}
+
+pub type ClientResult<T> = Result<T, DruidClientError>;
 
 pub struct DruidClient {
-    http_client: Client,
+    transport: Box<dyn Transport>,
     brokers_pool: Box<dyn BrokersPool>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    metrics: Option<Arc<dyn Metrics>>,
 }
 
 impl DruidClient {
     pub fn new(nodes: Vec<String>) -> Self {
         let strategy = SelectionStategy::default_for(&nodes);
         DruidClient {
-            http_client: Client::new(),
+            transport: Box::new(ReqwestTransport::default()),
             brokers_pool: Box::new(StaticPool::new(nodes, strategy)),
+            interceptors: vec![],
+            metrics: None,
         }
     }
 
-    fn url(&self) -> String {
-        format!("http://{}/druid/v2/?pretty", self.brokers_pool.broker())
+    /// Sends queries through the transport instead of the default [`ReqwestTransport`].
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Box::new(transport);
+        self
     }
 
-    async fn http_query(&self, request: &str) -> Result<String, DruidClientError> {
-        let response_str = self
-            .http_client
-            .post(&self.url())
-            .body(request.to_string())
-            .header(reqwest::header::CONTENT_TYPE, "application/json")
-            .send()
-            .await
-            .map_err(|source| DruidClientError::HttpConnection { source: source })?
-            .text()
-            .await
-            .map_err(|source| DruidClientError::HttpConnection { source: source })?;
+    /// Adds the interceptor to the end of the chain run around every query.
+    pub fn with_interceptor<I: Interceptor + 'static>(mut self, interceptor: I) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Reports queries, received bytes and the health of the brokers to the metrics.
+    pub fn with_metrics<M: Metrics + 'static>(mut self, metrics: M) -> Self {
+        let metrics: Arc<dyn Metrics> = Arc::new(metrics);
+        self.brokers_pool.set_metrics(metrics.clone());
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // a query rejected by one of the interceptors is reported to all of them as failed
+    fn intercept(&self, endpoint: Endpoint, body: String) -> ClientResult<QueryObserver> {
+        let mut observer = QueryObserver::new(
+            endpoint,
+            body,
+            self.interceptors.clone(),
+            self.metrics.clone(),
+        )?;
+        let rejected = match &mut observer.query {
+            Some(query) => self
+                .interceptors
+                .iter()
+                .find_map(|interceptor| interceptor.before(query).err()),
+            None => None,
+        };
+        if let Some(err) = rejected {
+            observer.finish(None, Some(&err));
+            return Err(err);
+        }
+        #[cfg(feature = "tracing")]
+        if let Some(query) = &observer.query {
+            trace::record_query(query);
+        }
+        Ok(observer)
+    }
+
+    async fn send(&self, observer: &mut QueryObserver) -> ClientResult<TransportResponse> {
+        let (endpoint, body, headers) = observer.request()?;
+        let broker = self.brokers_pool.broker().clone();
+        let mut request = TransportRequest::new(Method::Post, &broker, endpoint.path(), Some(body));
+        request.headers = headers;
+        #[cfg(feature = "tracing")]
+        trace::propagate(&mut request);
+        let response = self.transport.send(request).await;
+        let healthy = match &response {
+            Ok(response) => !NODE_UNAVAILABLE.contains(&response.status),
+            Err(_) => false,
+        };
+        self.brokers_pool.report(&broker, healthy);
+
+        let mut response = response?;
+        observer.status = Some(response.status);
+        let received = observer.received();
+        let metrics = self.metrics.clone();
+        response.body = response
+            .body
+            .inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    received.fetch_add(chunk.len(), Ordering::Relaxed);
+                    if let Some(metrics) = &metrics {
+                        metrics.bytes_received(&broker, chunk.len());
+                    }
+                }
+            })
+            .boxed();
+        Ok(response)
+    }
+
+    async fn http_query(&self, request: String) -> Result<String, DruidClientError> {
+        let mut observer = self.intercept(Endpoint::Native, request)?;
+        let response = self.native_response(&mut observer);
+        #[cfg(feature = "tracing")]
+        let response = trace::instrument(trace::request_span(), response);
+        let response = response.await;
+        match &response {
+            Ok(body) => observer.finish(Some(body), None),
+            Err(err) => observer.finish(None, Some(err)),
+        }
+        response
+    }
+
+    async fn native_response(&self, observer: &mut QueryObserver) -> ClientResult<String> {
+        let response_str = self.send(observer).await?.text().await?;
+        #[cfg(feature = "tracing")]
+        trace::record_response_size(response_str.len());
 
         let json_value = serde_json::from_str::<serde_json::Value>(&response_str)
-            .map_err(|err| DruidClientError::ParsingError { source: err });
-        if let Some(_) = json_value?.get("error") {
+            .map_err(|err| DruidClientError::ParsingError { source: err })?;
+        if let Some(_) = json_value.get("error") {
             return Err(DruidClientError::ServerError {
                 response: response_str,
             });
         }
+        observer.rows = json_value.as_array().map_or(1, Vec::len);
         Ok(response_str)
     }
 
@@ -131,29 +345,29 @@ impl DruidClient {
         self._query(query).await
     }
 
-    async fn _query<Req, Resp>(&self, query: &Req) -> ClientResult<Resp>
+    pub(crate) async fn _query<Req, Resp>(&self, query: &Req) -> ClientResult<Resp>
     where
         Req: Serialize,
         Resp: DeserializeOwned,
     {
         let request = serde_json::to_string(&query)
-            .map_err(|err| DruidClientError::ParsingError { source: err });
-
-        let response = match request {
-            Ok(str) => self.http_query(&str).await,
-            Err(e) => Err(e),
-        };
+            .map_err(|err| DruidClientError::ParsingError { source: err })?;
 
-        let response = response.and_then(|str| {
+        #[cfg(feature = "tracing")]
+        let span = trace::query_span(&request);
+        let response = async move {
+            let str = self.http_query(request).await?;
             serde_json::from_str::<Resp>(&str)
                 .map_err(|source| DruidClientError::ParsingResponseError { source: source })
-        });
+        };
+        #[cfg(feature = "tracing")]
+        let response = trace::instrument(span, response);
 
-        response
+        response.await
     }
 
     pub async fn datasource_metadata(
-        self,
+        &self,
         data_source: DataSource,
     ) -> ClientResult<Vec<MetadataResponse<HashMap<String, String>>>> {
         let query = DataSourceMetadata {
@@ -163,4 +377,442 @@ impl DruidClient {
 
         self._query(&query).await
     }
+
+    pub async fn sql<T: DeserializeOwned>(&self, query: &SqlQuery) -> ClientResult<SqlResponse<T>> {
+        let (response, mut observer) = self.http_sql_query(query).await?;
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(err) => {
+                observer.finish(None, Some(&err));
+                return Err(err);
+            }
+        };
+        let response = decode_sql(query, &body, &mut observer.rows);
+        match &response {
+            Ok(_) => observer.finish(Some(&body), None),
+            Err(err) => observer.finish(None, Some(err)),
+        }
+        response
+    }
+
+    /// Streams rows of line oriented result formats (objectLines, arrayLines, csv) as they arrive.
+    ///
+    /// Header rows are consumed before returning, so column names and types are available right away.
+    /// Object and array formats are received completely before the rows are emitted.
+    pub async fn sql_stream<T: DeserializeOwned + Send + 'static>(
+        &self,
+        query: &SqlQuery,
+    ) -> ClientResult<SqlRowStream<T>> {
+        if !query.result_format.is_line_oriented() {
+            let response = self.sql::<T>(query).await?;
+            return Ok(SqlRowStream {
+                columns: response.columns,
+                rows: stream::iter(response.rows.into_iter().map(Ok)).boxed(),
+            });
+        }
+        let (response, observer) = self.http_sql_query(query).await?;
+        let mut rows = SqlRows {
+            reader: RowReader::new(query, response),
+            decoder: SqlRowDecoder::new(query),
+            observer,
+        };
+        while rows.decoder.expects_header() {
+            match rows.next::<serde_json::Value>().await {
+                Ok(Some(_)) => (),
+                Ok(None) => break,
+                Err(err) => {
+                    rows.observer.finish(None, Some(&err));
+                    return Err(err);
+                }
+            }
+        }
+
+        let columns = rows.decoder.columns.clone();
+        let rows = stream::unfold(rows, |mut rows| async move {
+            loop {
+                match rows.next().await {
+                    Ok(None) => {
+                        rows.observer.finish(None, None);
+                        return None;
+                    }
+                    Ok(Some(None)) => continue,
+                    Ok(Some(Some(row))) => {
+                        rows.observer.rows += 1;
+                        return Some((Ok(row), rows));
+                    }
+                    Err(err) => {
+                        rows.observer.finish(None, Some(&err));
+                        return Some((Err(err), rows));
+                    }
+                }
+            }
+        });
+        Ok(SqlRowStream {
+            columns,
+            rows: rows.boxed(),
+        })
+    }
+
+    /// Runs `EXPLAIN PLAN FOR` the statement and returns the native queries it is planned into.
+    pub async fn explain(&self, query: &SqlQuery) -> ClientResult<ExplainPlan> {
+        let response = self.sql::<ExplainRow>(&query.explain()).await?;
+        match response.rows.into_iter().next() {
+            Some(row) => row.into_plan(),
+            None => Err(DruidClientError::UnexpectedPlan {
+                plan: String::new(),
+            }),
+        }
+    }
+
+    // the query is reported by the caller once the response body was read
+    async fn http_sql_query(
+        &self,
+        query: &SqlQuery,
+    ) -> ClientResult<(TransportResponse, QueryObserver)> {
+        query.check_parameters()?;
+        let request = serde_json::to_string(query)
+            .map_err(|source| DruidClientError::ParsingError { source })?;
+        let mut observer = self.intercept(Endpoint::Sql, request)?;
+        let response = self.sql_response(&mut observer);
+        #[cfg(feature = "tracing")]
+        let response = trace::instrument(trace::request_span(), response);
+        match response.await {
+            Ok(response) => Ok((response, observer)),
+            Err(err) => {
+                observer.finish(None, Some(&err));
+                Err(err)
+            }
+        }
+    }
+
+    async fn sql_response(&self, observer: &mut QueryObserver) -> ClientResult<TransportResponse> {
+        let response = self.send(observer).await?;
+
+        if !response.is_success() {
+            let response = response.text().await?;
+            return Err(DruidClientError::ServerError { response });
+        }
+        Ok(response)
+    }
+}
+
+fn decode_sql<T: DeserializeOwned>(
+    query: &SqlQuery,
+    body: &str,
+    decoded: &mut usize,
+) -> ClientResult<SqlResponse<T>> {
+    let mut decoder = SqlRowDecoder::new(query);
+    let mut rows = vec![];
+    if let SqlResultFormat::Csv = query.result_format {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(body.as_bytes());
+        for record in reader.records() {
+            let record =
+                record.map_err(|source| DruidClientError::ParsingCsvResponseError { source })?;
+            rows.extend(decoder.decode_record(record)?);
+        }
+    } else if query.result_format.is_line_oriented() {
+        for line in body.lines() {
+            rows.extend(decoder.decode_line(line)?);
+        }
+    } else if let SqlResultFormat::Object = query.result_format {
+        let objects = serde_json::from_str::<Vec<OrderedObject>>(body)
+            .map_err(|source| DruidClientError::ParsingResponseError { source })?;
+        for object in objects {
+            rows.extend(decoder.decode_object(object)?);
+        }
+    } else {
+        let values = serde_json::from_str::<Vec<serde_json::Value>>(body)
+            .map_err(|source| DruidClientError::ParsingResponseError { source })?;
+        for value in values {
+            rows.extend(decoder.decode_value(value)?);
+        }
+    }
+    *decoded = rows.len();
+    Ok(SqlResponse {
+        columns: decoder.columns,
+        rows,
+    })
+}
+
+// a stream dropped before its end reports the rows received until then
+struct SqlRows {
+    reader: RowReader,
+    decoder: SqlRowDecoder,
+    observer: QueryObserver,
+}
+
+impl SqlRows {
+    async fn next<T: DeserializeOwned>(&mut self) -> ClientResult<Option<Option<T>>> {
+        self.reader.next(&mut self.decoder, &self.observer).await
+    }
+}
+
+impl Drop for SqlRows {
+    fn drop(&mut self) {
+        self.observer.finish(None, None);
+    }
+}
+
+/// Rows of a Druid SQL response, decoded while the response body is being received.
+pub struct SqlRowStream<T> {
+    /// Empty unless the query asked for a `header`.
+    pub columns: Vec<SqlColumn>,
+    pub rows: BoxStream<'static, ClientResult<T>>,
+}
+
+impl<T> Stream for SqlRowStream<T> {
+    type Item = ClientResult<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rows.poll_next_unpin(cx)
+    }
+}
+
+/// Raw rows of a line oriented response, csv records may span lines when a quoted value
+/// contains a line break, so they are read by a single csv parser fed with the body chunks.
+enum RowReader {
+    Lines(LineReader),
+    Csv(Box<CsvReader>),
+}
+
+impl RowReader {
+    fn new(query: &SqlQuery, response: TransportResponse) -> Self {
+        match query.result_format {
+            SqlResultFormat::Csv => RowReader::Csv(Box::new(CsvReader::new(response))),
+            _ => RowReader::Lines(LineReader::new(response)),
+        }
+    }
+
+    /// Decodes the next row, `Some(None)` for header rows and blank lines, `None` at the end.
+    /// Rows are handed to the observer's interceptors before they're returned.
+    async fn next<T: DeserializeOwned>(
+        &mut self,
+        decoder: &mut SqlRowDecoder,
+        observer: &QueryObserver,
+    ) -> ClientResult<Option<Option<T>>> {
+        match self {
+            RowReader::Lines(lines) => match lines.next_line().await? {
+                Some(line) => {
+                    let row = decoder.decode_line(&line)?;
+                    if row.is_some() {
+                        observer.row(SqlRow::Line(&line));
+                    }
+                    Ok(Some(row))
+                }
+                None => Ok(None),
+            },
+            RowReader::Csv(records) => match records.next_record().await? {
+                Some(record) => {
+                    let fields = if observer.sees_rows() {
+                        Some(record.clone())
+                    } else {
+                        None
+                    };
+                    let row = decoder.decode_record(record)?;
+                    if let (Some(_), Some(fields)) = (&row, fields) {
+                        observer.row(SqlRow::Fields(&fields.iter().collect::<Vec<_>>()));
+                    }
+                    Ok(Some(row))
+                }
+                None => Ok(None),
+            },
+        }
+    }
+}
+
+struct LineReader {
+    body: BodyStream,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl LineReader {
+    fn new(response: TransportResponse) -> Self {
+        LineReader {
+            body: response.body,
+            buffer: vec![],
+            finished: false,
+        }
+    }
+
+    async fn next_line(&mut self) -> ClientResult<Option<String>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line[..end]);
+                return Ok(Some(line.trim_end_matches('\r').to_string()));
+            }
+            if self.finished {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                let line = String::from_utf8_lossy(&self.buffer).to_string();
+                self.buffer.clear();
+                return Ok(Some(line));
+            }
+            match self.body.next().await {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk?),
+                None => self.finished = true,
+            }
+        }
+    }
+}
+
+struct CsvReader {
+    body: BodyStream,
+    reader: csv_core::Reader,
+    chunk: Vec<u8>,
+    consumed: usize,
+    finished: bool,
+    output: Vec<u8>,
+    written: usize,
+    ends: Vec<usize>,
+    ended: usize,
+}
+
+impl CsvReader {
+    fn new(response: TransportResponse) -> Self {
+        CsvReader {
+            body: response.body,
+            reader: csv_core::Reader::new(),
+            chunk: vec![],
+            consumed: 0,
+            finished: false,
+            output: vec![0; 1024],
+            written: 0,
+            ends: vec![0; 16],
+            ended: 0,
+        }
+    }
+
+    async fn next_record(&mut self) -> ClientResult<Option<csv::StringRecord>> {
+        loop {
+            // an empty input tells the parser the body is complete
+            if self.consumed == self.chunk.len() && !self.finished {
+                match self.body.next().await {
+                    Some(chunk) => self.chunk = chunk?,
+                    None => {
+                        self.chunk.clear();
+                        self.finished = true;
+                    }
+                }
+                self.consumed = 0;
+                continue;
+            }
+            let (result, read, written, ended) = self.reader.read_record(
+                &self.chunk[self.consumed..],
+                &mut self.output[self.written..],
+                &mut self.ends[self.ended..],
+            );
+            self.consumed += read;
+            self.written += written;
+            self.ended += ended;
+            match result {
+                ReadRecordResult::InputEmpty => (),
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    let mut record = csv::StringRecord::new();
+                    let mut start = 0;
+                    for end in &self.ends[..self.ended] {
+                        record.push_field(&String::from_utf8_lossy(&self.output[start..*end]));
+                        start = *end;
+                    }
+                    self.written = 0;
+                    self.ended = 0;
+                    return Ok(Some(record));
+                }
+                ReadRecordResult::End => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        let server_error = |response: &str| DruidClientError::ServerError {
+            response: response.to_string(),
+        };
+        assert_eq!(
+            server_error(r#"{"error": "Query timeout", "errorMessage": null}"#).kind(),
+            "query_timeout"
+        );
+        assert_eq!(server_error("<html>Bad gateway</html>").kind(), "server");
+        let parsing = serde_json::from_str::<u8>("x").unwrap_err();
+        assert_eq!(
+            DruidClientError::ParsingResponseError { source: parsing }.kind(),
+            "parsing"
+        );
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Comment {
+        page: String,
+        comment: String,
+    }
+
+    const CSV: &str =
+        "page,comment\r\nMain,\"first\r\nsecond, \"\"quoted\"\"\"\r\nRust,plain\r\n\r\n";
+
+    fn comments() -> Vec<Comment> {
+        vec![
+            Comment {
+                page: "Main".into(),
+                comment: "first\r\nsecond, \"quoted\"".into(),
+            },
+            Comment {
+                page: "Rust".into(),
+                comment: "plain".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_csv_quoted_line_breaks() {
+        let broker = crate::transport::stub::StubTransport::new().otherwise(200, CSV);
+        let client = DruidClient::new(vec!["localhost:8082".into()]).with_transport(broker);
+        let query = SqlQuery::new("SELECT page, comment FROM wikipedia")
+            .result_format(SqlResultFormat::Csv)
+            .header(true);
+
+        let response = tokio_test::block_on(client.sql::<Comment>(&query)).unwrap();
+        assert_eq!(response.columns[1].name, "comment");
+        assert_eq!(response.rows, comments());
+
+        let rows: ClientResult<Vec<Comment>> = tokio_test::block_on(async {
+            let rows = client.sql_stream::<Comment>(&query).await?;
+            rows.rows.collect::<Vec<_>>().await.into_iter().collect()
+        });
+        assert_eq!(rows.unwrap(), comments());
+    }
+
+    #[test]
+    fn test_csv_records_across_chunks() {
+        // every byte in a chunk of its own, splitting quoted values and line breaks
+        let chunks: Vec<ClientResult<Vec<u8>>> = CSV.bytes().map(|byte| Ok(vec![byte])).collect();
+        let mut response = TransportResponse::new(200, String::new());
+        response.body = stream::iter(chunks).boxed();
+        let query = SqlQuery::new("")
+            .result_format(SqlResultFormat::Csv)
+            .header(true);
+        let mut rows = RowReader::new(&query, response);
+        let mut decoder = SqlRowDecoder::new(&query);
+        let observer = QueryObserver::new(Endpoint::Sql, "{}".into(), vec![], None).unwrap();
+        let decoded = tokio_test::block_on(async {
+            let mut decoded = vec![];
+            while let Some(row) = rows.next::<Comment>(&mut decoder, &observer).await? {
+                decoded.extend(row);
+            }
+            ClientResult::Ok(decoded)
+        });
+        assert_eq!(decoded.unwrap(), comments());
+        assert_eq!(decoder.columns[0].name, "page");
+    }
 }
@@ -1,14 +1,30 @@
+use crate::client::DruidClientError;
+use crate::metrics::Metrics;
+use crate::transport::{Method, ReqwestTransport, Transport, TransportRequest};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 pub trait BrokersPool {
     fn broker(&self) -> &String;
+
+    /// Whether the broker answered the request it was selected for.
+    fn report(&self, broker: &str, healthy: bool);
+
+    /// Metrics of the client the pool selects brokers for.
+    fn set_metrics(&mut self, metrics: Arc<dyn Metrics>);
 }
 
+// statuses of a node that can't serve requests, other errors are failures of the request
+pub(crate) const NODE_UNAVAILABLE: &[u16] = &[502, 503];
+
 pub struct StaticPool {
     brokers: Vec<String>,
     selection: SelectionStategy,
+    metrics: Option<Arc<dyn Metrics>>,
 }
 
 impl StaticPool {
@@ -16,13 +32,28 @@ impl StaticPool {
         StaticPool {
             brokers: brokers,
             selection: stategy,
+            metrics: None,
         }
     }
 }
 
 impl BrokersPool for StaticPool {
     fn broker(&self) -> &String {
-        self.selection.select(&self.brokers)
+        let broker = self.selection.select(&self.brokers);
+        if let Some(metrics) = &self.metrics {
+            metrics.broker_selected(broker);
+        }
+        broker
+    }
+
+    fn report(&self, broker: &str, healthy: bool) {
+        if let Some(metrics) = &self.metrics {
+            metrics.broker_health(broker, healthy);
+        }
+    }
+
+    fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        self.metrics = Some(metrics);
     }
 }
 
@@ -68,6 +99,138 @@ impl SelectionStategy {
     }
 }
 
+/// Json over http connection to one of druid services (coordinator, overlord),
+/// requests are balanced between the nodes of the pool.
+pub(crate) struct ServiceConnection {
+    transport: Box<dyn Transport>,
+    nodes_pool: Box<dyn BrokersPool>,
+}
+
+// characters that can't be used as is within a single path segment
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Escapes user provided value (datasource name, task id) to be used as a part of url path.
+pub(crate) fn path_segment(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH_SEGMENT).to_string()
+}
+
+// characters that can't be used as is within a query string value
+const QUERY_COMPONENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'+')
+    .add(b'<')
+    .add(b'=')
+    .add(b'>')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Escapes user provided value to be used as a query parameter value.
+pub(crate) fn query_component(value: &str) -> String {
+    utf8_percent_encode(value, QUERY_COMPONENT).to_string()
+}
+
+impl ServiceConnection {
+    pub(crate) fn new(nodes: Vec<String>) -> Self {
+        let strategy = SelectionStategy::default_for(&nodes);
+        ServiceConnection {
+            transport: Box::new(ReqwestTransport::default()),
+            nodes_pool: Box::new(StaticPool::new(nodes, strategy)),
+        }
+    }
+
+    pub(crate) fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Box::new(transport);
+        self
+    }
+
+    pub(crate) fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.nodes_pool.set_metrics(metrics);
+        self
+    }
+
+    pub(crate) async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, DruidClientError> {
+        self.send(Method::Get, path, None).await
+    }
+
+    pub(crate) async fn post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, DruidClientError> {
+        let body = serde_json::to_string(body)
+            .map_err(|source| DruidClientError::ParsingError { source })?;
+        self.send(Method::Post, path, Some(body)).await
+    }
+
+    pub(crate) async fn delete<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, DruidClientError> {
+        self.send(Method::Delete, path, None).await
+    }
+
+    /// Returns response body as is, for endpoints responding with plain text.
+    pub(crate) async fn get_text(&self, path: &str) -> Result<String, DruidClientError> {
+        self.send_raw(Method::Get, path, None).await
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<T, DruidClientError> {
+        let response = self.send_raw(method, path, body).await?;
+        // some endpoints acknowledge with an empty body
+        let response = if response.trim().is_empty() {
+            "null"
+        } else {
+            &response
+        };
+        serde_json::from_str::<T>(response)
+            .map_err(|source| DruidClientError::ParsingResponseError { source })
+    }
+
+    async fn send_raw(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<String, DruidClientError> {
+        let node = self.nodes_pool.broker().clone();
+        let request = TransportRequest::new(method, &node, path, body);
+        let response = self.transport.send(request).await;
+        let healthy = match &response {
+            Ok(response) => !NODE_UNAVAILABLE.contains(&response.status),
+            Err(_) => false,
+        };
+        self.nodes_pool.report(&node, healthy);
+        let response = response?;
+        let success = response.is_success();
+        let response = response.text().await?;
+        if !success {
+            return Err(DruidClientError::ServerError { response });
+        }
+        Ok(response)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -101,4 +264,20 @@ mod test {
         assert_eq!(strategy.select(&nodes), "localhost:8081");
         assert_eq!(strategy.select(&nodes), "localhost:8081");
     }
+
+    #[test]
+    fn test_path_segment() {
+        assert_eq!(path_segment("wikipedia"), "wikipedia");
+        assert_eq!(path_segment("my data/source?"), "my%20data%2Fsource%3F");
+    }
+
+    #[test]
+    fn test_query_component() {
+        assert_eq!(query_component("wikipedia"), "wikipedia");
+        assert_eq!(query_component("a&b=c+d"), "a%26b%3Dc%2Bd");
+        assert_eq!(
+            query_component("2015-09-12T00:00:00.000Z/2015-09-13"),
+            "2015-09-12T00:00:00.000Z/2015-09-13"
+        );
+    }
 }
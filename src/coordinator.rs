@@ -0,0 +1,674 @@
+//! Client for the [Coordinator API](https://druid.apache.org/docs/latest/operations/api-reference.html#coordinator):
+//! datasources, segments, load status and servers of the cluster.
+//!
+//! ```no_run
+//! use druid_io::coordinator::CoordinatorClient;
+//!
+//! # async fn example() {
+//! let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+//! let datasources = coordinator.datasources().await.unwrap();
+//! # }
+//! ```
+use crate::client::ClientResult;
+use crate::client::DruidClientError;
+use crate::connection::{path_segment, query_component, ServiceConnection};
+use crate::deletion::{ChangedSegments, DeletionKind, DeletionPlan, SegmentSelection};
+use crate::lookup::{LookupNodeState, LookupSpec, LookupStatus};
+use crate::metrics::Metrics;
+use crate::retention::{
+    CompactionConfig, CompactionConfigs, DatasourceLifecycle, LifecycleChange, Rule,
+};
+use crate::serialization::comma_separated;
+use crate::transport::Transport;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct CoordinatorClient {
+    connection: ServiceConnection,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DatasourceSummary {
+    pub name: String,
+    pub properties: DatasourceProperties,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DatasourceProperties {
+    #[serde(default)]
+    pub tiers: HashMap<String, TierSummary>,
+    pub segments: SegmentsSummary,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TierSummary {
+    pub size: u64,
+    pub segment_count: usize,
+    pub replicated_size: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentsSummary {
+    pub count: usize,
+    pub size: u64,
+    pub replicated_size: Option<u64>,
+    pub min_time: Option<String>,
+    pub max_time: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IntervalSummary {
+    pub size: u64,
+    pub count: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DataSegment {
+    pub data_source: String,
+    pub interval: String,
+    pub version: String,
+    pub load_spec: LoadSpec,
+    #[serde(deserialize_with = "comma_separated", default)]
+    pub dimensions: Vec<String>,
+    #[serde(deserialize_with = "comma_separated", default)]
+    pub metrics: Vec<String>,
+    pub shard_spec: ShardSpec,
+    pub binary_version: Option<usize>,
+    pub size: u64,
+    pub identifier: String,
+}
+
+// newer coordinators list unused segments along with the times their used status changed
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UnusedSegment {
+    #[serde(rename_all = "camelCase")]
+    WithStatus {
+        data_segment: DataSegment,
+    },
+    Segment(DataSegment),
+}
+
+impl UnusedSegment {
+    fn into_segment(self) -> DataSegment {
+        match self {
+            UnusedSegment::WithStatus { data_segment } => data_segment,
+            UnusedSegment::Segment(segment) => segment,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadSpec {
+    #[serde(rename = "type")]
+    pub storage_type: String,
+    pub path: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShardSpec {
+    #[serde(rename = "type")]
+    pub spec_type: String,
+    pub partition_num: Option<usize>,
+    pub partitions: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadQueue {
+    pub segments_to_load: usize,
+    pub segments_to_drop: usize,
+    pub segments_to_load_size: u64,
+    pub segments_to_drop_size: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerSummary {
+    pub host: String,
+    pub tier: String,
+    #[serde(rename = "type")]
+    pub server_type: String,
+    pub priority: i32,
+    pub curr_size: u64,
+    pub max_size: u64,
+}
+
+impl CoordinatorClient {
+    pub fn new(nodes: Vec<String>) -> Self {
+        CoordinatorClient {
+            connection: ServiceConnection::new(nodes),
+        }
+    }
+
+    /// Sends requests through the transport instead of the default `ReqwestTransport`.
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.connection = self.connection.with_transport(transport);
+        self
+    }
+
+    /// Reports the node each request went to and whether it answered to the metrics.
+    pub fn with_metrics<M: Metrics + 'static>(mut self, metrics: M) -> Self {
+        self.connection = self.connection.with_metrics(Arc::new(metrics));
+        self
+    }
+
+    /// Names of all datasources with at least one used segment.
+    pub async fn datasources(&self) -> ClientResult<Vec<String>> {
+        self.connection
+            .get("/druid/coordinator/v1/datasources")
+            .await
+    }
+
+    pub async fn datasources_summary(&self) -> ClientResult<Vec<DatasourceSummary>> {
+        self.connection
+            .get("/druid/coordinator/v1/datasources?simple")
+            .await
+    }
+
+    pub async fn datasource(&self, data_source: &str) -> ClientResult<DatasourceProperties> {
+        self.connection
+            .get(&format!(
+                "/druid/coordinator/v1/datasources/{}",
+                path_segment(data_source)
+            ))
+            .await
+    }
+
+    pub async fn segment_ids(&self, data_source: &str) -> ClientResult<Vec<String>> {
+        self.connection
+            .get(&format!(
+                "/druid/coordinator/v1/datasources/{}/segments",
+                path_segment(data_source)
+            ))
+            .await
+    }
+
+    pub async fn segments(&self, data_source: &str) -> ClientResult<Vec<DataSegment>> {
+        self.connection
+            .get(&format!(
+                "/druid/coordinator/v1/datasources/{}/segments?full",
+                path_segment(data_source)
+            ))
+            .await
+    }
+
+    pub async fn intervals(&self, data_source: &str) -> ClientResult<Vec<String>> {
+        self.connection
+            .get(&format!(
+                "/druid/coordinator/v1/datasources/{}/intervals",
+                path_segment(data_source)
+            ))
+            .await
+    }
+
+    pub async fn intervals_summary(
+        &self,
+        data_source: &str,
+    ) -> ClientResult<HashMap<String, IntervalSummary>> {
+        self.connection
+            .get(&format!(
+                "/druid/coordinator/v1/datasources/{}/intervals?simple",
+                path_segment(data_source)
+            ))
+            .await
+    }
+
+    /// Percentage of segments actually loaded in the cluster per datasource.
+    pub async fn load_status(&self) -> ClientResult<HashMap<String, f64>> {
+        self.connection
+            .get("/druid/coordinator/v1/loadstatus")
+            .await
+    }
+
+    /// Percentage of the used segments of the datasource in the interval that are loaded,
+    /// `None` while the datasource has no used segments in the interval. With `refresh_metadata`
+    /// the coordinator polls the metadata store first, so recently published segments count too;
+    /// Druid documents that as expensive, so it's better done once and not on every poll.
+    pub async fn datasource_load_status(
+        &self,
+        data_source: &str,
+        interval: &str,
+        refresh_metadata: bool,
+    ) -> ClientResult<Option<f64>> {
+        let status: Option<HashMap<String, f64>> = self
+            .connection
+            .get(&format!(
+                "/druid/coordinator/v1/datasources/{}/loadstatus?forceMetadataRefresh={}&interval={}",
+                path_segment(data_source),
+                refresh_metadata,
+                query_component(interval)
+            ))
+            .await?;
+        Ok(status.and_then(|status| status.get(data_source).copied()))
+    }
+
+    /// Number of segments left to load until datasources are fully available for queries.
+    pub async fn load_status_simple(&self) -> ClientResult<HashMap<String, usize>> {
+        self.connection
+            .get("/druid/coordinator/v1/loadstatus?simple")
+            .await
+    }
+
+    /// Segments to load and drop per historical server.
+    pub async fn load_queue(&self) -> ClientResult<HashMap<String, LoadQueue>> {
+        self.connection
+            .get("/druid/coordinator/v1/loadqueue?simple")
+            .await
+    }
+
+    pub async fn servers(&self) -> ClientResult<Vec<ServerSummary>> {
+        self.connection
+            .get("/druid/coordinator/v1/servers?simple")
+            .await
+    }
+
+    pub async fn tiers(&self) -> ClientResult<Vec<String>> {
+        self.connection.get("/druid/coordinator/v1/tiers").await
+    }
+}
+
+impl CoordinatorClient {
+    /// Creates empty lookups config, required once before the first lookup is added to a cluster.
+    pub async fn init_lookups(&self) -> ClientResult<()> {
+        let empty: HashMap<String, ()> = HashMap::new();
+        self.connection
+            .post("/druid/coordinator/v1/lookups/config", &empty)
+            .await
+    }
+
+    /// Tiers having lookups configured.
+    pub async fn lookup_tiers(&self) -> ClientResult<Vec<String>> {
+        self.connection
+            .get("/druid/coordinator/v1/lookups/config")
+            .await
+    }
+
+    /// Names of the lookups of the tier.
+    pub async fn lookups(&self, tier: &str) -> ClientResult<Vec<String>> {
+        self.connection
+            .get(&format!(
+                "/druid/coordinator/v1/lookups/config/{}",
+                path_segment(tier)
+            ))
+            .await
+    }
+
+    /// Lookup specs of all tiers.
+    pub async fn all_lookups(&self) -> ClientResult<HashMap<String, HashMap<String, LookupSpec>>> {
+        self.connection
+            .get("/druid/coordinator/v1/lookups/config/all")
+            .await
+    }
+
+    pub async fn lookup(&self, tier: &str, name: &str) -> ClientResult<LookupSpec> {
+        self.connection
+            .get(&lookup_path("config", tier, name))
+            .await
+    }
+
+    /// Creates or updates the lookup, an update needs a greater `version` than the current spec.
+    pub async fn update_lookup(
+        &self,
+        tier: &str,
+        name: &str,
+        spec: &LookupSpec,
+    ) -> ClientResult<()> {
+        self.connection
+            .post(&lookup_path("config", tier, name), spec)
+            .await
+    }
+
+    pub async fn delete_lookup(&self, tier: &str, name: &str) -> ClientResult<()> {
+        self.connection
+            .delete(&lookup_path("config", tier, name))
+            .await
+    }
+
+    /// Whether the lookup is loaded on all nodes of the tier, lists nodes still loading it.
+    pub async fn lookup_status(&self, tier: &str, name: &str) -> ClientResult<LookupStatus> {
+        self.connection
+            .get(&format!(
+                "{}?detailed=true",
+                lookup_path("status", tier, name)
+            ))
+            .await
+    }
+
+    /// Lookups loaded, to be loaded and dropped by each node of the tier.
+    pub async fn lookup_node_status(
+        &self,
+        tier: &str,
+    ) -> ClientResult<HashMap<String, LookupNodeState>> {
+        self.connection
+            .get(&format!(
+                "/druid/coordinator/v1/lookups/nodeStatus/{}",
+                path_segment(tier)
+            ))
+            .await
+    }
+}
+
+impl CoordinatorClient {
+    /// Retention rules of all datasources, `_default` holds the cluster wide ones.
+    pub async fn all_rules(&self) -> ClientResult<HashMap<String, Vec<Rule>>> {
+        self.connection.get("/druid/coordinator/v1/rules").await
+    }
+
+    /// Retention rules of the datasource, empty when only the default rules apply.
+    pub async fn rules(&self, data_source: &str) -> ClientResult<Vec<Rule>> {
+        self.connection
+            .get(&format!(
+                "/druid/coordinator/v1/rules/{}",
+                path_segment(data_source)
+            ))
+            .await
+    }
+
+    /// Replaces retention rules of the datasource.
+    pub async fn set_rules(&self, data_source: &str, rules: &[Rule]) -> ClientResult<()> {
+        self.connection
+            .post(
+                &format!("/druid/coordinator/v1/rules/{}", path_segment(data_source)),
+                &rules,
+            )
+            .await
+    }
+
+    pub async fn compaction_configs(&self) -> ClientResult<Vec<CompactionConfig>> {
+        let configs: CompactionConfigs = self
+            .connection
+            .get("/druid/coordinator/v1/config/compaction")
+            .await?;
+        Ok(configs.compaction_configs)
+    }
+
+    /// Auto-compaction config of the datasource, `None` when compaction isn't enabled for it.
+    pub async fn compaction_config(
+        &self,
+        data_source: &str,
+    ) -> ClientResult<Option<CompactionConfig>> {
+        let configs = self.compaction_configs().await?;
+        Ok(configs
+            .into_iter()
+            .find(|config| config.data_source == data_source))
+    }
+
+    /// Enables auto-compaction of the datasource or replaces its config.
+    pub async fn set_compaction_config(&self, config: &CompactionConfig) -> ClientResult<()> {
+        self.connection
+            .post("/druid/coordinator/v1/config/compaction", config)
+            .await
+    }
+
+    pub async fn delete_compaction_config(&self, data_source: &str) -> ClientResult<()> {
+        self.connection
+            .delete(&format!(
+                "/druid/coordinator/v1/config/compaction/{}",
+                path_segment(data_source)
+            ))
+            .await
+    }
+
+    /// Changes `apply_lifecycle` would make, without making them.
+    pub async fn diff_lifecycle(
+        &self,
+        lifecycle: &DatasourceLifecycle,
+    ) -> ClientResult<Vec<LifecycleChange>> {
+        let rules = match lifecycle.rules {
+            Some(_) => self.rules(&lifecycle.data_source).await?,
+            None => vec![],
+        };
+        let compaction = match lifecycle.compaction {
+            Some(_) => self.compaction_config(&lifecycle.data_source).await?,
+            None => None,
+        };
+        Ok(lifecycle.diff(&rules, compaction.as_ref()))
+    }
+
+    /// Brings rules and compaction config of the datasource to the desired state, returns the
+    /// changes made. Nothing is updated when the configuration already matches.
+    pub async fn apply_lifecycle(
+        &self,
+        lifecycle: &DatasourceLifecycle,
+    ) -> ClientResult<Vec<LifecycleChange>> {
+        let changes = self.diff_lifecycle(lifecycle).await?;
+        for change in &changes {
+            match change {
+                LifecycleChange::SetRules { desired, .. } => {
+                    self.set_rules(&lifecycle.data_source, desired).await?
+                }
+                LifecycleChange::SetCompaction { desired, .. } => {
+                    self.set_compaction_config(desired).await?
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
+
+impl CoordinatorClient {
+    /// Segments marked unused, still in deep storage until a kill task deletes them.
+    pub async fn unused_segments(&self, data_source: &str) -> ClientResult<Vec<DataSegment>> {
+        let segments: Vec<UnusedSegment> = self
+            .connection
+            .get(&format!(
+                "/druid/coordinator/v1/metadata/datasources/{}/unusedSegments",
+                path_segment(data_source)
+            ))
+            .await?;
+        Ok(segments
+            .into_iter()
+            .map(UnusedSegment::into_segment)
+            .collect())
+    }
+
+    /// Lists used segments that [`mark_unused`](Self::mark_unused) would disable, changes nothing.
+    pub async fn plan_mark_unused(
+        &self,
+        data_source: &str,
+        selection: SegmentSelection,
+    ) -> ClientResult<DeletionPlan> {
+        self.plan(data_source, DeletionKind::MarkUnused, selection)
+            .await
+    }
+
+    /// Lists unused segments of the interval, or the whole datasource, that a kill task would
+    /// permanently delete, changes nothing. Used segments have to be marked unused with
+    /// [`mark_unused`](Self::mark_unused) before they can be killed.
+    /// See [`OverlordClient::kill`](crate::overlord::OverlordClient::kill).
+    pub async fn plan_kill(
+        &self,
+        data_source: &str,
+        interval: Option<&str>,
+    ) -> ClientResult<DeletionPlan> {
+        let selection = match interval {
+            Some(interval) => SegmentSelection::Interval(interval.to_string()),
+            None => SegmentSelection::All,
+        };
+        self.plan(data_source, DeletionKind::Kill, selection).await
+    }
+
+    async fn plan(
+        &self,
+        data_source: &str,
+        kind: DeletionKind,
+        selection: SegmentSelection,
+    ) -> ClientResult<DeletionPlan> {
+        let segments = match kind {
+            DeletionKind::MarkUnused => self.segments(data_source).await?,
+            DeletionKind::Kill => self.unused_segments(data_source).await?,
+        };
+        DeletionPlan::new(data_source, kind, selection, segments)
+    }
+
+    /// Fails unless `confirmation` is the plan's confirmation token and the segments
+    /// matching the plan's selection are still the planned ones.
+    pub(crate) async fn confirm(
+        &self,
+        plan: &DeletionPlan,
+        confirmation: &str,
+    ) -> ClientResult<()> {
+        if confirmation != plan.confirmation_token() {
+            return Err(DruidClientError::DeletionNotConfirmed {
+                data_source: plan.data_source.clone(),
+            });
+        }
+        let current = self
+            .plan(&plan.data_source, plan.kind, plan.selection.clone())
+            .await?;
+        if current.segment_ids() != plan.segment_ids() {
+            return Err(DruidClientError::DeletionPlanOutdated {
+                data_source: plan.data_source.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Marks segments of the plan unused, `confirmation` has to be the plan's
+    /// [`confirmation_token`](DeletionPlan::confirmation_token). Fails without changes when
+    /// the segments matching the plan's selection have changed since planning.
+    /// Returns the number of segments marked unused.
+    pub async fn mark_unused(
+        &self,
+        plan: &DeletionPlan,
+        confirmation: &str,
+    ) -> ClientResult<usize> {
+        if plan.kind != DeletionKind::MarkUnused {
+            return Err(DruidClientError::DeletionNotSupported {
+                data_source: plan.data_source.clone(),
+                reason: "segments of a kill plan are unused already",
+            });
+        }
+        self.confirm(plan, confirmation).await?;
+        if plan.segments.is_empty() {
+            return Ok(0);
+        }
+
+        let path = format!(
+            "/druid/coordinator/v1/datasources/{}",
+            path_segment(&plan.data_source)
+        );
+        let changed: Option<ChangedSegments> = match plan.selection.payload() {
+            Some(payload) => {
+                self.connection
+                    .post(&format!("{}/markUnused", path), &payload)
+                    .await?
+            }
+            None => self.connection.delete(&path).await?,
+        };
+        Ok(changed.map_or(plan.segments.len(), |changed| changed.num_changed_segments))
+    }
+
+    /// Re-enables unused segments of an interval or by id, returns the number of segments
+    /// marked used. [`SegmentSelection::All`] is rejected without a request, druid doesn't
+    /// report how many segments it re-enabled for a whole datasource.
+    pub async fn mark_used(
+        &self,
+        data_source: &str,
+        selection: SegmentSelection,
+    ) -> ClientResult<usize> {
+        let payload =
+            selection
+                .payload()
+                .ok_or_else(|| DruidClientError::DeletionNotSupported {
+                    data_source: data_source.to_string(),
+                    reason: "marking every segment used isn't supported, select an interval or ids",
+                })?;
+        let path = format!(
+            "/druid/coordinator/v1/datasources/{}/markUsed",
+            path_segment(data_source)
+        );
+        let changed: Option<ChangedSegments> = self.connection.post(&path, &payload).await?;
+        Ok(changed.map_or(0, |changed| changed.num_changed_segments))
+    }
+}
+
+fn lookup_path(resource: &str, tier: &str, name: &str) -> String {
+    format!(
+        "/druid/coordinator/v1/lookups/{}/{}/{}",
+        resource,
+        path_segment(tier),
+        path_segment(name)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::stub::StubTransport;
+
+    #[test]
+    fn test_deserialize_full_segment() {
+        let str = r#"
+            {
+                "dataSource": "wikipedia",
+                "interval": "2015-09-12T00:00:00.000Z/2015-09-13T00:00:00.000Z",
+                "version": "2020-09-06T18:48:27.396Z",
+                "loadSpec": {"type": "local", "path": "/opt/druid/var/druid/segments/wikipedia/index.zip"},
+                "dimensions": "channel,cityName,page",
+                "metrics": "",
+                "shardSpec": {"type": "numbered", "partitionNum": 0, "partitions": 0},
+                "binaryVersion": 9,
+                "size": 4817636,
+                "identifier": "wikipedia_2015-09-12T00:00:00.000Z_2015-09-13T00:00:00.000Z_2020-09-06T18:48:27.396Z"
+            }
+        "#;
+        let segment = serde_json::from_str::<DataSegment>(str).unwrap();
+        assert_eq!(segment.dimensions, vec!["channel", "cityName", "page"]);
+        assert!(segment.metrics.is_empty());
+        assert_eq!(segment.shard_spec.partition_num, Some(0));
+    }
+
+    #[test]
+    fn test_deserialize_datasource_summary() {
+        let str = r#"
+            [{
+                "name": "wikipedia",
+                "properties": {
+                    "tiers": {"_default_tier": {"size": 4817636, "segmentCount": 1, "replicatedSize": 4817636}},
+                    "segments": {"maxTime": "2015-09-13T00:00:00.000Z", "size": 4817636, "minTime": "2015-09-12T00:00:00.000Z", "count": 1, "replicatedSize": 4817636}
+                }
+            }]
+        "#;
+        let summary = serde_json::from_str::<Vec<DatasourceSummary>>(str).unwrap();
+        assert_eq!(
+            summary[0].properties.tiers["_default_tier"].segment_count,
+            1
+        );
+        assert_eq!(summary[0].properties.segments.count, 1);
+    }
+
+    #[test]
+    fn test_mark_used() {
+        let transport = Arc::new(StubTransport::new().on_path(
+            "/druid/coordinator/v1/datasources/wikipedia/markUsed",
+            200,
+            r#"{"numChangedSegments": 2}"#,
+        ));
+        let coordinator =
+            CoordinatorClient::new(vec!["localhost:8081".into()]).with_transport(transport.clone());
+        let selection = SegmentSelection::Interval("2015-09-12/2015-09-13".into());
+        let changed = tokio_test::block_on(coordinator.mark_used("wikipedia", selection)).unwrap();
+        assert_eq!(changed, 2);
+        let body = transport.requests().pop().unwrap().body.unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({"interval": "2015-09-12/2015-09-13"})
+        );
+
+        let all = tokio_test::block_on(coordinator.mark_used("wikipedia", SegmentSelection::All));
+        assert!(matches!(
+            all,
+            Err(DruidClientError::DeletionNotSupported { .. })
+        ));
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
@@ -0,0 +1,254 @@
+//! Conversion of query results into [polars](https://docs.rs/polars) `DataFrame`s.
+//!
+//! Available with the `polars` feature. Columns follow the order of the query when converted
+//! with `to_data_frame_for_query`, otherwise `timestamp` (or `__time`) comes first and the
+//! other columns by name. Columns get their dtype from the json values:
+//! integers become `Int64`, other numbers `Float64`, booleans `Boolean` and everything
+//! else `String`. Missing values and json `null`s become polars nulls. Druid timestamps
+//! (`timestamp` as ISO-8601 string, `__time` as epoch millis) become `Datetime(ms)` columns.
+//!
+//! ```no_run
+//! # use druid_io::client::DruidClient;
+//! # use druid_io::query::group_by::GroupBy;
+//! use druid_io::dataframe::ToDataFrame;
+//!
+//! # async fn example(client: DruidClient, group_by: GroupBy) {
+//! let result = client.group_by::<serde_json::Value>(&group_by).await.unwrap();
+//! let df = result.to_data_frame().unwrap();
+//! # }
+//! ```
+use crate::export::columns_for;
+use crate::query::response::{ResultRows, Row};
+use crate::query::Query;
+use polars::prelude::*;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+pub trait ToDataFrame {
+    fn to_data_frame(&self) -> PolarsResult<DataFrame>;
+
+    /// Orders the columns like [`columns_for`] the query, other columns follow by name.
+    fn to_data_frame_for_query(&self, query: &Query) -> PolarsResult<DataFrame>;
+}
+
+impl<R: ResultRows> ToDataFrame for R {
+    fn to_data_frame(&self) -> PolarsResult<DataFrame> {
+        rows_to_data_frame(&flatten(self)?)
+    }
+
+    fn to_data_frame_for_query(&self, query: &Query) -> PolarsResult<DataFrame> {
+        ordered_rows_to_data_frame(&flatten(self)?, &columns_for(query))
+    }
+}
+
+fn flatten<R: ResultRows>(result: &R) -> PolarsResult<Vec<Row>> {
+    result
+        .rows()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| polars_err!(ComputeError: "couldn't flatten result rows: {}", err))
+}
+
+/// Builds a `DataFrame` out of flat rows; `timestamp` and `__time` go first, other columns by name.
+pub fn rows_to_data_frame(rows: &[Row]) -> PolarsResult<DataFrame> {
+    ordered_rows_to_data_frame(rows, &[])
+}
+
+/// Builds a `DataFrame` out of flat rows with the given columns first, in their order, even when
+/// no row has them. Columns the rows have besides those follow like in [`rows_to_data_frame`].
+pub fn ordered_rows_to_data_frame(rows: &[Row], columns: &[String]) -> PolarsResult<DataFrame> {
+    // don't rely on the key order of `Row`, it depends on serde_json's features
+    let mut others: Vec<&String> = rows
+        .iter()
+        .flat_map(Row::keys)
+        .filter(|name| !columns.contains(name))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if columns.is_empty() {
+        others.sort_by_key(|name| !matches!(name.as_str(), "timestamp" | "__time"));
+    }
+    let columns = columns
+        .iter()
+        .chain(others)
+        .map(|name| {
+            let values: Vec<Option<&Value>> = rows
+                .iter()
+                .map(|row| row.get(name).filter(|value| !value.is_null()))
+                .collect();
+            column(name, &values)
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+    DataFrame::new(columns)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnKind {
+    Null,
+    Boolean,
+    Integer,
+    Float,
+    Text,
+}
+
+impl ColumnKind {
+    fn of(value: &Value) -> ColumnKind {
+        match value {
+            Value::Null => ColumnKind::Null,
+            Value::Bool(_) => ColumnKind::Boolean,
+            Value::Number(number) if number.is_i64() => ColumnKind::Integer,
+            Value::Number(_) => ColumnKind::Float,
+            _ => ColumnKind::Text,
+        }
+    }
+
+    fn merge(self, other: ColumnKind) -> ColumnKind {
+        use ColumnKind::*;
+        match (self, other) {
+            (Null, kind) | (kind, Null) => kind,
+            (a, b) if a == b => a,
+            (Integer, Float) | (Float, Integer) => Float,
+            _ => Text,
+        }
+    }
+}
+
+fn column(name: &str, values: &[Option<&Value>]) -> PolarsResult<Column> {
+    let kind = values
+        .iter()
+        .flatten()
+        .fold(ColumnKind::Null, |kind, value| {
+            kind.merge(ColumnKind::of(value))
+        });
+
+    let series = match kind {
+        ColumnKind::Integer if name == "__time" => {
+            let millis: Int64Chunked = values
+                .iter()
+                .map(|value| value.and_then(Value::as_i64))
+                .collect();
+            datetime(name, millis)
+        }
+        ColumnKind::Text
+            if name == "timestamp" && values.iter().flatten().all(|v| iso_millis(v).is_some()) =>
+        {
+            let millis: Int64Chunked = values
+                .iter()
+                .map(|value| value.and_then(iso_millis))
+                .collect();
+            datetime(name, millis)
+        }
+        ColumnKind::Null | ColumnKind::Text => {
+            let strings: Vec<Option<String>> = values
+                .iter()
+                .map(|value| {
+                    value.map(|value| match value {
+                        Value::String(string) => string.clone(),
+                        other => other.to_string(),
+                    })
+                })
+                .collect();
+            Series::new(name.into(), strings)
+        }
+        ColumnKind::Boolean => {
+            let booleans: Vec<Option<bool>> = values
+                .iter()
+                .map(|value| value.and_then(Value::as_bool))
+                .collect();
+            Series::new(name.into(), booleans)
+        }
+        ColumnKind::Integer => {
+            let integers: Vec<Option<i64>> = values
+                .iter()
+                .map(|value| value.and_then(Value::as_i64))
+                .collect();
+            Series::new(name.into(), integers)
+        }
+        ColumnKind::Float => {
+            let floats: Vec<Option<f64>> = values
+                .iter()
+                .map(|value| value.and_then(Value::as_f64))
+                .collect();
+            Series::new(name.into(), floats)
+        }
+    };
+    Ok(series.into_column())
+}
+
+fn datetime(name: &str, millis: Int64Chunked) -> Series {
+    millis
+        .with_name(name.into())
+        .into_datetime(TimeUnit::Milliseconds, None)
+        .into_series()
+}
+
+fn iso_millis(value: &Value) -> Option<i64> {
+    value
+        .as_str()
+        .and_then(|str| chrono::DateTime::parse_from_rfc3339(str).ok())
+        .map(|time| time.timestamp_millis())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::definitions::{Aggregation, Dimension};
+    use crate::query::group_by::GroupByBuilder;
+    use crate::query::response::{GroupByResponse, TopNResponse};
+    use crate::query::DataSource;
+    use serde_json::json;
+
+    #[test]
+    fn test_group_by_dtypes_and_nulls() {
+        let response: Vec<GroupByResponse<Value>> = serde_json::from_value(json!([
+            {"timestamp": "2015-09-12T00:00:00.000Z", "event": {"page": "Main", "count": 10, "ratio": 0.5}},
+            {"timestamp": "2015-09-12T01:00:00.000Z", "event": {"page": null, "count": 3, "ratio": 1}}
+        ]))
+        .unwrap();
+        let df = response.to_data_frame().unwrap();
+
+        assert_eq!(
+            df.get_column_names(),
+            &["timestamp", "count", "page", "ratio"]
+        );
+        assert_eq!(
+            df.column("timestamp").unwrap().dtype(),
+            &DataType::Datetime(TimeUnit::Milliseconds, None)
+        );
+        assert_eq!(df.column("page").unwrap().dtype(), &DataType::String);
+        assert_eq!(df.column("page").unwrap().null_count(), 1);
+        assert_eq!(df.column("count").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(df.column("ratio").unwrap().dtype(), &DataType::Float64);
+    }
+
+    #[test]
+    fn test_query_column_order() {
+        let group_by = GroupByBuilder::new(DataSource::table("wikipedia"))
+            .dimensions(vec![Dimension::default("page")])
+            .aggregations(vec![Aggregation::count("count")])
+            .build();
+        let response: Vec<GroupByResponse<Value>> = serde_json::from_value(json!([
+            {"timestamp": "2015-09-12T00:00:00.000Z", "event": {"user": "Taffe316", "page": "Main", "count": 10}},
+            {"timestamp": "2015-09-12T01:00:00.000Z", "event": {"added": 5, "page": "Rust", "count": 3}}
+        ]))
+        .unwrap();
+        let df = response.to_data_frame_for_query(&group_by.into()).unwrap();
+
+        assert_eq!(
+            df.get_column_names(),
+            &["timestamp", "page", "count", "added", "user"]
+        );
+    }
+
+    #[test]
+    fn test_top_n_long_format() {
+        let response: Vec<TopNResponse<Value>> = serde_json::from_value(json!([
+            {"timestamp": "2015-09-12T00:00:00.000Z", "result": [{"page": "a", "count": 2}, {"page": "b", "count": 1}]},
+            {"timestamp": "2015-09-13T00:00:00.000Z", "result": [{"page": "c", "count": 5}]}
+        ]))
+        .unwrap();
+        let df = response.to_data_frame().unwrap();
+
+        assert_eq!(df.shape(), (3, 3));
+        assert_eq!(df.column("count").unwrap().i64().unwrap().get(2), Some(5));
+    }
+}
@@ -0,0 +1,277 @@
+//! Guarded deletion of datasource segments: marking them unused (they stay in deep storage
+//! and can be re-enabled) and killing them (unused segments are removed permanently).
+//!
+//! Destructive operations are two steps. A [`DeletionPlan`] lists the segments to be affected
+//! without changing anything, executing it requires the plan's confirmation token, so a typo in
+//! a datasource name or interval can't silently remove more data than was reviewed. The token is
+//! a hash of the ids of the planned segments, it's the same for any build of the client, and is
+//! meant to be shown to a person along with the plan and typed back by them, passing it straight
+//! back to the execution skips the review. It certifies nothing but the listed set of segments.
+//!
+//! ```no_run
+//! use druid_io::coordinator::CoordinatorClient;
+//! use druid_io::overlord::OverlordClient;
+//!
+//! # async fn example() {
+//! let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+//! let overlord = OverlordClient::new(vec!["localhost:8090".to_string()]);
+//!
+//! let plan = coordinator
+//!     .plan_kill("wikipedia", Some("2015-09-12/2015-09-13"))
+//!     .await
+//!     .unwrap();
+//! for segment in &plan.segments {
+//!     println!("{}", segment.identifier);
+//! }
+//! println!("type {} to delete these segments permanently", plan.confirmation_token());
+//! let mut confirmation = String::new();
+//! std::io::stdin().read_line(&mut confirmation).unwrap();
+//! let task_id = overlord
+//!     .kill(&coordinator, &plan, confirmation.trim())
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use crate::client::ClientResult;
+use crate::coordinator::DataSegment;
+use crate::query::definitions::ETERNITY;
+use crate::time::parse_interval;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SegmentSelection {
+    /// Every segment of the datasource
+    All,
+    /// Segments fully contained in the ISO 8601 interval, e.g. `2015-09-12/P1D`
+    Interval(String),
+    /// Segments with given ids
+    Segments(Vec<String>),
+}
+
+impl SegmentSelection {
+    /// Fails when the interval of the selection or of a segment isn't valid.
+    pub(crate) fn matches(&self, segment: &DataSegment) -> ClientResult<bool> {
+        match self {
+            SegmentSelection::All => Ok(true),
+            SegmentSelection::Interval(interval) => interval_contains(interval, &segment.interval),
+            SegmentSelection::Segments(ids) => Ok(ids.contains(&segment.identifier)),
+        }
+    }
+
+    /// Body of `markUnused` and `markUsed` requests, `None` for the whole datasource.
+    pub(crate) fn payload(&self) -> Option<SegmentsPayload> {
+        match self {
+            SegmentSelection::All => None,
+            SegmentSelection::Interval(interval) => Some(SegmentsPayload {
+                interval: Some(interval.clone()),
+                segment_ids: None,
+            }),
+            SegmentSelection::Segments(ids) => Some(SegmentsPayload {
+                interval: None,
+                segment_ids: Some(ids.clone()),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeletionKind {
+    /// Segments are dropped from historicals, but stay in deep storage and metadata
+    MarkUnused,
+    /// Unused segments are removed from deep storage and metadata by a kill task
+    Kill,
+}
+
+/// Segments affected by a deletion, see the [module](self) docs.
+#[derive(Debug, Clone)]
+pub struct DeletionPlan {
+    pub data_source: String,
+    pub kind: DeletionKind,
+    pub selection: SegmentSelection,
+    pub segments: Vec<DataSegment>,
+}
+
+impl DeletionPlan {
+    pub(crate) fn new(
+        data_source: &str,
+        kind: DeletionKind,
+        selection: SegmentSelection,
+        segments: Vec<DataSegment>,
+    ) -> ClientResult<Self> {
+        let mut planned = vec![];
+        for segment in segments {
+            if selection.matches(&segment)? {
+                planned.push(segment);
+            }
+        }
+        Ok(DeletionPlan {
+            data_source: data_source.to_string(),
+            kind,
+            selection,
+            segments: planned,
+        })
+    }
+
+    /// Token to be typed back by the person reviewing the plan, e.g. `3f1e5a9c0b27d468`.
+    /// It's a hash of the sorted ids of the planned segments, so it changes when the segments do
+    /// and only certifies that set, not the kind of deletion or anything else about the plan.
+    pub fn confirmation_token(&self) -> String {
+        format!("{:016x}", fnv1a(&self.segment_ids()))
+    }
+
+    pub fn size(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.size).sum()
+    }
+
+    /// Sorted ids of the planned segments.
+    pub(crate) fn segment_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self
+            .segments
+            .iter()
+            .map(|segment| segment.identifier.as_str())
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub(crate) fn kill_interval(&self) -> Option<&str> {
+        match &self.selection {
+            SegmentSelection::All => Some(ETERNITY),
+            SegmentSelection::Interval(interval) => Some(interval),
+            SegmentSelection::Segments(_) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SegmentsPayload {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interval: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segment_ids: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ChangedSegments {
+    pub(crate) num_changed_segments: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename = "kill")]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct KillTask {
+    pub(crate) data_source: String,
+    pub(crate) interval: String,
+}
+
+fn interval_contains(interval: &str, inner: &str) -> ClientResult<bool> {
+    let (start, end) = parse_interval(interval)?;
+    let (inner_start, inner_end) = parse_interval(inner)?;
+    Ok(start <= inner_start && inner_end <= end)
+}
+
+// 64 bit FNV-1a of the ids, unlike `DefaultHasher` it doesn't change between rust releases
+fn fnv1a(ids: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for id in ids {
+        // the separator keeps `["ab", "c"]` and `["a", "bc"]` apart
+        for byte in id.bytes().chain(std::iter::once(0)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn plan(ids: &[&str]) -> DeletionPlan {
+        let segments = ids
+            .iter()
+            .map(|id| {
+                serde_json::from_value(serde_json::json!({
+                    "dataSource": "wikipedia",
+                    "interval": "2015-09-12T00:00:00.000Z/2015-09-13T00:00:00.000Z",
+                    "version": "v1",
+                    "loadSpec": {"type": "local"},
+                    "shardSpec": {"type": "numbered"},
+                    "size": 1,
+                    "identifier": id,
+                }))
+                .unwrap()
+            })
+            .collect();
+        DeletionPlan::new(
+            "wikipedia",
+            DeletionKind::Kill,
+            SegmentSelection::All,
+            segments,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_confirmation_token() {
+        let token = plan(&["a", "b"]).confirmation_token();
+        assert_eq!(token, "ab40d7820d408076");
+        assert_eq!(token, plan(&["b", "a"]).confirmation_token());
+        assert_ne!(token, plan(&["a", "c"]).confirmation_token());
+        assert_ne!(token, plan(&["ab"]).confirmation_token());
+    }
+
+    #[test]
+    fn test_interval_contains() {
+        let contains = |interval, inner| interval_contains(interval, inner).unwrap();
+        let interval = "2015-09-12/2015-09-13";
+        assert!(contains(
+            interval,
+            "2015-09-12T00:00:00.000Z/2015-09-12T01:00:00.000Z"
+        ));
+        assert!(contains(
+            interval,
+            "2015-09-12T23:00:00.000Z/2015-09-13T00:00:00.000Z"
+        ));
+        assert!(!contains(
+            interval,
+            "2015-09-12T23:00:00.000Z/2015-09-13T01:00:00.000Z"
+        ));
+        assert!(contains(
+            "2015-09-12T10:00:00Z/2015-09-12T11:00Z",
+            "2015-09-12T10:00:00.000Z/2015-09-12T11:00:00.000Z"
+        ));
+        assert!(interval_contains("2015-09-12", "2015-09-12/2015-09-13").is_err());
+    }
+
+    #[test]
+    fn test_interval_contains_periods() {
+        let inner = "2015-09-12T00:00:00.000Z/2015-09-13T00:00:00.000Z";
+        assert!(interval_contains("2015-09-12/P1D", inner).unwrap());
+        assert!(interval_contains("P1D/2015-09-13", inner).unwrap());
+        assert!(interval_contains("2015-09-01/P1M", inner).unwrap());
+        assert!(!interval_contains("2015-09-12/PT23H", inner).unwrap());
+    }
+
+    #[test]
+    fn test_interval_contains_offsets() {
+        let inner = "2015-09-12T00:00:00.000Z/2015-09-12T01:00:00.000Z";
+        // 05:00 at +05:00 is midnight UTC
+        assert!(interval_contains("2015-09-12T05:00+05:00/2015-09-12T06:00+05:00", inner).unwrap());
+        // but midnight at +05:00 is still the day before in UTC
+        assert!(
+            !interval_contains("2015-09-12T00:00+05:00/2015-09-12T01:00+05:00", inner).unwrap()
+        );
+        assert!(interval_contains("2015-09-11T19:00-05:00/PT1H", inner).unwrap());
+    }
+
+    #[test]
+    fn test_interval_contains_eternity() {
+        let inner = "2015-09-12T00:00:00.000Z/2015-09-13T00:00:00.000Z";
+        assert!(interval_contains(ETERNITY, inner).unwrap());
+        assert!(interval_contains(ETERNITY, ETERNITY).unwrap());
+        assert!(!interval_contains(inner, ETERNITY).unwrap());
+    }
+}
@@ -0,0 +1,388 @@
+//! Export of query results into CSV and NDJSON (json lines).
+//!
+//! Rows are written one at a time as they are flattened from the result, nothing is
+//! buffered besides what the underlying writer does. Rows that are still being received,
+//! like the ones of `DruidClient::sql_stream`, are exported with [`write_stream`] as they
+//! arrive. Column order follows the query: `Scan::columns`, or dimension, aggregation and
+//! post-aggregation output names prefixed by the bucket `timestamp`. When the query doesn't
+//! define columns (e.g. a Scan of all columns) a complete result exports every column any
+//! row has, while a CSV written row by row takes them from the first row and fails on
+//! columns that show up later.
+//!
+//! ```no_run
+//! # use druid_io::client::DruidClient;
+//! # use druid_io::query::{Query, group_by::GroupBy};
+//! use druid_io::export::{CsvExporter, RowExporter};
+//!
+//! # async fn example(client: DruidClient, group_by: GroupBy) {
+//! let result = client.group_by::<serde_json::Value>(&group_by).await.unwrap();
+//! let query: Query = group_by.into();
+//! let file = std::fs::File::create("pages.csv").unwrap();
+//! let mut exporter = CsvExporter::for_query(file, &query);
+//! exporter.write_all(&result).unwrap();
+//! exporter.flush().unwrap();
+//! # }
+//! ```
+use crate::client::{ClientResult, DruidClientError};
+use crate::query::response::{ResultRows, Row};
+use crate::query::Query;
+use crate::serialization::OrderedObject;
+use futures::stream::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ExportError {
+    #[error("couldn't write exported rows")]
+    Io { source: std::io::Error },
+    #[error("couldn't write csv record")]
+    Csv { source: csv::Error },
+    #[error("couldn't convert result row to json")]
+    Json { source: serde_json::Error },
+    #[error("couldn't receive exported rows")]
+    Query { source: DruidClientError },
+    #[error("column `{column}` is not in the csv header taken from the first row")]
+    UnknownColumn { column: String },
+}
+type ExportResult<T> = Result<T, ExportError>;
+
+/// Output columns of a query result, in the order they should be exported.
+pub fn columns_for(query: &Query) -> Vec<String> {
+    match query {
+        Query::Scan(_) | Query::SegmentMetadata(_) => query.output_names(),
+        _ => std::iter::once("timestamp".to_string())
+            .chain(query.output_names())
+            .collect(),
+    }
+}
+
+pub trait RowExporter {
+    fn write_row(&mut self, row: &Row) -> ExportResult<()>;
+
+    fn flush(&mut self) -> ExportResult<()>;
+
+    /// Writes every row of the result, returns number of rows written.
+    fn write_all<R: ResultRows>(&mut self, result: &R) -> ExportResult<usize>
+    where
+        Self: Sized,
+    {
+        let mut count = 0;
+        for row in result.rows() {
+            let row = row.map_err(|source| ExportError::Json { source })?;
+            self.write_row(&row)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Writes every row as the iterator yields it, returns number of rows written.
+    fn write_iter<I, T>(&mut self, rows: I) -> ExportResult<usize>
+    where
+        Self: Sized,
+        I: IntoIterator<Item = T>,
+        T: Serialize,
+    {
+        let mut count = 0;
+        for row in rows {
+            self.write_row(&to_row(&row)?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Writes rows as they are received, e.g. of `DruidClient::sql_stream` with an object result
+/// format, returns number of rows written.
+pub async fn write_stream<E, S, T>(exporter: &mut E, rows: S) -> ExportResult<usize>
+where
+    E: RowExporter,
+    S: Stream<Item = ClientResult<T>> + Unpin,
+    T: Serialize,
+{
+    let mut rows = rows;
+    let mut count = 0;
+    while let Some(row) = rows.next().await {
+        let row = row.map_err(|source| ExportError::Query { source })?;
+        exporter.write_row(&to_row(&row)?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn to_row<T: Serialize>(row: &T) -> ExportResult<Row> {
+    match serde_json::to_value(row).map_err(|source| ExportError::Json { source })? {
+        Value::Object(row) => Ok(row),
+        other => Err(ExportError::Json {
+            source: serde::ser::Error::custom(format!(
+                "exported row has to be an object, found {}",
+                other
+            )),
+        }),
+    }
+}
+
+pub struct CsvExporter<W: Write> {
+    writer: csv::Writer<W>,
+    columns: Vec<String>,
+    header_written: bool,
+    // columns were taken from the first row instead of the query
+    inferred: bool,
+}
+
+impl<W: Write> CsvExporter<W> {
+    pub fn new(writer: W, columns: Vec<String>) -> Self {
+        CsvExporter {
+            writer: csv::Writer::from_writer(writer),
+            columns,
+            header_written: false,
+            inferred: false,
+        }
+    }
+
+    pub fn for_query(writer: W, query: &Query) -> Self {
+        CsvExporter::new(writer, columns_for(query))
+    }
+
+    pub fn into_inner(self) -> ExportResult<W> {
+        self.writer.into_inner().map_err(|err| ExportError::Io {
+            source: err.into_error(),
+        })
+    }
+}
+
+impl<W: Write> RowExporter for CsvExporter<W> {
+    fn write_row(&mut self, row: &Row) -> ExportResult<()> {
+        if !self.header_written {
+            if self.columns.is_empty() {
+                self.columns = row.keys().cloned().collect();
+                self.inferred = true;
+            }
+            self.writer
+                .write_record(&self.columns)
+                .map_err(|source| ExportError::Csv { source })?;
+            self.header_written = true;
+        }
+        if self.inferred {
+            if let Some(column) = row.keys().find(|key| !self.columns.contains(key)) {
+                return Err(ExportError::UnknownColumn {
+                    column: column.clone(),
+                });
+            }
+        }
+        let record = self.columns.iter().map(|column| match row.get(column) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(str)) => str.clone(),
+            Some(other) => other.to_string(),
+        });
+        self.writer
+            .write_record(record)
+            .map_err(|source| ExportError::Csv { source })
+    }
+
+    fn flush(&mut self) -> ExportResult<()> {
+        self.writer
+            .flush()
+            .map_err(|source| ExportError::Io { source })
+    }
+
+    /// Without columns of the query the header has every column of any row, in order of appearance.
+    fn write_all<R: ResultRows>(&mut self, result: &R) -> ExportResult<usize> {
+        if self.columns.is_empty() && !self.header_written {
+            for row in result.rows() {
+                let row = row.map_err(|source| ExportError::Json { source })?;
+                for key in row.keys() {
+                    if !self.columns.contains(key) {
+                        self.columns.push(key.clone());
+                    }
+                }
+            }
+        }
+        let mut count = 0;
+        for row in result.rows() {
+            let row = row.map_err(|source| ExportError::Json { source })?;
+            self.write_row(&row)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+pub struct NdjsonExporter<W: Write> {
+    writer: W,
+    columns: Vec<String>,
+}
+
+impl<W: Write> NdjsonExporter<W> {
+    pub fn new(writer: W, columns: Vec<String>) -> Self {
+        NdjsonExporter { writer, columns }
+    }
+
+    pub fn for_query(writer: W, query: &Query) -> Self {
+        NdjsonExporter::new(writer, columns_for(query))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> RowExporter for NdjsonExporter<W> {
+    fn write_row(&mut self, row: &Row) -> ExportResult<()> {
+        // known columns go first and are always present, anything else keeps row order
+        let mut ordered = OrderedObject::default();
+        for column in &self.columns {
+            let value = row.get(column).cloned().unwrap_or(Value::Null);
+            ordered.0.push((column.clone(), value));
+        }
+        for (key, value) in row {
+            if !self.columns.contains(key) {
+                ordered.0.push((key.clone(), value.clone()));
+            }
+        }
+        serde_json::to_writer(&mut self.writer, &ordered)
+            .map_err(|source| ExportError::Json { source })?;
+        self.writer
+            .write_all(b"\n")
+            .map_err(|source| ExportError::Io { source })
+    }
+
+    fn flush(&mut self) -> ExportResult<()> {
+        self.writer
+            .flush()
+            .map_err(|source| ExportError::Io { source })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::definitions::Granularity;
+    use crate::query::definitions::{Aggregation, Dimension};
+    use crate::query::group_by::GroupByBuilder;
+    use crate::query::group_by::PostAggregation;
+    use crate::query::response::GroupByResponse;
+    use crate::query::top_n::TopN;
+    use crate::query::DataSource;
+    use futures::stream;
+    use serde_json::json;
+
+    fn group_by() -> Query {
+        GroupByBuilder::new(DataSource::table("wikipedia"))
+            .dimensions(vec![Dimension::default("page")])
+            .aggregations(vec![
+                Aggregation::count("count"),
+                Aggregation::long_sum("added", "added"),
+            ])
+            .build()
+            .into()
+    }
+
+    fn result() -> Vec<GroupByResponse<Value>> {
+        serde_json::from_value(json!([
+            {"timestamp": "2015-09-12T00:00:00.000Z", "event": {"added": 7, "count": 2, "page": "Main, Page"}},
+            {"timestamp": "2015-09-12T00:00:00.000Z", "event": {"count": 1, "page": null}}
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_csv_export_uses_query_column_order() {
+        let mut exporter = CsvExporter::for_query(vec![], &group_by());
+        assert_eq!(exporter.write_all(&result()).unwrap(), 2);
+        let csv = String::from_utf8(exporter.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "timestamp,page,count,added\n\
+             2015-09-12T00:00:00.000Z,\"Main, Page\",2,7\n\
+             2015-09-12T00:00:00.000Z,,1,\n"
+        );
+    }
+
+    #[test]
+    fn test_ndjson_export() {
+        let mut exporter = NdjsonExporter::for_query(vec![], &group_by());
+        exporter.write_all(&result()).unwrap();
+        let ndjson = String::from_utf8(exporter.into_inner()).unwrap();
+        let lines: Vec<&str> = ndjson.lines().collect();
+        assert_eq!(
+            lines[0],
+            r#"{"timestamp":"2015-09-12T00:00:00.000Z","page":"Main, Page","count":2,"added":7}"#
+        );
+        assert_eq!(
+            lines[1],
+            r#"{"timestamp":"2015-09-12T00:00:00.000Z","page":null,"count":1,"added":null}"#
+        );
+    }
+
+    #[test]
+    fn test_csv_header_has_columns_of_every_row() {
+        let result: Vec<GroupByResponse<Value>> = serde_json::from_value(json!([
+            {"timestamp": "2015-09-12T00:00:00.000Z", "event": {"count": 2, "page": "Main"}},
+            {"timestamp": "2015-09-12T00:00:00.000Z", "event": {"count": 1, "page": "Rust", "user": "Taffe316"}}
+        ]))
+        .unwrap();
+        let mut exporter = CsvExporter::new(vec![], vec![]);
+        exporter.write_all(&result).unwrap();
+        let csv = String::from_utf8(exporter.into_inner().unwrap()).unwrap();
+        assert_eq!(csv.lines().next(), Some("count,page,timestamp,user"));
+        assert!(csv.ends_with(",Taffe316\n"));
+
+        let mut exporter = CsvExporter::new(vec![], vec![]);
+        let rows = result.rows().map(Result::unwrap).collect::<Vec<_>>();
+        match exporter.write_iter(rows) {
+            Err(ExportError::UnknownColumn { column }) => assert_eq!(column, "user"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_export_stream() {
+        let rows = stream::iter(vec![
+            Ok(json!({"page": "Main", "count": 2})),
+            Ok(json!({"count": 1})),
+        ]);
+        let mut exporter = NdjsonExporter::new(vec![], vec!["page".into(), "count".into()]);
+        let count = tokio_test::block_on(write_stream(&mut exporter, rows)).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(exporter.into_inner()).unwrap(),
+            "{\"page\":\"Main\",\"count\":2}\n{\"page\":null,\"count\":1}\n"
+        );
+
+        let failed = stream::iter(vec![Err(DruidClientError::Transport {
+            source: "connection reset".into(),
+        })]);
+        let mut exporter = NdjsonExporter::new(vec![], vec![]);
+        match tokio_test::block_on(write_stream::<_, _, Value>(&mut exporter, failed)) {
+            Err(ExportError::Query { .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_top_n_columns() {
+        let top_n = TopN {
+            data_source: DataSource::table("wikipedia"),
+            dimension: Dimension::default("page"),
+            threshold: 10,
+            metric: "count".into(),
+            aggregations: vec![Aggregation::count("count")],
+            post_aggregations: vec![PostAggregation::Javascript {
+                name: "double".into(),
+                field_names: vec!["count".into()],
+                function: "function(count) { return count * 2; }".into(),
+            }],
+            intervals: vec!["2015-09-12/2015-09-13".into()],
+            filter: None,
+            granularity: Granularity::All,
+            context: Default::default(),
+        };
+        assert_eq!(
+            columns_for(&top_n.into()),
+            vec!["timestamp", "page", "count", "double"]
+        );
+    }
+}
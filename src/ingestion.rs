@@ -0,0 +1,552 @@
+//! Typed [native batch ingestion](https://druid.apache.org/docs/latest/ingestion/native-batch.html) specs.
+//!
+//! ```no_run
+//! use druid_io::ingestion::*;
+//! use druid_io::query::definitions::{Aggregation, Granularity};
+//!
+//! let task = IngestionTask::index_parallel(IngestionSpec {
+//!     data_schema: DataSchema {
+//!         data_source: "countries".into(),
+//!         timestamp_spec: TimestampSpec::constant("2010-01-01T00:00:00Z"),
+//!         dimensions_spec: DimensionsSpec::names(vec!["Name", "Code", "Capital"]),
+//!         metrics_spec: vec![Aggregation::count("count")],
+//!         granularity_spec: GranularitySpec::uniform(Granularity::Hour, Granularity::Hour),
+//!         transform_spec: None,
+//!     },
+//!     io_config: IoConfig::new(
+//!         TaskType::IndexParallel,
+//!         InputSource::Local {
+//!             base_dir: "quickstart/tutorial/".into(),
+//!             filter: Some("countries.csv".into()),
+//!             files: None,
+//!         },
+//!         InputFormat::csv_with_header(),
+//!     ),
+//!     tuning_config: None,
+//! });
+//! ```
+use crate::query::definitions::{Aggregation, Filter, Granularity};
+use crate::serialization::{tagged_or_untagged, OrderedObject};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskType {
+    IndexParallel,
+    Index,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestionTask {
+    #[serde(rename = "type")]
+    pub task_type: TaskType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub spec: IngestionSpec,
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub context: HashMap<String, serde_json::Value>,
+}
+
+impl IngestionTask {
+    pub fn index_parallel(spec: IngestionSpec) -> Self {
+        IngestionTask {
+            task_type: TaskType::IndexParallel,
+            id: None,
+            spec,
+            context: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestionSpec {
+    pub io_config: IoConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tuning_config: Option<TuningConfig>,
+    pub data_schema: DataSchema,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DataSchema {
+    pub data_source: String,
+    pub timestamp_spec: TimestampSpec,
+    pub dimensions_spec: DimensionsSpec,
+    #[serde(default)]
+    pub metrics_spec: Vec<Aggregation>,
+    pub granularity_spec: GranularitySpec,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transform_spec: Option<TransformSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TimestampSpec {
+    pub column: String,
+    /// iso, posix, millis, micro, nano, auto or a Joda format, druid's default is `auto`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing_value: Option<String>,
+}
+
+impl TimestampSpec {
+    pub fn new(column: &str, format: &str) -> Self {
+        TimestampSpec {
+            column: column.to_string(),
+            format: Some(format.to_string()),
+            missing_value: None,
+        }
+    }
+
+    /// Every row gets the same timestamp, for data without time column.
+    pub fn constant(timestamp: &str) -> Self {
+        TimestampSpec {
+            column: "!!!_no_such_column_!!!".to_string(),
+            format: None,
+            missing_value: Some(timestamp.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DimensionsSpec {
+    #[serde(default)]
+    pub dimensions: Vec<DimensionSchema>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub dimension_exclusions: Vec<String>,
+}
+
+impl DimensionsSpec {
+    pub fn names(names: Vec<&str>) -> Self {
+        DimensionsSpec {
+            dimensions: names
+                .into_iter()
+                .map(|name| DimensionSchema::Name(name.to_string()))
+                .collect(),
+            dimension_exclusions: vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum DimensionSchema {
+    /// String dimension
+    Name(String),
+    Typed {
+        #[serde(rename = "type")]
+        dimension_type: DimensionType,
+        name: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DimensionType {
+    String,
+    Long,
+    Float,
+    Double,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename = "uniform")]
+#[serde(rename_all = "camelCase")]
+pub struct GranularitySpec {
+    #[serde(
+        default,
+        deserialize_with = "tagged_or_untagged",
+        serialize_with = "upper_case",
+        skip_serializing_if = "is_null"
+    )]
+    pub segment_granularity: Granularity,
+    #[serde(
+        default,
+        deserialize_with = "tagged_or_untagged",
+        serialize_with = "upper_case",
+        skip_serializing_if = "is_null"
+    )]
+    pub query_granularity: Granularity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollup: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub intervals: Vec<String>,
+}
+
+impl GranularitySpec {
+    pub fn uniform(segment_granularity: Granularity, query_granularity: Granularity) -> Self {
+        GranularitySpec {
+            segment_granularity,
+            query_granularity,
+            rollup: None,
+            intervals: vec![],
+        }
+    }
+}
+
+fn is_null(granularity: &Granularity) -> bool {
+    matches!(granularity, Granularity::Null)
+}
+
+// granularity spec is conventionally written in upper case: "DAY", "HOUR"
+fn upper_case<S: Serializer>(granularity: &Granularity, serializer: S) -> Result<S::Ok, S::Error> {
+    match serde_json::to_value(granularity) {
+        Ok(serde_json::Value::String(name)) => serializer.serialize_str(&name.to_uppercase()),
+        _ => granularity.serialize(serializer),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransformSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename = "expression")]
+pub struct Transform {
+    pub name: String,
+    pub expression: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IoConfig {
+    #[serde(rename = "type")]
+    pub config_type: TaskType,
+    pub input_source: InputSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_format: Option<InputFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub append_to_existing: Option<bool>,
+}
+
+impl IoConfig {
+    pub fn new(
+        config_type: TaskType,
+        input_source: InputSource,
+        input_format: InputFormat,
+    ) -> Self {
+        IoConfig {
+            config_type,
+            input_source,
+            input_format: Some(input_format),
+            append_to_existing: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum InputSource {
+    #[serde(rename_all = "camelCase")]
+    Local {
+        base_dir: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        filter: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        files: Option<Vec<String>>,
+    },
+    Http {
+        uris: Vec<String>,
+    },
+    Inline {
+        data: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    S3 {
+        #[serde(skip_serializing_if = "Vec::is_empty", default)]
+        uris: Vec<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty", default)]
+        prefixes: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Druid {
+        data_source: String,
+        interval: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum InputFormat {
+    Json {},
+    #[serde(rename_all = "camelCase")]
+    Csv {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        columns: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        find_columns_from_header: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        skip_header_rows: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        list_delimiter: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Tsv {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        delimiter: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        columns: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        find_columns_from_header: Option<bool>,
+    },
+    Parquet {},
+    Orc {},
+}
+
+impl InputFormat {
+    pub fn csv_with_header() -> Self {
+        InputFormat::Csv {
+            columns: None,
+            find_columns_from_header: Some(true),
+            skip_header_rows: None,
+            list_delimiter: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TuningConfig {
+    #[serde(rename = "type")]
+    pub config_type: TaskType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partitions_spec: Option<PartitionsSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rows_in_memory: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_num_concurrent_sub_tasks: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force_guaranteed_rollup: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionsSpec {
+    #[serde(rename_all = "camelCase")]
+    Dynamic {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_rows_per_segment: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_total_rows: Option<u64>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Hashed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        num_shards: Option<usize>,
+        #[serde(skip_serializing_if = "Vec::is_empty", default)]
+        partition_dimensions: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target_rows_per_segment: Option<usize>,
+    },
+    #[serde(rename_all = "camelCase")]
+    SingleDim {
+        partition_dimension: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target_rows_per_segment: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        assume_grouped: Option<bool>,
+    },
+}
+
+/// Ingestion of rows serialized from Rust values, sent inline within the task spec.
+/// Meant for small reference tables, Druid keeps the whole task payload in its metadata store.
+///
+/// Columns are derived from the serialized rows: integer and float fields become `longSum` and
+/// `doubleSum` metrics, any other field a string dimension (arrays multi-value dimensions).
+/// Rows aren't rolled up, unless `timestamp` is set all of them get the same one.
+#[derive(Debug, Clone)]
+pub struct InlineIngestion {
+    data_source: String,
+    data: String,
+    columns: Vec<(String, ColumnKind)>,
+    timestamp_spec: TimestampSpec,
+    append_to_existing: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnKind {
+    Unknown,
+    Long,
+    Double,
+    String,
+}
+
+impl ColumnKind {
+    fn merge(self, value: &serde_json::Value) -> Self {
+        use serde_json::Value;
+        let kind = match value {
+            Value::Null | Value::Object(_) => return self,
+            Value::Number(n) if n.is_i64() || n.is_u64() => ColumnKind::Long,
+            Value::Number(_) => ColumnKind::Double,
+            _ => ColumnKind::String,
+        };
+        match (self, kind) {
+            (ColumnKind::Unknown, kind) => kind,
+            (ColumnKind::Long, ColumnKind::Double) | (ColumnKind::Double, ColumnKind::Long) => {
+                ColumnKind::Double
+            }
+            (current, kind) if current == kind => kind,
+            _ => ColumnKind::String,
+        }
+    }
+}
+
+impl InlineIngestion {
+    pub fn from_rows<T: Serialize>(data_source: &str, rows: &[T]) -> serde_json::Result<Self> {
+        let mut data = String::new();
+        let mut columns: Vec<(String, ColumnKind)> = vec![];
+        for row in rows {
+            // columns are taken in the order the row serializes its fields
+            let json = serde_json::to_string(row)?;
+            let row = serde_json::from_str::<OrderedObject>(&json).map_err(|_| {
+                serde::ser::Error::custom(format!("inline row has to be an object, found {}", json))
+            })?;
+            for (name, value) in &row.0 {
+                match columns.iter_mut().find(|(column, _)| column == name) {
+                    Some((_, kind)) => *kind = kind.merge(value),
+                    None => columns.push((name.clone(), ColumnKind::Unknown.merge(value))),
+                }
+            }
+            data.push_str(&json);
+            data.push('\n');
+        }
+        Ok(InlineIngestion {
+            data_source: data_source.to_string(),
+            data,
+            columns,
+            timestamp_spec: TimestampSpec::constant("2000-01-01T00:00:00Z"),
+            append_to_existing: false,
+        })
+    }
+
+    /// Takes row timestamps from given field instead of using a constant one.
+    pub fn timestamp(mut self, timestamp_spec: TimestampSpec) -> Self {
+        self.timestamp_spec = timestamp_spec;
+        self
+    }
+
+    /// Appends the rows instead of replacing the datasource.
+    pub fn append(mut self, append_to_existing: bool) -> Self {
+        self.append_to_existing = append_to_existing;
+        self
+    }
+
+    pub fn task(&self) -> IngestionTask {
+        let mut dimensions = vec![];
+        let mut metrics = vec![Aggregation::count("count")];
+        for (name, kind) in &self.columns {
+            if *name == self.timestamp_spec.column {
+                continue;
+            }
+            match kind {
+                ColumnKind::Long => metrics.push(Aggregation::long_sum(name, name)),
+                ColumnKind::Double => metrics.push(Aggregation::double_sum(name, name)),
+                _ => dimensions.push(DimensionSchema::Name(name.clone())),
+            }
+        }
+        let mut granularity_spec = GranularitySpec::uniform(Granularity::All, Granularity::None);
+        granularity_spec.rollup = Some(false);
+        let mut io_config = IoConfig::new(
+            TaskType::IndexParallel,
+            InputSource::Inline {
+                data: self.data.clone(),
+            },
+            InputFormat::Json {},
+        );
+        if self.append_to_existing {
+            io_config.append_to_existing = Some(true);
+        }
+        IngestionTask::index_parallel(IngestionSpec {
+            io_config,
+            tuning_config: None,
+            data_schema: DataSchema {
+                data_source: self.data_source.clone(),
+                timestamp_spec: self.timestamp_spec.clone(),
+                dimensions_spec: DimensionsSpec {
+                    dimensions,
+                    dimension_exclusions: vec![],
+                },
+                metrics_spec: metrics,
+                granularity_spec,
+                transform_spec: None,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Serialize)]
+    struct Country {
+        name: String,
+        code: String,
+        phone_code: i32,
+        area: f64,
+        languages: Vec<String>,
+    }
+
+    #[test]
+    fn test_inline_ingestion() {
+        let rows = vec![
+            Country {
+                name: "Estonia".into(),
+                code: "EE".into(),
+                phone_code: 372,
+                area: 45339.0,
+                languages: vec!["et".into()],
+            },
+            Country {
+                name: "Latvia".into(),
+                code: "LV".into(),
+                phone_code: 371,
+                area: 64589.5,
+                languages: vec!["lv".into(), "ru".into()],
+            },
+        ];
+        let task = InlineIngestion::from_rows("countries", &rows)
+            .unwrap()
+            .task();
+        let json = serde_json::to_value(&task).unwrap();
+        assert_eq!(
+            json["spec"]["dataSchema"]["dimensionsSpec"]["dimensions"],
+            json!(["name", "code", "languages"])
+        );
+        assert_eq!(
+            json["spec"]["dataSchema"]["metricsSpec"],
+            json!([
+                {"type": "count", "name": "count"},
+                {"type": "longSum", "name": "phone_code", "fieldName": "phone_code"},
+                {"type": "doubleSum", "name": "area", "fieldName": "area"}
+            ])
+        );
+        let data = json["spec"]["ioConfig"]["inputSource"]["data"]
+            .as_str()
+            .unwrap();
+        assert_eq!(data.lines().count(), 2);
+        assert!(data.starts_with(r#"{"name":"Estonia","code":"EE","phone_code":372"#));
+    }
+
+    #[test]
+    fn test_column_kind() {
+        let kind = ColumnKind::Unknown.merge(&json!(null)).merge(&json!(1));
+        assert_eq!(kind, ColumnKind::Long);
+        assert_eq!(kind.merge(&json!(1.5)), ColumnKind::Double);
+        assert_eq!(kind.merge(&json!("1")), ColumnKind::String);
+    }
+}
@@ -0,0 +1,511 @@
+//! Middleware around the queries of a [`DruidClient`](crate::client::DruidClient).
+//!
+//! Interceptors added with [`DruidClient::with_interceptor`](crate::client::DruidClient::with_interceptor)
+//! see every native and SQL query, in the order they were added. [`Interceptor::before`]
+//! can change the serialized query, its context and the http headers, or reject the query
+//! with an error. [`Interceptor::after`] observes how long the query took, the status, size and
+//! body of the response and whether it failed. Streamed SQL responses aren't kept in memory,
+//! their rows are handed to [`Interceptor::sql_row`] as they arrive instead.
+//!
+//! Without interceptors and metrics (or traces) queries are sent as they were serialized,
+//! [`OutgoingQuery`] is only made for someone to look at.
+//!
+//! ```rust
+//! use druid_io::client::{ClientResult, DruidClient};
+//! use druid_io::interceptor::{Interceptor, OutgoingQuery, QueryOutcome};
+//!
+//! // queries of this tenant run with a lower priority
+//! struct TenantPriority {
+//!     tenant: String,
+//!     priority: i64,
+//! }
+//!
+//! impl Interceptor for TenantPriority {
+//!     fn before(&self, query: &mut OutgoingQuery) -> ClientResult<()> {
+//!         query.set_context("priority", self.priority);
+//!         query.add_header("X-Tenant", &self.tenant);
+//!         Ok(())
+//!     }
+//!
+//!     fn after(&self, outcome: &QueryOutcome<'_>) {
+//!         println!("{} query took {:?}", self.tenant, outcome.elapsed);
+//!     }
+//! }
+//!
+//! let client = DruidClient::new(vec!["localhost:8082".to_string()])
+//!     .with_interceptor(TenantPriority { tenant: "reports".into(), priority: -1 });
+//! ```
+use crate::client::{ClientResult, DruidClientError};
+use crate::metrics::Metrics;
+use crate::transport::Endpoint;
+use serde_json::{Map, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A serialized query on its way to the broker.
+#[derive(Debug, Clone)]
+pub struct OutgoingQuery {
+    pub endpoint: Endpoint,
+    /// The json object posted to the endpoint.
+    pub query: Map<String, Value>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl OutgoingQuery {
+    pub(crate) fn new(endpoint: Endpoint, body: &str) -> ClientResult<Self> {
+        Ok(OutgoingQuery {
+            endpoint,
+            query: serde_json::from_str(body)
+                .map_err(|source| DruidClientError::ParsingError { source })?,
+            headers: vec![],
+        })
+    }
+
+    /// `queryType` of a native query, `None` for SQL.
+    pub fn query_type(&self) -> Option<&str> {
+        self.query.get("queryType").and_then(Value::as_str)
+    }
+
+    /// Context parameters of the query, created when the query has none.
+    pub fn context_mut(&mut self) -> &mut Map<String, Value> {
+        let context = self
+            .query
+            .entry("context")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if !context.is_object() {
+            *context = Value::Object(Map::new());
+        }
+        match context {
+            Value::Object(context) => context,
+            _ => unreachable!("context was replaced with an object"),
+        }
+    }
+
+    pub fn set_context<V: Into<Value>>(&mut self, key: &str, value: V) {
+        self.context_mut().insert(key.to_string(), value.into());
+    }
+
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+}
+
+/// A row of a streamed SQL response as it was received, before it's decoded.
+#[derive(Debug, Clone, Copy)]
+pub enum SqlRow<'a> {
+    /// A line of the `objectLines` or `arrayLines` format.
+    Line(&'a str),
+    /// The fields of a `csv` record.
+    Fields(&'a [&'a str]),
+}
+
+/// How a query went, for native queries once the response is received and parsed,
+/// for SQL queries once the response body was read to its end or failed to.
+pub struct QueryOutcome<'a> {
+    /// The query as it was sent, after all interceptors ran.
+    pub query: &'a OutgoingQuery,
+    pub elapsed: Duration,
+    /// Http status of the response, `None` when the broker wasn't reached or the query not sent.
+    pub status: Option<u16>,
+    /// Size of the response body received.
+    pub bytes: usize,
+    /// Rows of an SQL response, entries of the result array of a native one.
+    pub rows: usize,
+    /// Body of a successful response, `None` for failed queries and streamed SQL responses.
+    pub body: Option<&'a str>,
+    pub error: Option<&'a DruidClientError>,
+}
+
+pub trait Interceptor: Send + Sync {
+    /// Called before the query is sent, an error fails the query without sending it.
+    fn before(&self, _query: &mut OutgoingQuery) -> ClientResult<()> {
+        Ok(())
+    }
+
+    /// Called with every row of a streamed SQL response as it's received, header rows excepted.
+    fn sql_row(&self, _query: &OutgoingQuery, _row: SqlRow<'_>) {}
+
+    /// Called after the query completed or failed, including queries rejected by `before`.
+    fn after(&self, _outcome: &QueryOutcome<'_>) {}
+}
+
+// endpoint, body and headers of the request sent for a query
+type Request = (Endpoint, String, Vec<(String, String)>);
+
+/// Tracks a query while it runs and reports its outcome to the interceptors and metrics, once.
+pub(crate) struct QueryObserver {
+    /// The parsed query, `None` when there's nobody to look at it.
+    pub(crate) query: Option<OutgoingQuery>,
+    endpoint: Endpoint,
+    // the query as serialized by the client, sent as is when it wasn't parsed
+    body: String,
+    pub(crate) status: Option<u16>,
+    pub(crate) rows: usize,
+    started: Instant,
+    received: Arc<AtomicUsize>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    metrics: Option<Arc<dyn Metrics>>,
+    finished: bool,
+}
+
+impl QueryObserver {
+    pub(crate) fn new(
+        endpoint: Endpoint,
+        body: String,
+        interceptors: Vec<Arc<dyn Interceptor>>,
+        metrics: Option<Arc<dyn Metrics>>,
+    ) -> ClientResult<Self> {
+        let observed = !interceptors.is_empty() || metrics.is_some() || cfg!(feature = "tracing");
+        let query = if observed {
+            Some(OutgoingQuery::new(endpoint, &body)?)
+        } else {
+            None
+        };
+        Ok(QueryObserver {
+            query,
+            endpoint,
+            body,
+            status: None,
+            rows: 0,
+            started: Instant::now(),
+            received: Arc::new(AtomicUsize::new(0)),
+            interceptors,
+            metrics,
+            finished: false,
+        })
+    }
+
+    /// Endpoint, body and headers of the request, once.
+    pub(crate) fn request(&mut self) -> ClientResult<Request> {
+        match &self.query {
+            Some(query) => {
+                let body = serde_json::to_string(&query.query)
+                    .map_err(|source| DruidClientError::ParsingError { source })?;
+                Ok((query.endpoint, body, query.headers.clone()))
+            }
+            None => Ok((self.endpoint, std::mem::take(&mut self.body), vec![])),
+        }
+    }
+
+    /// Whether any interceptor is handed the rows of streamed SQL responses.
+    pub(crate) fn sees_rows(&self) -> bool {
+        !self.interceptors.is_empty()
+    }
+
+    pub(crate) fn row(&self, row: SqlRow<'_>) {
+        if let Some(query) = &self.query {
+            for interceptor in &self.interceptors {
+                interceptor.sql_row(query, row);
+            }
+        }
+    }
+
+    /// Counter of the response bytes, added to while the body is received.
+    pub(crate) fn received(&self) -> Arc<AtomicUsize> {
+        self.received.clone()
+    }
+
+    pub(crate) fn finish(&mut self, body: Option<&str>, error: Option<&DruidClientError>) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        let query = match &self.query {
+            Some(query) => query,
+            None => return,
+        };
+        let outcome = QueryOutcome {
+            query,
+            elapsed: self.started.elapsed(),
+            status: self.status,
+            bytes: self.received.load(Ordering::Relaxed),
+            rows: self.rows,
+            body,
+            error,
+        };
+        for interceptor in &self.interceptors {
+            interceptor.after(&outcome);
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.query(&outcome);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::DruidClient;
+    use crate::query::sql::{SqlQuery, SqlResultFormat};
+    use crate::transport::stub::StubTransport;
+    use crate::transport::TransportRequest;
+    use futures::StreamExt;
+    use serde::Deserialize;
+    use std::sync::{Arc, Mutex};
+
+    fn broker() -> Arc<StubTransport> {
+        let broken =
+            |request: &TransportRequest| request.body.as_deref().unwrap_or("").contains("broken");
+        Arc::new(
+            StubTransport::new()
+                .on(broken, 500, r#"{"error": "Unknown exception"}"#)
+                .otherwise(200, r#"[{"page": "Rust"}]"#),
+        )
+    }
+
+    struct Priority;
+
+    impl Interceptor for Priority {
+        fn before(&self, query: &mut OutgoingQuery) -> ClientResult<()> {
+            query.set_context("priority", 10);
+            query.add_header("X-Tenant", "reports");
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Audit {
+        log: Mutex<Vec<(Value, bool)>>,
+    }
+
+    impl Interceptor for Arc<Audit> {
+        fn after(&self, outcome: &QueryOutcome<'_>) {
+            let query = Value::Object(outcome.query.query.clone());
+            self.log
+                .lock()
+                .unwrap()
+                .push((query, outcome.error.is_some()));
+        }
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Page {
+        page: String,
+    }
+
+    #[test]
+    fn test_interceptors() {
+        let broker = broker();
+        let audit = Arc::new(Audit::default());
+        let client = DruidClient::new(vec!["localhost:8082".into()])
+            .with_transport(broker.clone())
+            .with_interceptor(Priority)
+            .with_interceptor(audit.clone());
+
+        let query = SqlQuery::new("SELECT page FROM wikipedia");
+        let pages = tokio_test::block_on(client.sql::<Page>(&query)).unwrap();
+        assert_eq!(pages.rows[0].page, "Rust");
+        let broken = SqlQuery::new("SELECT broken FROM wikipedia");
+        assert!(tokio_test::block_on(client.sql::<Page>(&broken)).is_err());
+
+        let requests = broker.requests();
+        let body: Value = serde_json::from_str(requests[0].body.as_deref().unwrap()).unwrap();
+        assert_eq!(body["context"]["priority"], 10);
+        assert!(requests[0]
+            .headers
+            .contains(&("X-Tenant".to_string(), "reports".to_string())));
+
+        let log = audit.log.lock().unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].0["query"], "SELECT page FROM wikipedia");
+        assert_eq!(log[0].0["context"]["priority"], 10);
+        assert_eq!((log[0].1, log[1].1), (false, true));
+    }
+
+    struct ReadOnly;
+
+    impl Interceptor for ReadOnly {
+        fn before(&self, query: &mut OutgoingQuery) -> ClientResult<()> {
+            let sql = query.query.get("query").and_then(Value::as_str);
+            match sql {
+                Some(sql) if sql.starts_with("DELETE") => Err(DruidClientError::ServerError {
+                    response: "read only".into(),
+                }),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejected_query() {
+        let broker = broker();
+        let client = DruidClient::new(vec!["localhost:8082".into()])
+            .with_transport(broker.clone())
+            .with_interceptor(ReadOnly);
+        let query = SqlQuery::new("DELETE FROM wikipedia");
+        assert!(tokio_test::block_on(client.sql::<Page>(&query)).is_err());
+        assert!(broker.requests().is_empty());
+    }
+
+    // status, bytes, rows and whether the query failed
+    type Summary = (Option<u16>, usize, usize, bool);
+
+    #[derive(Default)]
+    struct Summaries {
+        log: Mutex<Vec<Summary>>,
+    }
+
+    impl Interceptor for Arc<Summaries> {
+        fn after(&self, outcome: &QueryOutcome<'_>) {
+            self.log.lock().unwrap().push((
+                outcome.status,
+                outcome.bytes,
+                outcome.rows,
+                outcome.error.is_some(),
+            ));
+        }
+    }
+
+    #[test]
+    fn test_sql_outcome_after_body() {
+        const LINES: &str = "{\"page\": \"Rust\"}\n{\"page\": \"Druid\"}\n";
+        let summaries = Arc::new(Summaries::default());
+        let client = DruidClient::new(vec!["localhost:8082".into()])
+            .with_transport(broker())
+            .with_interceptor(summaries.clone());
+        let query = SqlQuery::new("SELECT page FROM wikipedia");
+        tokio_test::block_on(client.sql::<Page>(&query)).unwrap();
+        let broken = SqlQuery::new("SELECT broken FROM wikipedia");
+        assert!(tokio_test::block_on(client.sql::<Page>(&broken)).is_err());
+        assert_eq!(
+            *summaries.log.lock().unwrap(),
+            vec![
+                (Some(200), r#"[{"page": "Rust"}]"#.len(), 1, false),
+                (
+                    Some(500),
+                    r#"{"error": "Unknown exception"}"#.len(),
+                    0,
+                    true
+                ),
+            ]
+        );
+
+        let summaries = Arc::new(Summaries::default());
+        let client = DruidClient::new(vec!["localhost:8082".into()])
+            .with_transport(StubTransport::new().otherwise(200, LINES))
+            .with_interceptor(summaries.clone());
+        let query = query.result_format(SqlResultFormat::ObjectLines);
+        let pages: Vec<ClientResult<Page>> = tokio_test::block_on(async {
+            let stream = client.sql_stream::<Page>(&query).await.unwrap();
+            assert!(summaries.log.lock().unwrap().is_empty());
+            stream.rows.collect().await
+        });
+        assert_eq!(pages.len(), 2);
+        // a stream dropped early reports the rows read until then
+        tokio_test::block_on(async {
+            let mut stream = client.sql_stream::<Page>(&query).await.unwrap();
+            stream.rows.next().await.unwrap().unwrap();
+        });
+        assert_eq!(
+            *summaries.log.lock().unwrap(),
+            vec![
+                (Some(200), LINES.len(), 2, false),
+                (Some(200), LINES.len(), 1, false)
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct Bodies {
+        bodies: Mutex<Vec<Option<String>>>,
+        rows: Mutex<Vec<String>>,
+    }
+
+    impl Interceptor for Arc<Bodies> {
+        fn sql_row(&self, _query: &OutgoingQuery, row: SqlRow<'_>) {
+            let row = match row {
+                SqlRow::Line(line) => line.to_string(),
+                SqlRow::Fields(fields) => fields.join("|"),
+            };
+            self.rows.lock().unwrap().push(row);
+        }
+
+        fn after(&self, outcome: &QueryOutcome<'_>) {
+            let body = outcome.body.map(String::from);
+            self.bodies.lock().unwrap().push(body);
+        }
+    }
+
+    #[test]
+    fn test_outcome_body() {
+        let bodies = Arc::new(Bodies::default());
+        let client = DruidClient::new(vec!["localhost:8082".into()])
+            .with_transport(broker())
+            .with_interceptor(bodies.clone());
+        let query = SqlQuery::new("SELECT page FROM wikipedia");
+        tokio_test::block_on(client.sql::<Page>(&query)).unwrap();
+        let broken = SqlQuery::new("SELECT broken FROM wikipedia");
+        assert!(tokio_test::block_on(client.sql::<Page>(&broken)).is_err());
+        let scan = crate::query::scan::Scan {
+            data_source: crate::query::DataSource::table("wikipedia"),
+            intervals: vec!["2015-09-12/2015-09-13".into()],
+            result_format: crate::query::scan::ResultFormat::List,
+            filter: None,
+            columns: vec![],
+            batch_size: 10,
+            limit: None,
+            ordering: None,
+            context: Default::default(),
+        };
+        let scan = crate::query::Query::Scan(scan);
+        tokio_test::block_on(client.query::<Value>(&scan)).unwrap();
+        assert_eq!(
+            *bodies.bodies.lock().unwrap(),
+            vec![
+                Some(r#"[{"page": "Rust"}]"#.to_string()),
+                None,
+                Some(r#"[{"page": "Rust"}]"#.to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_streamed_rows() {
+        let lines = "{\"page\": \"Rust\"}\n{\"page\": \"Druid\"}\n";
+        let csv = "page\nRust\n\"Druid, Apache\"\n";
+        for (format, body, rows) in [
+            (
+                SqlResultFormat::ObjectLines,
+                lines,
+                vec![r#"{"page": "Rust"}"#, r#"{"page": "Druid"}"#],
+            ),
+            (SqlResultFormat::Csv, csv, vec!["Rust", "Druid, Apache"]),
+        ] {
+            let bodies = Arc::new(Bodies::default());
+            let client = DruidClient::new(vec!["localhost:8082".into()])
+                .with_transport(StubTransport::new().otherwise(200, body))
+                .with_interceptor(bodies.clone());
+            let query = SqlQuery::new("SELECT page FROM wikipedia")
+                .result_format(format)
+                .header(format == SqlResultFormat::Csv);
+            let pages: Vec<ClientResult<Page>> = tokio_test::block_on(async {
+                let stream = client.sql_stream::<Page>(&query).await.unwrap();
+                stream.rows.collect().await
+            });
+            assert_eq!(pages.len(), 2);
+            assert_eq!(*bodies.rows.lock().unwrap(), rows);
+            // the body of a stream isn't kept
+            assert_eq!(*bodies.bodies.lock().unwrap(), vec![None]);
+        }
+    }
+
+    #[test]
+    fn test_unobserved_query() {
+        let body = r#"{"queryType":"scan","b":1,"a":2}"#;
+        let mut observer = QueryObserver::new(Endpoint::Native, body.into(), vec![], None).unwrap();
+        if !cfg!(feature = "tracing") {
+            // sent as serialized without parsing it
+            assert!(observer.query.is_none());
+            assert_eq!(observer.request().unwrap().1, body);
+        }
+        let observer = QueryObserver::new(
+            Endpoint::Native,
+            body.into(),
+            vec![Arc::new(Priority)],
+            None,
+        )
+        .unwrap();
+        assert_eq!(observer.query.unwrap().query_type(), Some("scan"));
+    }
+}
@@ -23,6 +23,8 @@
 //! * SegmentMetadata
 //! * DataSourceMetadata
 //!
+//! As well as [Druid SQL](https://druid.apache.org/docs/latest/querying/sql.html) queries.
+//!
 //! ## Usage
 //!
 //! ### Client
@@ -98,10 +100,38 @@
 
 extern crate serde_json;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod client;
 pub mod connection;
+pub mod coordinator;
+#[cfg(feature = "polars")]
+pub mod dataframe;
+pub mod deletion;
+pub mod export;
+pub mod ingestion;
+pub mod interceptor;
+pub mod lookup;
+pub mod metrics;
+pub mod overlord;
 pub mod query;
+pub mod retention;
+pub mod schema;
 pub mod serialization;
+pub mod supervisor;
+pub mod system;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod time;
+#[cfg(feature = "tracing")]
+pub mod trace;
+pub mod transport;
+pub mod typed;
+
+// derived code refers to the crate by name
+extern crate self as druid_io;
+pub use druid_io_derive::DruidResult;
+pub use typed::DruidResult;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,219 @@
+//! Typed specs of [lookups](https://druid.apache.org/docs/latest/querying/lookups.html), created through
+//! the [`CoordinatorClient`](crate::coordinator::CoordinatorClient) and referenced in queries by
+//! `DataSource::Lookup`, `Dimension::Lookup` or `ExtractFN::RegisteredLookup`.
+//!
+//! ```no_run
+//! use druid_io::coordinator::CoordinatorClient;
+//! use druid_io::lookup::{LookupSpec, DEFAULT_LOOKUP_TIER};
+//! use std::collections::HashMap;
+//!
+//! # async fn example() {
+//! let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+//! let mut map = HashMap::new();
+//! map.insert("EE".to_string(), "Estonia".to_string());
+//! coordinator
+//!     .update_lookup(DEFAULT_LOOKUP_TIER, "country_names", &LookupSpec::map("v1", map))
+//!     .await
+//!     .unwrap();
+//! let status = coordinator.lookup_status(DEFAULT_LOOKUP_TIER, "country_names").await.unwrap();
+//! # }
+//! ```
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Tier of the nodes without `druid.lookup.lookupTier` configured.
+pub const DEFAULT_LOOKUP_TIER: &str = "__default";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupSpec {
+    /// Updates are applied only when the version is greater than the current one (compared as strings)
+    pub version: String,
+    pub lookup_extractor_factory: LookupExtractorFactory,
+}
+
+impl LookupSpec {
+    pub fn map(version: &str, map: HashMap<String, String>) -> Self {
+        LookupSpec {
+            version: version.to_string(),
+            lookup_extractor_factory: LookupExtractorFactory::Map {
+                map,
+                injective: None,
+            },
+        }
+    }
+
+    pub fn jdbc(version: &str, namespace: JdbcNamespace) -> Self {
+        LookupSpec {
+            version: version.to_string(),
+            lookup_extractor_factory: LookupExtractorFactory::CachedNamespace {
+                extraction_namespace: Box::new(ExtractionNamespace::Jdbc(namespace)),
+                first_cache_timeout: None,
+                injective: None,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum LookupExtractorFactory {
+    #[serde(rename = "map")]
+    Map {
+        map: HashMap<String, String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        injective: Option<bool>,
+    },
+    #[serde(rename = "cachedNamespace", rename_all = "camelCase")]
+    CachedNamespace {
+        extraction_namespace: Box<ExtractionNamespace>,
+        /// Milliseconds to wait for the first load, `0` doesn't wait
+        #[serde(skip_serializing_if = "Option::is_none")]
+        first_cache_timeout: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        injective: Option<bool>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ExtractionNamespace {
+    Jdbc(JdbcNamespace),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JdbcNamespace {
+    pub connector_config: JdbcConnectorConfig,
+    pub table: String,
+    pub key_column: String,
+    pub value_column: String,
+    /// Column with last update time, enables incremental reloads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ts_column: Option<String>,
+    /// SQL condition limiting the loaded rows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// ISO 8601 period, e.g. `PT10M`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll_period: Option<String>,
+}
+
+impl JdbcNamespace {
+    pub fn new(
+        connector_config: JdbcConnectorConfig,
+        table: &str,
+        key_column: &str,
+        value_column: &str,
+    ) -> Self {
+        JdbcNamespace {
+            connector_config,
+            table: table.to_string(),
+            key_column: key_column.to_string(),
+            value_column: value_column.to_string(),
+            ts_column: None,
+            filter: None,
+            poll_period: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JdbcConnectorConfig {
+    #[serde(rename = "connectURI")]
+    pub connect_uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Plain password or a password provider spec
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupStatus {
+    pub loaded: bool,
+    #[serde(default)]
+    pub pending_nodes: Vec<String>,
+}
+
+/// Lookups of a single node.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupNodeState {
+    #[serde(default)]
+    pub current: HashMap<String, LookupSpec>,
+    #[serde(default)]
+    pub to_load: HashMap<String, LookupSpec>,
+    #[serde(default)]
+    pub to_drop: Vec<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize_jdbc_lookup() {
+        let mut namespace = JdbcNamespace::new(
+            JdbcConnectorConfig {
+                connect_uri: "jdbc:postgresql://localhost:5432/druid".into(),
+                user: Some("druid".into()),
+                password: Some(json!("diurd")),
+            },
+            "countries",
+            "code",
+            "name",
+        );
+        namespace.poll_period = Some("PT10M".into());
+        let spec = LookupSpec::jdbc("v1", namespace);
+        let expected = json!({
+            "version": "v1",
+            "lookupExtractorFactory": {
+                "type": "cachedNamespace",
+                "extractionNamespace": {
+                    "type": "jdbc",
+                    "connectorConfig": {
+                        "connectURI": "jdbc:postgresql://localhost:5432/druid",
+                        "user": "druid",
+                        "password": "diurd"
+                    },
+                    "table": "countries",
+                    "keyColumn": "code",
+                    "valueColumn": "name",
+                    "pollPeriod": "PT10M"
+                }
+            }
+        });
+        assert_eq!(serde_json::to_value(&spec).unwrap(), expected);
+        assert_eq!(
+            serde_json::from_value::<LookupSpec>(expected).unwrap(),
+            spec
+        );
+    }
+
+    #[test]
+    fn test_deserialize_node_state() {
+        let str = r#"
+            {
+                "localhost:8083": {
+                    "current": {
+                        "country_names": {
+                            "version": "v1",
+                            "lookupExtractorFactory": {"type": "map", "map": {"EE": "Estonia"}}
+                        }
+                    },
+                    "toLoad": {},
+                    "toDrop": []
+                }
+            }
+        "#;
+        let state = serde_json::from_str::<HashMap<String, LookupNodeState>>(str).unwrap();
+        let current = &state["localhost:8083"].current["country_names"];
+        assert!(matches!(
+            &current.lookup_extractor_factory,
+            LookupExtractorFactory::Map { map, .. } if map["EE"] == "Estonia"
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+//! Client side metrics of the queries sent to Druid.
+//!
+//! A [`Metrics`] added with [`DruidClient::with_metrics`](crate::client::DruidClient::with_metrics)
+//! is told about every native and SQL query (count, latency, error kind), the bytes received
+//! from the brokers, and by the [`BrokersPool`](crate::connection::BrokersPool) about the broker
+//! each query went to and whether it answered. A request the broker couldn't be reached for, or
+//! answered with `502 Bad Gateway` or `503 Service Unavailable`, counts against its health.
+//! Metrics added to the [`CoordinatorClient`](crate::coordinator::CoordinatorClient) or the
+//! [`OverlordClient`](crate::overlord::OverlordClient) are told the same about their nodes.
+//!
+//! With the `metrics` feature `MetricsFacade` reports them through the
+//! [`metrics`](https://docs.rs/metrics) crate, to be exported by any of its recorders, e.g. to
+//! Prometheus.
+//!
+//! ```rust
+//! use druid_io::client::DruidClient;
+//! use druid_io::interceptor::QueryOutcome;
+//! use druid_io::metrics::Metrics;
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//!
+//! #[derive(Default)]
+//! struct Failures(AtomicU64);
+//!
+//! impl Metrics for Failures {
+//!     fn query(&self, outcome: &QueryOutcome<'_>) {
+//!         if outcome.error.is_some() {
+//!             self.0.fetch_add(1, Ordering::Relaxed);
+//!         }
+//!     }
+//! }
+//!
+//! let client = DruidClient::new(vec!["localhost:8082".to_string()]).with_metrics(Failures::default());
+//! ```
+use crate::interceptor::QueryOutcome;
+use std::sync::Arc;
+
+/// Receives measurements of a client, every method does nothing by default.
+pub trait Metrics: Send + Sync {
+    /// A query completed or failed, including queries rejected by an interceptor.
+    fn query(&self, _outcome: &QueryOutcome<'_>) {}
+
+    /// A chunk of a response body was received from the broker.
+    fn bytes_received(&self, _broker: &str, _bytes: usize) {}
+
+    /// The pool picked the broker to send a query to.
+    fn broker_selected(&self, _broker: &str) {}
+
+    /// The broker answered a request (`healthy`), or was unreachable or unavailable.
+    fn broker_health(&self, _broker: &str, _healthy: bool) {}
+}
+
+/// Shares the metrics with the code reading them.
+impl<T: Metrics + ?Sized> Metrics for Arc<T> {
+    fn query(&self, outcome: &QueryOutcome<'_>) {
+        (**self).query(outcome)
+    }
+
+    fn bytes_received(&self, broker: &str, bytes: usize) {
+        (**self).bytes_received(broker, bytes)
+    }
+
+    fn broker_selected(&self, broker: &str) {
+        (**self).broker_selected(broker)
+    }
+
+    fn broker_health(&self, broker: &str, healthy: bool) {
+        (**self).broker_health(broker, healthy)
+    }
+}
+
+/// Reports to the recorder installed for the `metrics` crate:
+///
+/// * `druid_queries_total` counter, by `endpoint` (`native`, `sql`) and `query_type` (`sql` for SQL)
+/// * `druid_query_duration_seconds` histogram, by `endpoint` and `query_type`
+/// * `druid_query_errors_total` counter, by `endpoint`, `query_type` and `kind` of the error
+/// * `druid_response_bytes_total` counter, by `broker`
+/// * `druid_broker_requests_total` counter, by `broker`
+/// * `druid_broker_failures_total` counter, by `broker`
+/// * `druid_broker_up` gauge, by `broker`, `0` after a failed request and `1` after an answered one
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MetricsFacade;
+
+#[cfg(feature = "metrics")]
+impl Metrics for MetricsFacade {
+    fn query(&self, outcome: &QueryOutcome<'_>) {
+        let endpoint = match outcome.query.endpoint {
+            crate::transport::Endpoint::Native => "native",
+            crate::transport::Endpoint::Sql => "sql",
+        };
+        let query_type = outcome.query.query_type().unwrap_or("sql").to_string();
+        let labels = [
+            ("endpoint", endpoint.to_string()),
+            ("query_type", query_type),
+        ];
+        ::metrics::counter!("druid_queries_total", &labels).increment(1);
+        ::metrics::histogram!("druid_query_duration_seconds", &labels)
+            .record(outcome.elapsed.as_secs_f64());
+        if let Some(error) = outcome.error {
+            let mut labels = labels.to_vec();
+            labels.push(("kind", error.kind().to_string()));
+            ::metrics::counter!("druid_query_errors_total", &labels).increment(1);
+        }
+    }
+
+    fn bytes_received(&self, broker: &str, bytes: usize) {
+        ::metrics::counter!("druid_response_bytes_total", "broker" => broker.to_string())
+            .increment(bytes as u64);
+    }
+
+    fn broker_selected(&self, broker: &str) {
+        ::metrics::counter!("druid_broker_requests_total", "broker" => broker.to_string())
+            .increment(1);
+    }
+
+    fn broker_health(&self, broker: &str, healthy: bool) {
+        if !healthy {
+            ::metrics::counter!("druid_broker_failures_total", "broker" => broker.to_string())
+                .increment(1);
+        }
+        let up = if healthy { 1.0 } else { 0.0 };
+        ::metrics::gauge!("druid_broker_up", "broker" => broker.to_string()).set(up);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{DruidClient, DruidClientError};
+    use crate::coordinator::CoordinatorClient;
+    use crate::query::sql::SqlQuery;
+    use crate::transport::stub::StubTransport;
+    use crate::transport::TransportRequest;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        queries: Mutex<Vec<(Option<String>, Option<&'static str>)>>,
+        bytes: Mutex<usize>,
+        brokers: Mutex<Vec<(String, bool)>>,
+    }
+
+    impl Metrics for Recorder {
+        fn query(&self, outcome: &QueryOutcome<'_>) {
+            let kind = outcome.error.map(DruidClientError::kind);
+            let query_type = outcome.query.query_type().map(str::to_string);
+            self.queries.lock().unwrap().push((query_type, kind));
+        }
+
+        fn bytes_received(&self, _broker: &str, bytes: usize) {
+            *self.bytes.lock().unwrap() += bytes;
+        }
+
+        fn broker_health(&self, broker: &str, healthy: bool) {
+            self.brokers
+                .lock()
+                .unwrap()
+                .push((broker.to_string(), healthy));
+        }
+    }
+
+    // the first broker is down
+    fn brokers() -> StubTransport {
+        let down = |request: &TransportRequest| request.node == "broker-1:8082";
+        let broken =
+            |request: &TransportRequest| request.body.as_deref().unwrap_or("").contains("broken");
+        StubTransport::new()
+            .on(down, 503, "")
+            .on(broken, 500, r#"{"error": "Query timeout"}"#)
+            .otherwise(200, r#"[{"page": "Rust"}]"#)
+    }
+
+    #[test]
+    fn test_service_metrics() {
+        let recorder = Arc::new(Recorder::default());
+        let coordinator =
+            CoordinatorClient::new(vec!["broker-1:8082".into(), "broker-2:8082".into()])
+                .with_transport(brokers())
+                .with_metrics(recorder.clone());
+        for _ in 0..3 {
+            let _ = tokio_test::block_on(coordinator.datasources());
+        }
+
+        let brokers = recorder.brokers.lock().unwrap();
+        let health: Vec<bool> = brokers.iter().map(|(_, healthy)| *healthy).collect();
+        assert_eq!(health, vec![false, true, false]);
+        assert_eq!(brokers[1].0, "broker-2:8082");
+        assert!(recorder.queries.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_client_metrics() {
+        let recorder = Arc::new(Recorder::default());
+        let client = DruidClient::new(vec!["broker-1:8082".into(), "broker-2:8082".into()])
+            .with_transport(brokers())
+            .with_metrics(recorder.clone());
+
+        let query = SqlQuery::new("SELECT page FROM wikipedia");
+        let broken = SqlQuery::new("SELECT broken FROM wikipedia");
+        for query in &[&query, &query, &query, &broken] {
+            let _ = tokio_test::block_on(client.sql::<serde_json::Value>(query));
+        }
+
+        let queries = recorder.queries.lock().unwrap();
+        assert_eq!(
+            *queries,
+            vec![
+                (None, Some("server")),
+                (None, None),
+                (None, Some("server")),
+                (None, Some("query_timeout")),
+            ]
+        );
+        assert_eq!(
+            *recorder.bytes.lock().unwrap(),
+            r#"[{"page": "Rust"}]"#.len() + r#"{"error": "Query timeout"}"#.len()
+        );
+        // failed queries don't count against a broker that answered them
+        let brokers = recorder.brokers.lock().unwrap();
+        let health: Vec<bool> = brokers.iter().map(|(_, healthy)| *healthy).collect();
+        assert_eq!(health, vec![false, true, false, true]);
+        assert_eq!(brokers[0].0, "broker-1:8082");
+    }
+}
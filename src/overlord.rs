@@ -0,0 +1,836 @@
+//! Client for the [Overlord task API](https://druid.apache.org/docs/latest/operations/api-reference.html#overlord):
+//! submitting ingestion tasks and following their status, reports and logs.
+//!
+//! ```no_run
+//! use druid_io::overlord::{OverlordClient, WaitOptions};
+//! # use druid_io::ingestion::IngestionTask;
+//!
+//! # async fn example(task: IngestionTask) {
+//! let overlord = OverlordClient::new(vec!["localhost:8090".to_string()]);
+//! let task_id = overlord.submit_task(&task).await.unwrap();
+//! let status = overlord.task_status(&task_id).await.unwrap();
+//!
+//! // or block until the task is done and its segments are queryable
+//! # let coordinator = druid_io::coordinator::CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+//! let completion = overlord
+//!     .wait_for_task(&task_id, &coordinator, WaitOptions::default())
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use crate::client::{ClientResult, DruidClientError};
+use crate::connection::{path_segment, query_component, ServiceConnection};
+use crate::coordinator::CoordinatorClient;
+use crate::deletion::{DeletionKind, DeletionPlan, KillTask};
+use crate::ingestion::InlineIngestion;
+use crate::metrics::Metrics;
+use crate::query::definitions::ETERNITY;
+use crate::supervisor::{
+    SupervisorHealth, SupervisorIdResponse, SupervisorLag, SupervisorSpec, SupervisorStatus,
+};
+use crate::transport::Transport;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub struct OverlordClient {
+    connection: ServiceConnection,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TaskState {
+    Running,
+    Pending,
+    Waiting,
+    Complete,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TaskStatusCode {
+    Running,
+    Success,
+    Failed,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskLocation {
+    pub host: Option<String>,
+    pub port: i32,
+    pub tls_port: i32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskStatus {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub task_type: Option<String>,
+    pub created_time: Option<String>,
+    pub status_code: Option<TaskStatusCode>,
+    pub status: Option<TaskStatusCode>,
+    pub runner_status_code: Option<String>,
+    /// Milliseconds, `-1` while the task is running
+    pub duration: Option<i64>,
+    pub location: Option<TaskLocation>,
+    pub data_source: Option<String>,
+    pub error_msg: Option<String>,
+}
+
+impl TaskStatus {
+    pub fn code(&self) -> Option<TaskStatusCode> {
+        self.status_code.or(self.status)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TaskStatusResponse {
+    status: TaskStatus,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TaskIdResponse {
+    task: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskReport {
+    pub ingestion_stats_and_errors: Option<IngestionStatsAndErrors>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestionStatsAndErrors {
+    pub task_id: String,
+    pub payload: IngestionStatsPayload,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestionStatsPayload {
+    pub ingestion_state: Option<String>,
+    /// Row counters per ingestion phase, e.g. `determinePartitions`, `buildSegments`
+    #[serde(default)]
+    pub row_stats: HashMap<String, RowStats>,
+    pub error_msg: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RowStats {
+    pub processed: u64,
+    pub processed_bytes: u64,
+    pub processed_with_error: u64,
+    pub thrown_away: u64,
+    pub unparseable: u64,
+}
+
+impl OverlordClient {
+    pub fn new(nodes: Vec<String>) -> Self {
+        OverlordClient {
+            connection: ServiceConnection::new(nodes),
+        }
+    }
+
+    /// Sends requests through the transport instead of the default `ReqwestTransport`.
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.connection = self.connection.with_transport(transport);
+        self
+    }
+
+    /// Reports the node each request went to and whether it answered to the metrics.
+    pub fn with_metrics<M: Metrics + 'static>(mut self, metrics: M) -> Self {
+        self.connection = self.connection.with_metrics(Arc::new(metrics));
+        self
+    }
+
+    /// Submits the task and returns its id.
+    pub async fn submit_task<T: Serialize>(&self, task: &T) -> ClientResult<String> {
+        let response: TaskIdResponse = self.connection.post("/druid/indexer/v1/task", task).await?;
+        Ok(response.task)
+    }
+
+    /// Tasks of the cluster, optionally only those in given state and of given datasource.
+    pub async fn tasks(
+        &self,
+        state: Option<TaskState>,
+        data_source: Option<&str>,
+    ) -> ClientResult<Vec<TaskStatus>> {
+        let mut filters = vec![];
+        if let Some(state) = state {
+            let state = serde_json::to_value(state).unwrap_or_default();
+            filters.push(format!(
+                "state={}",
+                state.as_str().unwrap_or_default().to_lowercase()
+            ));
+        }
+        if let Some(data_source) = data_source {
+            filters.push(format!("datasource={}", query_component(data_source)));
+        }
+        let mut path = "/druid/indexer/v1/tasks".to_string();
+        if !filters.is_empty() {
+            path = format!("{}?{}", path, filters.join("&"));
+        }
+        self.connection.get(&path).await
+    }
+
+    pub async fn task_status(&self, task_id: &str) -> ClientResult<TaskStatus> {
+        let response: TaskStatusResponse = self
+            .connection
+            .get(&format!(
+                "/druid/indexer/v1/task/{}/status",
+                path_segment(task_id)
+            ))
+            .await?;
+        Ok(response.status)
+    }
+
+    pub async fn task_report(&self, task_id: &str) -> ClientResult<TaskReport> {
+        self.connection
+            .get(&format!(
+                "/druid/indexer/v1/task/{}/reports",
+                path_segment(task_id)
+            ))
+            .await
+    }
+
+    // intervals of the task's granularity spec, empty when the spec has none
+    async fn task_intervals(&self, task_id: &str) -> Vec<String> {
+        let task: ClientResult<serde_json::Value> = self
+            .connection
+            .get(&format!("/druid/indexer/v1/task/{}", path_segment(task_id)))
+            .await;
+        task.ok()
+            .and_then(|task| {
+                let intervals = task.pointer("/payload/spec/dataSchema/granularitySpec/intervals");
+                serde_json::from_value(intervals?.clone()).ok()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Task log, negative offset reads only the last bytes of it.
+    pub async fn task_log(&self, task_id: &str, offset: Option<i64>) -> ClientResult<String> {
+        let mut path = format!("/druid/indexer/v1/task/{}/log", path_segment(task_id));
+        if let Some(offset) = offset {
+            path = format!("{}?offset={}", path, offset);
+        }
+        self.connection.get_text(&path).await
+    }
+
+    pub async fn shutdown_task(&self, task_id: &str) -> ClientResult<()> {
+        let _: TaskIdResponse = self
+            .connection
+            .post(
+                &format!("/druid/indexer/v1/task/{}/shutdown", path_segment(task_id)),
+                &(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+impl OverlordClient {
+    /// Creates the supervisor or updates the spec of an existing one, returns the supervisor id.
+    pub async fn submit_supervisor(&self, spec: &SupervisorSpec) -> ClientResult<String> {
+        let response: SupervisorIdResponse = self
+            .connection
+            .post("/druid/indexer/v1/supervisor", spec)
+            .await?;
+        Ok(response.id)
+    }
+
+    /// Ids of the active supervisors.
+    pub async fn supervisors(&self) -> ClientResult<Vec<String>> {
+        self.connection.get("/druid/indexer/v1/supervisor").await
+    }
+
+    pub async fn supervisor_spec(&self, supervisor_id: &str) -> ClientResult<SupervisorSpec> {
+        self.connection
+            .get(&supervisor_path(supervisor_id, ""))
+            .await
+    }
+
+    pub async fn supervisor_status(&self, supervisor_id: &str) -> ClientResult<SupervisorStatus> {
+        self.connection
+            .get(&supervisor_path(supervisor_id, "/status"))
+            .await
+    }
+
+    pub async fn supervisor_health(&self, supervisor_id: &str) -> ClientResult<bool> {
+        let path = supervisor_path(supervisor_id, "/health");
+        match self.connection.get::<SupervisorHealth>(&path).await {
+            Ok(health) => Ok(health.healthy),
+            // unhealthy supervisor is reported with 503 status
+            Err(DruidClientError::ServerError { response }) => {
+                match serde_json::from_str::<SupervisorHealth>(&response) {
+                    Ok(health) => Ok(health.healthy),
+                    Err(_) => Err(DruidClientError::ServerError { response }),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn supervisor_lag(&self, supervisor_id: &str) -> ClientResult<SupervisorLag> {
+        let status = self.supervisor_status(supervisor_id).await?;
+        Ok(status.payload.lag())
+    }
+
+    pub async fn suspend_supervisor(&self, supervisor_id: &str) -> ClientResult<()> {
+        let _: serde_json::Value = self
+            .connection
+            .post(&supervisor_path(supervisor_id, "/suspend"), &())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn resume_supervisor(&self, supervisor_id: &str) -> ClientResult<()> {
+        let _: serde_json::Value = self
+            .connection
+            .post(&supervisor_path(supervisor_id, "/resume"), &())
+            .await?;
+        Ok(())
+    }
+
+    /// Clears stored offsets, the supervisor restarts reading from the earliest or latest
+    /// offset depending on `useEarliestOffset`. Can cause data to be skipped or read twice.
+    pub async fn reset_supervisor(&self, supervisor_id: &str) -> ClientResult<()> {
+        let _: SupervisorIdResponse = self
+            .connection
+            .post(&supervisor_path(supervisor_id, "/reset"), &())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn terminate_supervisor(&self, supervisor_id: &str) -> ClientResult<()> {
+        let _: SupervisorIdResponse = self
+            .connection
+            .post(&supervisor_path(supervisor_id, "/terminate"), &())
+            .await?;
+        Ok(())
+    }
+}
+
+fn supervisor_path(supervisor_id: &str, action: &str) -> String {
+    format!(
+        "/druid/indexer/v1/supervisor/{}{}",
+        path_segment(supervisor_id),
+        action
+    )
+}
+
+/// Polling schedule of [`OverlordClient::wait_for_task`]: the poll interval starts at
+/// `poll_interval` and grows by `backoff_factor` up to `max_poll_interval`.
+#[derive(Debug, Clone)]
+pub struct WaitOptions {
+    pub poll_interval: Duration,
+    pub max_poll_interval: Duration,
+    pub backoff_factor: f64,
+    /// Overall time limit for the task to finish and its segments to load
+    pub timeout: Duration,
+    /// Skip waiting for the coordinator to load the segments of the task
+    pub skip_load: bool,
+}
+
+impl Default for WaitOptions {
+    fn default() -> Self {
+        WaitOptions {
+            poll_interval: Duration::from_secs(1),
+            max_poll_interval: Duration::from_secs(10),
+            backoff_factor: 1.5,
+            timeout: Duration::from_secs(30 * 60),
+            skip_load: false,
+        }
+    }
+}
+
+impl WaitOptions {
+    fn next_interval(&self, interval: Duration) -> Duration {
+        interval
+            .mul_f64(self.backoff_factor.max(1.0))
+            .min(self.max_poll_interval)
+    }
+}
+
+#[derive(Debug)]
+pub enum WaitProgress<'a> {
+    Task(&'a TaskStatus),
+    /// Percentage of the segments of the task's interval loaded by historicals
+    Loading {
+        data_source: &'a str,
+        loaded: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskCompletion {
+    pub task_id: String,
+    pub status: TaskStatusCode,
+    /// Run time of the task as reported by the overlord
+    pub duration: Duration,
+    /// Time spent waiting, including segments loading
+    pub waited: Duration,
+    pub row_stats: HashMap<String, RowStats>,
+    /// Failure reason, from the task report when the status doesn't have one
+    pub error_msg: Option<String>,
+}
+
+impl TaskCompletion {
+    pub fn is_success(&self) -> bool {
+        self.status == TaskStatusCode::Success
+    }
+}
+
+impl OverlordClient {
+    /// Polls the task until it finishes, then polls the coordinator until the segments
+    /// of its datasource in the task's interval, including the ones it published, are loaded.
+    /// A task that published nothing there, e.g. because every row was filtered out, is done
+    /// right away. Failed tasks are returned as a [`TaskCompletion`] with the failure reason,
+    /// exceeding `options.timeout` results in [`DruidClientError::TaskTimeout`].
+    pub async fn wait_for_task(
+        &self,
+        task_id: &str,
+        coordinator: &CoordinatorClient,
+        options: WaitOptions,
+    ) -> ClientResult<TaskCompletion> {
+        self.wait_for_task_with_progress(task_id, coordinator, options, |_| {})
+            .await
+    }
+
+    pub async fn wait_for_task_with_progress<F: FnMut(WaitProgress)>(
+        &self,
+        task_id: &str,
+        coordinator: &CoordinatorClient,
+        options: WaitOptions,
+        mut progress: F,
+    ) -> ClientResult<TaskCompletion> {
+        let started = Instant::now();
+        let mut interval = options.poll_interval;
+
+        let status = loop {
+            let status = self.task_status(task_id).await?;
+            progress(WaitProgress::Task(&status));
+            if !matches!(status.code(), None | Some(TaskStatusCode::Running)) {
+                break status;
+            }
+            interval = sleep_until_next_poll(task_id, started, interval, &options).await?;
+        };
+
+        let payload = match self.task_report(task_id).await {
+            Ok(report) => report.ingestion_stats_and_errors.map(|r| r.payload),
+            Err(_) => None, // not every task type has reports
+        };
+        let (row_stats, report_error) = match payload {
+            Some(payload) => (payload.row_stats, payload.error_msg),
+            None => (HashMap::new(), None),
+        };
+        let completion = TaskCompletion {
+            task_id: task_id.to_string(),
+            status: status.code().unwrap_or(TaskStatusCode::Failed),
+            duration: Duration::from_millis(status.duration.unwrap_or(0).max(0) as u64),
+            waited: started.elapsed(),
+            row_stats,
+            error_msg: status.error_msg.clone().or(report_error),
+        };
+
+        let data_source = match &status.data_source {
+            Some(data_source) if completion.is_success() && !options.skip_load => data_source,
+            _ => return Ok(completion),
+        };
+        let task_interval = match self.task_intervals(task_id).await.as_slice() {
+            [task_interval] => task_interval.clone(),
+            _ => ETERNITY.to_string(),
+        };
+        interval = options.poll_interval;
+        // only the first poll needs to see the segments the task just published
+        let mut refresh_metadata = true;
+        loop {
+            let load_status = coordinator
+                .datasource_load_status(data_source, &task_interval, refresh_metadata)
+                .await?;
+            refresh_metadata = false;
+            let loaded = match load_status {
+                Some(loaded) => loaded,
+                None => break,
+            };
+            progress(WaitProgress::Loading {
+                data_source,
+                loaded,
+            });
+            if loaded >= 100.0 {
+                break;
+            }
+            interval = sleep_until_next_poll(task_id, started, interval, &options).await?;
+        }
+
+        Ok(TaskCompletion {
+            waited: started.elapsed(),
+            ..completion
+        })
+    }
+}
+
+impl OverlordClient {
+    /// Ingests the rows into `data_source` (replacing its data) and waits for them to be queryable,
+    /// see [`InlineIngestion`] for how the columns are derived.
+    pub async fn ingest_rows<T: Serialize>(
+        &self,
+        data_source: &str,
+        rows: &[T],
+        coordinator: &CoordinatorClient,
+        options: WaitOptions,
+    ) -> ClientResult<TaskCompletion> {
+        let ingestion = InlineIngestion::from_rows(data_source, rows)
+            .map_err(|source| DruidClientError::ParsingError { source })?;
+        self.ingest_inline(&ingestion, coordinator, options).await
+    }
+
+    pub async fn ingest_inline(
+        &self,
+        ingestion: &InlineIngestion,
+        coordinator: &CoordinatorClient,
+        options: WaitOptions,
+    ) -> ClientResult<TaskCompletion> {
+        let task_id = self.submit_task(&ingestion.task()).await?;
+        self.wait_for_task(&task_id, coordinator, options).await
+    }
+}
+
+impl OverlordClient {
+    /// Permanently deletes the unused segments of a [`DeletionPlan`] made by
+    /// [`CoordinatorClient::plan_kill`], `confirmation` has to be the plan's confirmation token.
+    /// Fails without submitting a kill task when the plan is empty or the unused segments of
+    /// its interval have changed since planning, otherwise returns the id of the kill task.
+    pub async fn kill(
+        &self,
+        coordinator: &CoordinatorClient,
+        plan: &DeletionPlan,
+        confirmation: &str,
+    ) -> ClientResult<String> {
+        let interval = match (plan.kind, plan.kill_interval()) {
+            (DeletionKind::Kill, Some(interval)) => interval.to_string(),
+            (DeletionKind::Kill, None) => {
+                return Err(DruidClientError::DeletionNotSupported {
+                    data_source: plan.data_source.clone(),
+                    reason: "kill tasks delete intervals, segments can't be selected by id",
+                })
+            }
+            (DeletionKind::MarkUnused, _) => {
+                return Err(DruidClientError::DeletionNotSupported {
+                    data_source: plan.data_source.clone(),
+                    reason: "only plans made by `plan_kill` can be killed",
+                })
+            }
+        };
+        coordinator.confirm(plan, confirmation).await?;
+        if plan.segments.is_empty() {
+            return Err(DruidClientError::NothingToKill {
+                data_source: plan.data_source.clone(),
+                interval,
+            });
+        }
+        self.submit_task(&KillTask {
+            data_source: plan.data_source.clone(),
+            interval,
+        })
+        .await
+    }
+}
+
+async fn sleep_until_next_poll(
+    task_id: &str,
+    started: Instant,
+    interval: Duration,
+    options: &WaitOptions,
+) -> ClientResult<Duration> {
+    let waited = started.elapsed();
+    if waited >= options.timeout {
+        return Err(DruidClientError::TaskTimeout {
+            task_id: task_id.to_string(),
+            waited,
+        });
+    }
+    tokio::time::delay_for(interval.min(options.timeout - waited)).await;
+    Ok(options.next_interval(interval))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::deletion::SegmentSelection;
+    use crate::transport::stub::StubTransport;
+
+    #[test]
+    fn test_deserialize_status() {
+        let str = r#"
+            {
+                "task": "index_parallel_countries_2020-09-06T18:48:27.396Z",
+                "status": {
+                    "id": "index_parallel_countries_2020-09-06T18:48:27.396Z",
+                    "type": "index_parallel",
+                    "createdTime": "2020-09-06T18:48:27.396Z",
+                    "queueInsertionTime": "1970-01-01T00:00:00.000Z",
+                    "statusCode": "FAILED",
+                    "status": "FAILED",
+                    "runnerStatusCode": "WAITING",
+                    "duration": 1234,
+                    "location": {"host": null, "port": -1, "tlsPort": -1},
+                    "dataSource": "countries",
+                    "errorMsg": "java.lang.RuntimeException: boom"
+                }
+            }
+        "#;
+        let status = serde_json::from_str::<TaskStatusResponse>(str)
+            .unwrap()
+            .status;
+        assert_eq!(status.code(), Some(TaskStatusCode::Failed));
+        assert_eq!(status.data_source.as_deref(), Some("countries"));
+        assert_eq!(status.location.unwrap().host, None);
+    }
+
+    #[test]
+    fn test_deserialize_report() {
+        let str = r#"
+            {
+                "ingestionStatsAndErrors": {
+                    "taskId": "index_countries",
+                    "payload": {
+                        "ingestionState": "COMPLETED",
+                        "unparseableEvents": {},
+                        "rowStats": {
+                            "determinePartitions": {"processed": 0, "processedWithError": 0, "thrownAway": 0, "unparseable": 0},
+                            "buildSegments": {"processed": 250, "processedWithError": 0, "thrownAway": 0, "unparseable": 1}
+                        },
+                        "errorMsg": null
+                    },
+                    "type": "ingestionStatsAndErrors"
+                }
+            }
+        "#;
+        let report = serde_json::from_str::<TaskReport>(str).unwrap();
+        let payload = report.ingestion_stats_and_errors.unwrap().payload;
+        assert_eq!(payload.row_stats["buildSegments"].processed, 250);
+        assert_eq!(payload.row_stats["buildSegments"].unparseable, 1);
+    }
+
+    #[test]
+    fn test_wait_for_segments_of_task_interval() {
+        let status = serde_json::json!({
+            "task": "index_countries",
+            "status": {
+                "id": "index_countries",
+                "statusCode": "SUCCESS",
+                "status": "SUCCESS",
+                "duration": 1234,
+                "dataSource": "countries"
+            }
+        });
+        let task = serde_json::json!({
+            "task": "index_countries",
+            "payload": {"spec": {"dataSchema": {"granularitySpec": {"intervals": ["2020-01-01/2020-02-01"]}}}}
+        });
+        let transport = Arc::new(
+            StubTransport::new()
+                .on_path("/druid/indexer/v1/task/index_countries/status", 200, &status.to_string())
+                .on_path("/druid/indexer/v1/task/index_countries", 200, &task.to_string())
+                .on_path(
+                    "/druid/coordinator/v1/datasources/countries/loadstatus?forceMetadataRefresh=true&interval=2020-01-01/2020-02-01",
+                    200,
+                    r#"{"countries": 50.0}"#,
+                )
+                .on_path(
+                    "/druid/coordinator/v1/datasources/countries/loadstatus?forceMetadataRefresh=false&interval=2020-01-01/2020-02-01",
+                    200,
+                    r#"{"countries": 100.0}"#,
+                ),
+        );
+        let coordinator =
+            CoordinatorClient::new(vec!["localhost:8081".into()]).with_transport(transport.clone());
+        let overlord =
+            OverlordClient::new(vec!["localhost:8090".into()]).with_transport(transport.clone());
+
+        let mut loaded = vec![];
+        let completion = tokio_test::block_on(overlord.wait_for_task_with_progress(
+            "index_countries",
+            &coordinator,
+            WaitOptions {
+                poll_interval: Duration::from_millis(1),
+                ..WaitOptions::default()
+            },
+            |progress| {
+                if let WaitProgress::Loading {
+                    loaded: percent, ..
+                } = progress
+                {
+                    loaded.push(percent);
+                }
+            },
+        ))
+        .unwrap();
+        assert!(completion.is_success());
+        assert_eq!(loaded, vec![50.0, 100.0]);
+        let refreshes = transport
+            .requests()
+            .iter()
+            .filter(|request| request.path.contains("forceMetadataRefresh=true"))
+            .count();
+        assert_eq!(refreshes, 1);
+    }
+
+    #[test]
+    fn test_wait_for_task_publishing_nothing() {
+        let status = serde_json::json!({
+            "task": "index_countries",
+            "status": {"id": "index_countries", "status": "SUCCESS", "dataSource": "countries"}
+        });
+        let transport = Arc::new(
+            StubTransport::new()
+                .on_path(
+                    "/druid/indexer/v1/task/index_countries/status",
+                    200,
+                    &status.to_string(),
+                )
+                .on(|request| request.path.contains("/loadstatus"), 204, "")
+                .otherwise(404, ""),
+        );
+        let coordinator =
+            CoordinatorClient::new(vec!["localhost:8081".into()]).with_transport(transport.clone());
+        let overlord =
+            OverlordClient::new(vec!["localhost:8090".into()]).with_transport(transport.clone());
+
+        let options = WaitOptions {
+            timeout: Duration::from_millis(50),
+            ..WaitOptions::default()
+        };
+        let completion =
+            tokio_test::block_on(overlord.wait_for_task("index_countries", &coordinator, options))
+                .unwrap();
+        assert!(completion.is_success());
+    }
+
+    #[test]
+    fn test_tasks_filter() {
+        let transport = Arc::new(StubTransport::new().otherwise(200, "[]"));
+        let overlord =
+            OverlordClient::new(vec!["localhost:8090".into()]).with_transport(transport.clone());
+        tokio_test::block_on(overlord.tasks(Some(TaskState::Running), Some("sales&returns=1")))
+            .unwrap();
+        assert_eq!(
+            transport.requests()[0].path,
+            "/druid/indexer/v1/tasks?state=running&datasource=sales%26returns%3D1"
+        );
+    }
+
+    fn segment(interval: &str) -> serde_json::Value {
+        serde_json::json!({
+            "dataSource": "wikipedia",
+            "interval": interval,
+            "version": "2020-09-06T18:48:27.396Z",
+            "loadSpec": {"type": "local"},
+            "shardSpec": {"type": "numbered", "partitionNum": 0, "partitions": 0},
+            "size": 4817636,
+            "identifier": format!("wikipedia_{}", interval.replace('/', "_")),
+        })
+    }
+
+    #[test]
+    fn test_kill() {
+        let unused = serde_json::json!([
+            {"dataSegment": segment("2015-09-12T00:00:00.000Z/2015-09-13T00:00:00.000Z")},
+            segment("2015-09-14T00:00:00.000Z/2015-09-15T00:00:00.000Z"),
+        ]);
+        let transport = Arc::new(
+            StubTransport::new()
+                .on_path(
+                    "/druid/coordinator/v1/metadata/datasources/wikipedia/unusedSegments",
+                    200,
+                    &unused.to_string(),
+                )
+                .on_path(
+                    "/druid/indexer/v1/task",
+                    200,
+                    r#"{"task": "kill_wikipedia"}"#,
+                ),
+        );
+        let coordinator =
+            CoordinatorClient::new(vec!["localhost:8081".into()]).with_transport(transport.clone());
+        let overlord =
+            OverlordClient::new(vec!["localhost:8090".into()]).with_transport(transport.clone());
+
+        let plan =
+            tokio_test::block_on(coordinator.plan_kill("wikipedia", Some("2015-09-12/2015-09-13")))
+                .unwrap();
+        assert_eq!(plan.segments.len(), 1);
+        let killed = tokio_test::block_on(overlord.kill(&coordinator, &plan, "kill:wikipedia:1"));
+        assert!(matches!(
+            killed,
+            Err(DruidClientError::DeletionNotConfirmed { .. })
+        ));
+        let token = plan.confirmation_token();
+        let task_id = tokio_test::block_on(overlord.kill(&coordinator, &plan, &token)).unwrap();
+        assert_eq!(task_id, "kill_wikipedia");
+        let task = transport.requests().pop().unwrap().body.unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&task).unwrap(),
+            serde_json::json!({"type": "kill", "dataSource": "wikipedia", "interval": "2015-09-12/2015-09-13"})
+        );
+
+        let all = tokio_test::block_on(coordinator.plan_kill("wikipedia", None)).unwrap();
+        assert_eq!(all.segments.len(), 2);
+        let token = all.confirmation_token();
+        tokio_test::block_on(overlord.kill(&coordinator, &all, &token)).unwrap();
+        let task = transport.requests().pop().unwrap().body.unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&task).unwrap()["interval"],
+            "-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z"
+        );
+
+        let empty =
+            tokio_test::block_on(coordinator.plan_kill("wikipedia", Some("2016-01-01/2016-01-02")))
+                .unwrap();
+        let token = empty.confirmation_token();
+        let killed = tokio_test::block_on(overlord.kill(&coordinator, &empty, &token));
+        assert!(matches!(
+            killed,
+            Err(DruidClientError::NothingToKill { .. })
+        ));
+
+        let by_id = DeletionPlan {
+            selection: SegmentSelection::Segments(vec![plan.segments[0].identifier.clone()]),
+            ..plan
+        };
+        let token = by_id.confirmation_token();
+        let killed = tokio_test::block_on(overlord.kill(&coordinator, &by_id, &token));
+        assert!(matches!(
+            killed,
+            Err(DruidClientError::DeletionNotSupported { .. })
+        ));
+        let submitted = transport
+            .requests()
+            .iter()
+            .filter(|request| request.path == "/druid/indexer/v1/task")
+            .count();
+        assert_eq!(submitted, 2);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let options = WaitOptions {
+            poll_interval: Duration::from_secs(1),
+            max_poll_interval: Duration::from_secs(3),
+            backoff_factor: 2.0,
+            ..Default::default()
+        };
+        let intervals: Vec<_> = std::iter::successors(Some(options.poll_interval), |i| {
+            Some(options.next_interval(*i))
+        })
+        .take(4)
+        .map(|i| i.as_secs())
+        .collect();
+        assert_eq!(intervals, vec![1, 2, 3, 3]);
+    }
+}
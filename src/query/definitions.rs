@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 
+/// Interval covering all time, as Druid writes it.
+pub const ETERNITY: &str = "-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z";
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
@@ -79,6 +82,18 @@ impl Dimension {
             delegate: Box::new(dimension),
         }
     }
+
+    pub fn output_name(&self) -> &str {
+        match self {
+            Dimension::Default { output_name, .. }
+            | Dimension::Extraction { output_name, .. }
+            | Dimension::LookupMap { output_name, .. }
+            | Dimension::Lookup { output_name, .. } => output_name,
+            Dimension::ListFiltered { delegate, .. }
+            | Dimension::RegexFiltered { delegate, .. }
+            | Dimension::PrefixFiltered { delegate, .. } => delegate.output_name(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -168,6 +183,39 @@ pub enum HllType {
 
 // todo: macro
 impl Aggregation {
+    pub fn name(&self) -> &str {
+        match self {
+            Aggregation::Count { name }
+            | Aggregation::LongSum { name, .. }
+            | Aggregation::DoubleSum { name, .. }
+            | Aggregation::FloatSum { name, .. }
+            | Aggregation::LongMax { name, .. }
+            | Aggregation::DoubleMax { name, .. }
+            | Aggregation::FloatMax { name, .. }
+            | Aggregation::LongMin { name, .. }
+            | Aggregation::FloatMin { name, .. }
+            | Aggregation::DoubleMin { name, .. }
+            | Aggregation::LongFirst { name, .. }
+            | Aggregation::FloatFirst { name, .. }
+            | Aggregation::DoubleFirst { name, .. }
+            | Aggregation::LongLast { name, .. }
+            | Aggregation::FloatLast { name, .. }
+            | Aggregation::DoubleLast { name, .. }
+            | Aggregation::StringFirst { name, .. }
+            | Aggregation::StringLast { name, .. }
+            | Aggregation::DoubleAny { name, .. }
+            | Aggregation::FloatAny { name, .. }
+            | Aggregation::LongAny { name, .. }
+            | Aggregation::StringAny { name, .. }
+            | Aggregation::Javascript { name, .. }
+            | Aggregation::ThetaSketch { name, .. }
+            | Aggregation::HLLSketchBuild { name, .. }
+            | Aggregation::Cardinality { name, .. }
+            | Aggregation::HyperUnique { name, .. } => name,
+            Aggregation::Filtered { aggregator, .. } => aggregator.name(),
+        }
+    }
+
     pub fn count(name: &str) -> Aggregation {
         Aggregation::Count {
             name: name.to_string(),
@@ -453,9 +501,10 @@ pub enum Ordering {
     Descending,
     None,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum SortingOrder {
+    #[default]
     Lexicographic,
     Alphanumeric,
     Strlen,
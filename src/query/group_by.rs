@@ -5,6 +5,9 @@ use super::DataSource;
 use super::{definitions::Ordering, JsonAny, JsonNumber};
 use crate::query::definitions::Aggregation;
 use crate::query::definitions::SortingOrder;
+use crate::serialization::{
+    default_for_null, intervals, noop_as_none, string_map, tagged_or_untagged,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,14 +16,26 @@ use serde::{Deserialize, Serialize};
 pub struct GroupBy {
     pub data_source: DataSource,
     pub dimensions: Vec<Dimension>,
+    #[serde(default, deserialize_with = "noop_as_none")]
     pub limit_spec: Option<LimitSpec>,
     pub having: Option<HavingSpec>,
+    #[serde(deserialize_with = "tagged_or_untagged", default)]
     pub granularity: Granularity,
     pub filter: Option<Filter>,
     pub aggregations: Vec<Aggregation>,
+    #[serde(default)]
     pub post_aggregations: Vec<PostAggregation>,
+    #[serde(deserialize_with = "intervals")]
     pub intervals: Vec<String>,
+    // druid computes no subtotals at all for an empty list, so it's left out
+    #[serde(
+        rename = "subtotalsSpec",
+        default,
+        deserialize_with = "default_for_null",
+        skip_serializing_if = "Vec::is_empty"
+    )]
     pub subtotal_spec: Vec<Vec<String>>,
+    #[serde(default, deserialize_with = "string_map")]
     pub context: std::collections::HashMap<String, String>,
 }
 
@@ -37,6 +52,7 @@ pub struct LimitSpec {
 pub struct OrderByColumnSpec {
     pub dimension: String,
     pub direction: Ordering,
+    #[serde(deserialize_with = "tagged_or_untagged", default)]
     pub dimension_order: SortingOrder,
 }
 
@@ -99,7 +115,7 @@ pub enum PostAggregation {
     #[serde(rename_all = "camelCase")]
     Arithmetic {
         name: String,
-        #[serde(rename(serialize = "fn"))]
+        #[serde(rename = "fn")]
         function: String,
         fields: Vec<PostAggregator>,
         ordering: Option<String>,
@@ -128,6 +144,19 @@ pub enum PostAggregation {
     },
 }
 
+impl PostAggregation {
+    pub fn name(&self) -> &str {
+        match self {
+            PostAggregation::Arithmetic { name, .. }
+            | PostAggregation::DoubleGreatest { name, .. }
+            | PostAggregation::LongGreatest { name, .. }
+            | PostAggregation::LongLeast { name, .. }
+            | PostAggregation::DoubleLeast { name, .. }
+            | PostAggregation::Javascript { name, .. } => name,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type")]
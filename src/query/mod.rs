@@ -1,9 +1,12 @@
 use crate::query::search::Search;
+use definitions::{Aggregation, Dimension};
 use group_by::GroupBy;
+use group_by::PostAggregation;
 use scan::Scan;
 use segment_metadata::SegmentMetadata;
-use serde::{Deserialize, Serialize};
-use time_boundary::TimeBoundary;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize};
+use time_boundary::{TimeBoundType, TimeBoundary};
 use timeseries::Timeseries;
 use top_n::TopN;
 
@@ -13,11 +16,12 @@ pub mod response;
 pub mod scan;
 pub mod search;
 pub mod segment_metadata;
+pub mod sql;
 pub mod time_boundary;
 pub mod timeseries;
 pub mod top_n;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Debug)]
 #[serde(untagged)]
 #[serde(rename_all = "camelCase")]
 pub enum Query {
@@ -29,6 +33,145 @@ pub enum Query {
     Timeseries(Timeseries),
     TopN(TopN),
 }
+// Every query struct already writes its own `queryType`, so the enum is serialized untagged
+// while deserialization dispatches on the tag.
+impl<'de> Deserialize<'de> for Query {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const QUERY_TYPES: &[&str] = &[
+            "groupBy",
+            "scan",
+            "search",
+            "segmentMetadata",
+            "timeBoundary",
+            "timeseries",
+            "topN",
+        ];
+        // fields changing the results, lost when a query struct doesn't model them
+        const RESULT_FIELDS: &[&str] = &[
+            "virtualColumns",
+            "filter",
+            "aggregations",
+            "postAggregations",
+            "having",
+            "limitSpec",
+            "subtotalsSpec",
+        ];
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let query_type = value
+            .get("queryType")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| de::Error::missing_field("queryType"))?
+            .to_string();
+        let fields: Vec<&str> = RESULT_FIELDS
+            .iter()
+            .copied()
+            .filter(|field| value.get(field).is_some_and(is_set))
+            .collect();
+        let query = match query_type.as_str() {
+            "groupBy" => serde_json::from_value(value).map(Query::GroupBy),
+            "scan" => serde_json::from_value(value).map(Query::Scan),
+            "search" => serde_json::from_value(value).map(Query::Search),
+            "segmentMetadata" => serde_json::from_value(value).map(Query::SegmentMetadata),
+            "timeBoundary" => serde_json::from_value(value).map(Query::TimeBoundary),
+            "timeseries" => serde_json::from_value(value).map(Query::Timeseries),
+            "topN" => serde_json::from_value(value).map(Query::TopN),
+            other => return Err(de::Error::unknown_variant(other, QUERY_TYPES)),
+        };
+        let query: Query = query.map_err(de::Error::custom)?;
+
+        let written = serde_json::to_value(&query).map_err(de::Error::custom)?;
+        match fields
+            .iter()
+            .find(|field| !written.get(**field).is_some_and(is_set))
+        {
+            Some(field) => Err(de::Error::custom(format!(
+                "`{}` of {} queries is not supported",
+                field, query_type
+            ))),
+            None => Ok(query),
+        }
+    }
+}
+
+// Druid writes the fields a query doesn't use as `null` or empty
+fn is_set(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => false,
+        serde_json::Value::Array(values) => !values.is_empty(),
+        serde_json::Value::Object(fields) => !fields.is_empty(),
+        _ => true,
+    }
+}
+
+impl Query {
+    /// Names of the columns every result row of this query carries, in query order.
+    ///
+    /// Bucket timestamps are not included. Scan queries without explicit `columns`
+    /// return all columns of the datasource, so the list is empty for them.
+    pub fn output_names(&self) -> Vec<String> {
+        match self {
+            Query::GroupBy(query) => query.output_names(),
+            Query::Timeseries(query) => query.output_names(),
+            Query::TopN(query) => query.output_names(),
+            Query::Scan(query) => query.output_names(),
+            Query::Search(_) => names(vec!["dimension", "value", "count"].into_iter()),
+            Query::TimeBoundary(query) => match query.bound {
+                TimeBoundType::MaxTime => vec!["maxTime".into()],
+                TimeBoundType::MinTime => vec!["minTime".into()],
+                TimeBoundType::MinMaxTime => vec!["minTime".into(), "maxTime".into()],
+            },
+            Query::SegmentMetadata(_) => vec![],
+        }
+    }
+}
+
+fn names<'a>(names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    names.map(|name| name.to_string()).collect()
+}
+
+impl GroupBy {
+    /// Output names of the dimensions, aggregations and post aggregations.
+    pub fn output_names(&self) -> Vec<String> {
+        names(
+            self.dimensions
+                .iter()
+                .map(Dimension::output_name)
+                .chain(self.aggregations.iter().map(Aggregation::name))
+                .chain(self.post_aggregations.iter().map(PostAggregation::name)),
+        )
+    }
+}
+
+impl Timeseries {
+    /// Output names of the aggregations and post aggregations.
+    pub fn output_names(&self) -> Vec<String> {
+        names(
+            self.aggregations
+                .iter()
+                .map(Aggregation::name)
+                .chain(self.post_aggregations.iter().map(PostAggregation::name)),
+        )
+    }
+}
+
+impl TopN {
+    /// Output names of the dimension, aggregations and post aggregations.
+    pub fn output_names(&self) -> Vec<String> {
+        names(
+            std::iter::once(self.dimension.output_name())
+                .chain(self.aggregations.iter().map(Aggregation::name))
+                .chain(self.post_aggregations.iter().map(PostAggregation::name)),
+        )
+    }
+}
+
+impl Scan {
+    /// Requested columns, empty when all columns of the datasource are returned.
+    pub fn output_names(&self) -> Vec<String> {
+        self.columns.clone()
+    }
+}
+
 impl From<TopN> for Query {
     fn from(query: TopN) -> Self {
         Query::TopN(query)
@@ -197,7 +340,7 @@ impl From<isize> for JsonNumber {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum JsonAny {
     Float(f32),
@@ -1,4 +1,4 @@
-use super::{definitions::Granularity, JsonAny};
+use super::{definitions::Granularity, JsonAny, Query};
 use crate::serialization::default_for_null;
 use crate::serialization::tagged_or_untagged;
 use serde::de::DeserializeOwned;
@@ -8,14 +8,14 @@ use std::collections::HashMap;
 #[derive(Deserialize, Serialize, Debug)]
 pub struct DruidListResponse<T: DeserializeOwned> {
     pub timestamp: String,
-    #[serde(bound (deserialize = ""))]
+    #[serde(bound(deserialize = ""))]
     pub result: Vec<T>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct MetadataResponse<T: DeserializeOwned> {
     pub timestamp: String,
-    #[serde(bound (deserialize = ""))]
+    #[serde(bound(deserialize = ""))]
     pub result: T,
 }
 
@@ -24,7 +24,7 @@ pub type TopNResponse<T> = DruidListResponse<T>;
 #[derive(Deserialize, Serialize, Debug)]
 pub struct GroupByResponse<T: DeserializeOwned> {
     pub timestamp: String,
-    #[serde(bound (deserialize = ""))]
+    #[serde(bound(deserialize = ""))]
     pub event: T,
 }
 
@@ -42,7 +42,7 @@ pub type SearchResponse = DruidListResponse<DimValue>;
 pub struct ScanResponse<T: DeserializeOwned> {
     segment_id: String,
     columns: Vec<String>,
-    #[serde(bound (deserialize = ""))]
+    #[serde(bound(deserialize = ""))]
     events: Vec<T>,
 }
 
@@ -110,6 +110,171 @@ pub struct SegmentMetadataResponse {
 #[serde(rename_all = "camelCase")]
 pub struct TimeseriesResponse<T: DeserializeOwned> {
     timestamp: Option<String>,
-    #[serde(bound (deserialize = ""))]
+    #[serde(bound(deserialize = ""))]
     result: T,
 }
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SqlColumn {
+    pub name: String,
+    pub druid_type: Option<String>,
+    pub sql_type: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SqlResponse<T: DeserializeOwned> {
+    /// Empty unless the query asked for a `header`.
+    pub columns: Vec<SqlColumn>,
+    #[serde(bound(deserialize = ""))]
+    pub rows: Vec<T>,
+}
+
+/// Native queries a Druid SQL statement is planned into, see [`DruidClient::explain`](crate::client::DruidClient::explain).
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ExplainPlan {
+    pub queries: Vec<ExplainedQuery>,
+    /// Datasources the statement reads, only reported by Druid 0.23+.
+    pub resources: Vec<PlanResource>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ExplainedQuery {
+    pub query: Query,
+    /// Output columns of the native query in the order SQL exposes them.
+    pub signature: Vec<SignatureColumn>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SignatureColumn {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PlanResource {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub resource_type: String,
+}
+
+/// Flat view over query results: every output row becomes a single json object.
+///
+/// Nested result arrays (TopN, Search) are flattened into long format, so each
+/// element of `result` produces its own row with the bucket `timestamp` attached.
+/// Rows are produced lazily, one at a time.
+pub trait ResultRows {
+    fn rows<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Row, serde_json::Error>> + 'a>;
+}
+
+pub type Row = serde_json::Map<String, serde_json::Value>;
+
+fn row_of<T: Serialize>(timestamp: Option<&String>, value: &T) -> Result<Row, serde_json::Error> {
+    let mut row = Row::new();
+    if let Some(timestamp) = timestamp {
+        row.insert("timestamp".into(), timestamp.clone().into());
+    }
+    match serde_json::to_value(value)? {
+        serde_json::Value::Object(fields) => row.extend(fields),
+        other => {
+            row.insert("result".into(), other);
+        }
+    }
+    Ok(row)
+}
+
+impl<T: DeserializeOwned + Serialize> ResultRows for Vec<DruidListResponse<T>> {
+    fn rows<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Row, serde_json::Error>> + 'a> {
+        Box::new(self.iter().flat_map(|bucket| {
+            bucket
+                .result
+                .iter()
+                .map(move |item| row_of(Some(&bucket.timestamp), item))
+        }))
+    }
+}
+
+impl<T: DeserializeOwned + Serialize> ResultRows for Vec<GroupByResponse<T>> {
+    fn rows<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Row, serde_json::Error>> + 'a> {
+        Box::new(self.iter().map(|r| row_of(Some(&r.timestamp), &r.event)))
+    }
+}
+
+impl<T: DeserializeOwned + Serialize> ResultRows for Vec<TimeseriesResponse<T>> {
+    fn rows<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Row, serde_json::Error>> + 'a> {
+        Box::new(self.iter().map(|r| row_of(r.timestamp.as_ref(), &r.result)))
+    }
+}
+
+impl<T: DeserializeOwned + Serialize> ResultRows for Vec<MetadataResponse<T>> {
+    fn rows<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Row, serde_json::Error>> + 'a> {
+        Box::new(self.iter().map(|r| row_of(Some(&r.timestamp), &r.result)))
+    }
+}
+
+impl<T: DeserializeOwned + Serialize> ResultRows for Vec<ScanResponse<T>> {
+    fn rows<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Row, serde_json::Error>> + 'a> {
+        Box::new(self.iter().flat_map(|batch| {
+            batch.events.iter().map(move |event| {
+                // compactedList result format returns rows as arrays aligned with `columns`
+                match serde_json::to_value(event)? {
+                    serde_json::Value::Array(values) => {
+                        Ok(batch.columns.iter().cloned().zip(values).collect())
+                    }
+                    _ => row_of(None, event),
+                }
+            })
+        }))
+    }
+}
+
+impl<T: DeserializeOwned + Serialize> ResultRows for SqlResponse<T> {
+    fn rows<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Row, serde_json::Error>> + 'a> {
+        Box::new(self.rows.iter().map(move |row| {
+            // array result formats return rows aligned with header columns
+            match serde_json::to_value(row)? {
+                serde_json::Value::Array(values) if !self.columns.is_empty() => Ok(self
+                    .columns
+                    .iter()
+                    .map(|column| column.name.clone())
+                    .zip(values)
+                    .collect()),
+                _ => row_of(None, row),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_top_n_rows_are_flattened() {
+        let response: Vec<TopNResponse<serde_json::Value>> = serde_json::from_value(json!([
+            {"timestamp": "2015-09-12T00:00:00.000Z", "result": [
+                {"page": "Main", "count": 10},
+                {"page": "Other", "count": 3}
+            ]}
+        ]))
+        .unwrap();
+        let rows = response.rows().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["timestamp"], "2015-09-12T00:00:00.000Z");
+        assert_eq!(rows[1]["page"], "Other");
+        assert_eq!(rows[1]["count"], 3);
+    }
+
+    #[test]
+    fn test_compacted_scan_rows() {
+        let response: Vec<ScanResponse<serde_json::Value>> = serde_json::from_value(json!([
+            {"segmentId": "s1", "columns": ["__time", "page"], "events": [[1442018818771i64, "Main"]]}
+        ]))
+        .unwrap();
+        let rows = response.rows().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows[0]["__time"], 1442018818771i64);
+        assert_eq!(rows[0]["page"], "Main");
+    }
+}
@@ -1,6 +1,7 @@
 use super::definitions::Filter;
 use super::definitions::Ordering;
 use super::DataSource;
+use crate::serialization::{intervals, string_map};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -8,13 +9,16 @@ use serde::{Deserialize, Serialize};
 #[serde(rename_all = "camelCase")]
 pub struct Scan {
     pub data_source: DataSource,
+    #[serde(deserialize_with = "intervals")]
     pub intervals: Vec<String>,
     pub result_format: ResultFormat,
     pub filter: Option<Filter>,
+    #[serde(default)]
     pub columns: Vec<String>,
     pub batch_size: usize,
     pub limit: Option<usize>,
     pub ordering: Option<Ordering>,
+    #[serde(default, deserialize_with = "string_map")]
     pub context: std::collections::HashMap<String, String>,
 }
 
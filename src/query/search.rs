@@ -2,6 +2,7 @@ use super::definitions::Filter;
 use super::definitions::Granularity;
 use super::definitions::SortingOrder;
 use super::DataSource;
+use crate::serialization::{intervals, string_map, tagged_or_untagged};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -9,13 +10,16 @@ use serde::{Deserialize, Serialize};
 #[serde(tag = "queryType", rename = "search")]
 pub struct Search {
     pub data_source: DataSource,
+    #[serde(deserialize_with = "tagged_or_untagged", default)]
     pub granularity: Granularity,
     pub filter: Option<Filter>,
     pub limit: usize,
+    #[serde(deserialize_with = "intervals")]
     pub intervals: Vec<String>,
     pub search_dimensions: Vec<String>,
     pub query: SearchQuerySpec,
     pub sort: Option<SortingOrder>,
+    #[serde(default, deserialize_with = "string_map")]
     pub context: std::collections::HashMap<String, String>,
 }
 
@@ -1,4 +1,5 @@
 use crate::query::DataSource;
+use crate::serialization::intervals;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -6,6 +7,7 @@ use serde::{Deserialize, Serialize};
 #[serde(rename_all = "camelCase")]
 pub struct SegmentMetadata {
     pub data_source: DataSource,
+    #[serde(deserialize_with = "intervals", default)]
     pub intervals: Vec<String>,
     pub to_include: ToInclude,
     pub merge: bool,
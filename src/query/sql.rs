@@ -0,0 +1,713 @@
+use crate::client::DruidClientError;
+use crate::query::response::{
+    ExplainPlan, ExplainedQuery, PlanResource, SignatureColumn, SqlColumn,
+};
+use crate::query::JsonAny;
+use crate::serialization::OrderedObject;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SqlQuery {
+    pub query: String,
+    pub result_format: SqlResultFormat,
+    pub header: bool,
+    pub types_header: bool,
+    pub sql_types_header: bool,
+    pub context: std::collections::HashMap<String, String>,
+    /// Values for `?` placeholders of the query, in order of appearance.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub parameters: Vec<SqlParameter>,
+}
+
+/// Typed value of a [dynamic parameter](https://druid.apache.org/docs/latest/querying/sql.html#dynamic-parameters).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "UPPERCASE")]
+pub enum SqlParameter {
+    Varchar(String),
+    Bigint(i64),
+    Double(f64),
+    Boolean(bool),
+    /// `yyyy-MM-dd HH:mm:ss` or ISO-8601 timestamp string
+    Timestamp(String),
+    Array(Vec<JsonAny>),
+}
+
+impl SqlParameter {
+    pub fn timestamp(timestamp: &str) -> Self {
+        SqlParameter::Timestamp(timestamp.to_string())
+    }
+    pub fn array<T: Into<JsonAny>>(values: Vec<T>) -> Self {
+        SqlParameter::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<&str> for SqlParameter {
+    fn from(str: &str) -> Self {
+        SqlParameter::Varchar(str.to_string())
+    }
+}
+impl From<String> for SqlParameter {
+    fn from(str: String) -> Self {
+        SqlParameter::Varchar(str)
+    }
+}
+impl From<i64> for SqlParameter {
+    fn from(integer: i64) -> Self {
+        SqlParameter::Bigint(integer)
+    }
+}
+impl From<i32> for SqlParameter {
+    fn from(integer: i32) -> Self {
+        SqlParameter::Bigint(integer.into())
+    }
+}
+impl From<f64> for SqlParameter {
+    fn from(float: f64) -> Self {
+        SqlParameter::Double(float)
+    }
+}
+impl From<bool> for SqlParameter {
+    fn from(boolean: bool) -> Self {
+        SqlParameter::Boolean(boolean)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SqlResultFormat {
+    Object,
+    Array,
+    ObjectLines,
+    ArrayLines,
+    Csv,
+}
+
+impl SqlResultFormat {
+    /// Line oriented formats can be consumed row by row while the response is still being received.
+    pub fn is_line_oriented(&self) -> bool {
+        match self {
+            SqlResultFormat::ObjectLines | SqlResultFormat::ArrayLines | SqlResultFormat::Csv => {
+                true
+            }
+            SqlResultFormat::Object | SqlResultFormat::Array => false,
+        }
+    }
+}
+
+impl SqlQuery {
+    pub fn new(query: &str) -> Self {
+        SqlQuery {
+            query: query.to_string(),
+            result_format: SqlResultFormat::Object,
+            header: false,
+            types_header: false,
+            sql_types_header: false,
+            context: Default::default(),
+            parameters: vec![],
+        }
+    }
+    pub fn result_format(mut self, result_format: SqlResultFormat) -> Self {
+        self.result_format = result_format;
+        self
+    }
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+    /// Druid types of the columns are only reported as part of the header.
+    pub fn types_header(mut self, types_header: bool) -> Self {
+        self.types_header = types_header;
+        self.header |= types_header;
+        self
+    }
+    /// SQL types of the columns are only reported as part of the header.
+    pub fn sql_types_header(mut self, sql_types_header: bool) -> Self {
+        self.sql_types_header = sql_types_header;
+        self.header |= sql_types_header;
+        self
+    }
+    pub fn add_context(mut self, key: &str, value: &str) -> Self {
+        self.context.insert(key.to_string(), value.to_string());
+        self
+    }
+    /// Binds the value of the next `?` placeholder.
+    pub fn bind<P: Into<SqlParameter>>(mut self, parameter: P) -> Self {
+        self.parameters.push(parameter.into());
+        self
+    }
+
+    /// Number of `?` placeholders, ignoring string literals, quoted identifiers and comments.
+    pub fn placeholder_count(&self) -> usize {
+        let mut count = 0;
+        let mut chars = self.query.chars().peekable();
+        while let Some(char) = chars.next() {
+            match char {
+                '?' => count += 1,
+                '\'' | '"' => {
+                    // quotes are escaped by doubling them, which this skips as two literals
+                    for next in chars.by_ref() {
+                        if next == char {
+                            break;
+                        }
+                    }
+                }
+                '-' if chars.peek() == Some(&'-') => {
+                    for next in chars.by_ref() {
+                        if next == '\n' {
+                            break;
+                        }
+                    }
+                }
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    let mut previous = ' ';
+                    for next in chars.by_ref() {
+                        if previous == '*' && next == '/' {
+                            break;
+                        }
+                        previous = next;
+                    }
+                }
+                _ => (),
+            }
+        }
+        count
+    }
+
+    /// `EXPLAIN PLAN FOR` this statement, with the plan reported as native json queries.
+    pub fn explain(&self) -> SqlQuery {
+        let mut context = self.context.clone();
+        context.insert("useNativeQueryExplain".into(), "true".into());
+        SqlQuery {
+            query: format!("EXPLAIN PLAN FOR {}", self.query),
+            result_format: SqlResultFormat::Object,
+            header: false,
+            types_header: false,
+            sql_types_header: false,
+            context,
+            parameters: self.parameters.clone(),
+        }
+    }
+
+    /// Verifies that every placeholder has a bound value and there are no extra values.
+    pub fn check_parameters(&self) -> Result<(), DruidClientError> {
+        let placeholders = self.placeholder_count();
+        if placeholders != self.parameters.len() {
+            return Err(DruidClientError::SqlParametersMismatch {
+                placeholders,
+                parameters: self.parameters.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) struct ExplainRow {
+    pub(crate) plan: String,
+    pub(crate) resources: Option<String>,
+}
+
+impl ExplainRow {
+    /// Newer brokers report the plan as json, older ones as a text like
+    /// `DruidQueryRel(query=[{..native query..}], signature=[{d0:STRING, a0:LONG}])`.
+    pub(crate) fn into_plan(self) -> Result<ExplainPlan, DruidClientError> {
+        let queries = match serde_json::from_str::<Vec<ExplainedQuery>>(&self.plan) {
+            Ok(queries) => queries,
+            // a json plan of a query that can't be represented
+            Err(source) if serde_json::from_str::<Value>(&self.plan).is_ok() => {
+                return Err(DruidClientError::ParsingResponseError { source })
+            }
+            Err(_) => parse_text_plan(&self.plan)?,
+        };
+        let resources = match self.resources {
+            Some(resources) => serde_json::from_str::<Vec<PlanResource>>(&resources)
+                .map_err(|source| DruidClientError::ParsingResponseError { source })?,
+            None => vec![],
+        };
+        Ok(ExplainPlan { queries, resources })
+    }
+}
+
+fn parse_text_plan(plan: &str) -> Result<Vec<ExplainedQuery>, DruidClientError> {
+    let mut queries = vec![];
+    let mut rest = plan;
+    while let Some(start) = rest.find("query=[") {
+        rest = &rest[start + "query=[".len()..];
+        let mut json = serde_json::Deserializer::from_str(rest).into_iter::<Value>();
+        let query = match json.next() {
+            Some(query) => {
+                query.map_err(|source| DruidClientError::ParsingResponseError { source })?
+            }
+            None => break,
+        };
+        rest = &rest[json.byte_offset()..];
+        let query = serde_json::from_value(query)
+            .map_err(|source| DruidClientError::ParsingResponseError { source })?;
+
+        // signature belongs to this query only if it comes before the next nested one
+        let section = &rest[..rest.find("query=[").unwrap_or(rest.len())];
+        let signature = match section.find("signature=[{") {
+            Some(start) => {
+                let columns = &section[start + "signature=[{".len()..];
+                let columns = &columns[..columns.find("}]").unwrap_or(columns.len())];
+                columns
+                    .split(", ")
+                    .filter(|column| !column.is_empty())
+                    .map(|column| match column.rfind(':') {
+                        Some(split) => SignatureColumn {
+                            name: column[..split].to_string(),
+                            column_type: Some(column[split + 1..].to_string()),
+                        },
+                        None => SignatureColumn {
+                            name: column.to_string(),
+                            column_type: None,
+                        },
+                    })
+                    .collect()
+            }
+            None => vec![],
+        };
+        queries.push(ExplainedQuery { query, signature });
+    }
+    if queries.is_empty() {
+        return Err(DruidClientError::UnexpectedPlan {
+            plan: plan.to_string(),
+        });
+    }
+    Ok(queries)
+}
+
+/// Decodes Druid SQL response rows one at a time, splitting off the header rows.
+///
+/// Object formats report the header as the first object (`{"column": {"type": .., "sqlType": ..}}`),
+/// array and csv formats as up to three leading rows: names, druid types and sql types.
+pub(crate) struct SqlRowDecoder {
+    header_rows: Vec<HeaderRow>,
+    pub(crate) columns: Vec<SqlColumn>,
+}
+
+#[derive(Clone, Copy)]
+enum HeaderRow {
+    Names,
+    DruidTypes,
+    SqlTypes,
+}
+
+impl SqlRowDecoder {
+    pub(crate) fn new(query: &SqlQuery) -> Self {
+        let mut header_rows = vec![];
+        if query.header {
+            header_rows.push(HeaderRow::Names);
+            match query.result_format {
+                SqlResultFormat::Object | SqlResultFormat::ObjectLines => (),
+                _ => {
+                    if query.types_header {
+                        header_rows.push(HeaderRow::DruidTypes);
+                    }
+                    if query.sql_types_header {
+                        header_rows.push(HeaderRow::SqlTypes);
+                    }
+                }
+            }
+        }
+        header_rows.reverse();
+        SqlRowDecoder {
+            header_rows,
+            columns: vec![],
+        }
+    }
+
+    pub(crate) fn expects_header(&self) -> bool {
+        !self.header_rows.is_empty()
+    }
+
+    /// Decodes a single line of a json lines response, `None` for header and blank lines.
+    pub(crate) fn decode_line<T: DeserializeOwned>(
+        &mut self,
+        line: &str,
+    ) -> Result<Option<T>, DruidClientError> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        if line.trim_start().starts_with('{') {
+            let object = serde_json::from_str::<OrderedObject>(line)
+                .map_err(|source| DruidClientError::ParsingResponseError { source })?;
+            return self.decode_object(object);
+        }
+        let value = serde_json::from_str::<Value>(line)
+            .map_err(|source| DruidClientError::ParsingResponseError { source })?;
+        self.decode_value(value)
+    }
+
+    /// Decodes a single row of an object format, `None` if the row was the header.
+    ///
+    /// The header lists the columns in the order of its keys.
+    pub(crate) fn decode_object<T: DeserializeOwned>(
+        &mut self,
+        object: OrderedObject,
+    ) -> Result<Option<T>, DruidClientError> {
+        if self.header_rows.pop().is_some() {
+            self.columns = object
+                .0
+                .into_iter()
+                .map(|(name, types)| SqlColumn {
+                    druid_type: type_of(&types, "type"),
+                    sql_type: type_of(&types, "sqlType"),
+                    name,
+                })
+                .collect();
+            return Ok(None);
+        }
+        serde_json::from_value(object.into())
+            .map(Some)
+            .map_err(|source| DruidClientError::ParsingResponseError { source })
+    }
+
+    /// Decodes a single record of a csv response, `None` if the record was part of the header.
+    pub(crate) fn decode_record<T: DeserializeOwned>(
+        &mut self,
+        record: csv::StringRecord,
+    ) -> Result<Option<T>, DruidClientError> {
+        if let Some(header_row) = self.header_rows.pop() {
+            self.header_row(header_row, record.iter().map(String::from).collect());
+            return Ok(None);
+        }
+        let names = csv::StringRecord::from(
+            self.columns
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>(),
+        );
+        let headers = if names.is_empty() { None } else { Some(&names) };
+        record
+            .deserialize(headers)
+            .map(Some)
+            .map_err(|source| DruidClientError::ParsingCsvResponseError { source })
+    }
+
+    /// Decodes a single json row of an array format, `None` if the row was part of the header.
+    pub(crate) fn decode_value<T: DeserializeOwned>(
+        &mut self,
+        value: Value,
+    ) -> Result<Option<T>, DruidClientError> {
+        if let Some(header_row) = self.header_rows.pop() {
+            if let Value::Array(values) = value {
+                self.header_row(
+                    header_row,
+                    values
+                        .into_iter()
+                        .map(|v| v.as_str().unwrap_or_default().to_string())
+                        .collect(),
+                );
+            }
+            return Ok(None);
+        }
+        serde_json::from_value(value)
+            .map(Some)
+            .map_err(|source| DruidClientError::ParsingResponseError { source })
+    }
+
+    fn header_row(&mut self, row: HeaderRow, values: Vec<String>) {
+        match row {
+            HeaderRow::Names => {
+                self.columns = values
+                    .into_iter()
+                    .map(|name| SqlColumn {
+                        name,
+                        druid_type: None,
+                        sql_type: None,
+                    })
+                    .collect()
+            }
+            HeaderRow::DruidTypes => {
+                for (column, value) in self.columns.iter_mut().zip(values) {
+                    column.druid_type = Some(value);
+                }
+            }
+            HeaderRow::SqlTypes => {
+                for (column, value) in self.columns.iter_mut().zip(values) {
+                    column.sql_type = Some(value);
+                }
+            }
+        }
+    }
+}
+
+fn type_of(types: &Value, key: &str) -> Option<String> {
+    types.get(key).and_then(Value::as_str).map(String::from)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::top_n::TopNMetricSpec;
+    use serde_json::json;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Page {
+        page: String,
+        count: usize,
+    }
+
+    #[test]
+    fn test_serialize_sql_query() {
+        let query = SqlQuery::new("SELECT page FROM wikipedia")
+            .result_format(SqlResultFormat::ArrayLines)
+            .types_header(true);
+        assert_eq!(
+            serde_json::to_value(&query).unwrap(),
+            json!({
+                "query": "SELECT page FROM wikipedia",
+                "resultFormat": "arrayLines",
+                "header": true,
+                "typesHeader": true,
+                "sqlTypesHeader": false,
+                "context": {}
+            })
+        );
+    }
+
+    #[test]
+    fn test_serialize_parameters() {
+        let query = SqlQuery::new(
+            "SELECT * FROM wikipedia WHERE user = ? AND added > ? AND __time > ? AND page IN (?)",
+        )
+        .bind("Taffe316")
+        .bind(10)
+        .bind(SqlParameter::timestamp("2015-09-12 00:00:00"))
+        .bind(SqlParameter::array(vec!["Main", "Other"]));
+        assert_eq!(
+            serde_json::to_value(&query).unwrap()["parameters"],
+            json!([
+                {"type": "VARCHAR", "value": "Taffe316"},
+                {"type": "BIGINT", "value": 10},
+                {"type": "TIMESTAMP", "value": "2015-09-12 00:00:00"},
+                {"type": "ARRAY", "value": ["Main", "Other"]}
+            ])
+        );
+        assert!(query.check_parameters().is_ok());
+    }
+
+    const GROUP_BY: &str = r#"{"queryType":"groupBy","dataSource":{"type":"table","name":"wikipedia"},"intervals":{"type":"intervals","intervals":["-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z"]},"virtualColumns":[],"filter":{"type":"selector","dimension":"user","value":"Taffe316","extractionFn":null},"granularity":{"type":"all"},"dimensions":[{"type":"default","dimension":"page","outputName":"d0","outputType":"STRING"}],"aggregations":[{"type":"count","name":"a0"}],"postAggregations":[],"having":null,"limitSpec":{"type":"default","columns":[{"dimension":"a0","direction":"descending","dimensionOrder":{"type":"numeric"}}],"limit":10},"context":{"sqlOuterLimit":100,"sqlQueryId":"0f5b0d5c","useNativeQueryExplain":true},"descending":false}"#;
+
+    #[test]
+    fn test_explain_json_plan() {
+        let row = ExplainRow {
+            plan: format!(
+                r#"[{{"query":{},"signature":[{{"name":"d0","type":"STRING"}},{{"name":"a0","type":"LONG"}}]}}]"#,
+                GROUP_BY
+            ),
+            resources: Some(r#"[{"name":"wikipedia","type":"DATASOURCE"}]"#.into()),
+        };
+        let plan = row.into_plan().unwrap();
+        assert_eq!(plan.resources[0].name, "wikipedia");
+        assert_eq!(
+            plan.queries[0].signature[1].column_type.as_deref(),
+            Some("LONG")
+        );
+        match &plan.queries[0].query {
+            crate::query::Query::GroupBy(group_by) => {
+                assert_eq!(group_by.dimensions[0].output_name(), "d0");
+                assert_eq!(group_by.limit_spec.as_ref().unwrap().limit, 10);
+                assert_eq!(group_by.context["sqlOuterLimit"], "100");
+            }
+            other => panic!("unexpected query {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_explain_subtotals() {
+        let query = GROUP_BY.replace(r#""having":null"#, r#""subtotalsSpec":[["d0"],[]]"#);
+        let row = ExplainRow {
+            plan: format!(r#"[{{"query":{},"signature":[]}}]"#, query),
+            resources: None,
+        };
+        let plan = row.into_plan().unwrap();
+        match &plan.queries[0].query {
+            crate::query::Query::GroupBy(group_by) => {
+                assert_eq!(group_by.subtotal_spec, vec![vec!["d0".to_string()], vec![]]);
+                assert_eq!(
+                    serde_json::to_value(group_by).unwrap()["subtotalsSpec"],
+                    json!([["d0"], []])
+                );
+            }
+            other => panic!("unexpected query {:?}", other),
+        }
+    }
+
+    fn explain_top_n(fields: &str) -> Result<ExplainPlan, DruidClientError> {
+        let query = format!(
+            r#"{{"queryType":"topN","dataSource":{{"type":"table","name":"wikipedia"}},"virtualColumns":[],"dimension":{{"type":"default","dimension":"page","outputName":"d0","outputType":"STRING"}},"threshold":10,"intervals":{{"type":"intervals","intervals":["{}"]}},"granularity":{{"type":"all"}},"aggregations":[{{"type":"count","name":"a0"}}],"postAggregations":[],"context":{{}},{}}}"#,
+            "2015-09-12/2015-09-13", fields
+        );
+        ExplainRow {
+            plan: format!(r#"[{{"query":{},"signature":[]}}]"#, query),
+            resources: None,
+        }
+        .into_plan()
+    }
+
+    #[test]
+    fn test_explain_top_n_metric() {
+        let plan = explain_top_n(r#""metric":{"type":"inverted","metric":"a0"}"#).unwrap();
+        match &plan.queries[0].query {
+            crate::query::Query::TopN(top_n) => {
+                assert_eq!(top_n.metric, TopNMetricSpec::inverted("a0".into()))
+            }
+            other => panic!("unexpected query {:?}", other),
+        }
+
+        let plan = explain_top_n(
+            r#""metric":{"type":"dimension","ordering":{"type":"alphanumeric"},"previousStop":"b"}"#,
+        )
+        .unwrap();
+        let top_n = match plan.queries.into_iter().next().unwrap().query {
+            crate::query::Query::TopN(top_n) => top_n,
+            other => panic!("unexpected query {:?}", other),
+        };
+        assert_eq!(
+            serde_json::to_value(&top_n).unwrap()["metric"],
+            json!({"type": "dimension", "ordering": "alphanumeric", "previousStop": "b"})
+        );
+    }
+
+    #[test]
+    fn test_explain_top_n_filter() {
+        let plan = explain_top_n(
+            r#""metric":"a0","filter":{"type":"selector","dimension":"user","value":"Taffe316"}"#,
+        )
+        .unwrap();
+        match &plan.queries[0].query {
+            crate::query::Query::TopN(top_n) => assert!(matches!(
+                &top_n.filter,
+                Some(crate::query::definitions::Filter::Selector { value, .. }) if value == "Taffe316"
+            )),
+            other => panic!("unexpected query {:?}", other),
+        }
+        assert!(explain_top_n(r#""metric":"a0","filter":null"#).is_ok());
+    }
+
+    #[test]
+    fn test_explain_unsupported_fields() {
+        let unsupported = [
+            r#""metric":{"type":"numeric","metric":"a0","extra":1}"#,
+            r#""metric":"a0","virtualColumns":[{"type":"expression","name":"v0","expression":"1"}]"#,
+        ];
+        for fields in unsupported.iter() {
+            match explain_top_n(fields) {
+                Err(DruidClientError::ParsingResponseError { .. }) => (),
+                other => panic!("unexpected {:?} for {}", other, fields),
+            }
+        }
+    }
+
+    #[test]
+    fn test_explain_text_plan() {
+        let row = ExplainRow {
+            plan: format!(
+                "DruidQueryRel(query=[{}], signature=[{{d0:STRING, a0:LONG}}])\n",
+                GROUP_BY
+            ),
+            resources: None,
+        };
+        let plan = row.into_plan().unwrap();
+        assert_eq!(plan.queries.len(), 1);
+        assert_eq!(
+            plan.queries[0].signature,
+            vec![
+                SignatureColumn {
+                    name: "d0".into(),
+                    column_type: Some("STRING".into())
+                },
+                SignatureColumn {
+                    name: "a0".into(),
+                    column_type: Some("LONG".into())
+                },
+            ]
+        );
+        assert!(plan.resources.is_empty());
+    }
+
+    #[test]
+    fn test_placeholder_count() {
+        let query = SqlQuery::new(
+            "SELECT 'why?', \"what?\" -- really?\n FROM t /* huh? */ WHERE a = ? AND b = 'it''s?' AND c = ?",
+        );
+        assert_eq!(query.placeholder_count(), 2);
+        match query.bind(1).check_parameters() {
+            Err(DruidClientError::SqlParametersMismatch {
+                placeholders: 2,
+                parameters: 1,
+            }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_object_header() {
+        let query = SqlQuery::new("").types_header(true).sql_types_header(true);
+        let mut decoder = SqlRowDecoder::new(&query);
+        let header = r#"{"page": {"type": "STRING", "sqlType": "VARCHAR"}, "count": {"type": "LONG", "sqlType": "BIGINT"}}"#;
+        assert_eq!(decoder.decode_line::<Page>(header).unwrap(), None);
+        assert!(!decoder.expects_header());
+        assert_eq!(decoder.columns[1].name, "count");
+        assert_eq!(decoder.columns[1].druid_type.as_deref(), Some("LONG"));
+        assert_eq!(decoder.columns[1].sql_type.as_deref(), Some("BIGINT"));
+
+        let row = decoder.decode_line::<Page>(r#"{"page": "Main", "count": 3}"#);
+        assert_eq!(
+            row.unwrap(),
+            Some(Page {
+                page: "Main".into(),
+                count: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_array_lines_header() {
+        let query = SqlQuery::new("")
+            .result_format(SqlResultFormat::ArrayLines)
+            .types_header(true)
+            .sql_types_header(true);
+        let mut decoder = SqlRowDecoder::new(&query);
+        for line in &[
+            r#"["page","count"]"#,
+            r#"["STRING","LONG"]"#,
+            r#"["VARCHAR","BIGINT"]"#,
+        ] {
+            assert_eq!(decoder.decode_line::<Page>(line).unwrap(), None);
+        }
+        assert_eq!(decoder.columns[0].druid_type.as_deref(), Some("STRING"));
+        assert_eq!(decoder.columns[0].sql_type.as_deref(), Some("VARCHAR"));
+        let row = decoder
+            .decode_line::<(String, usize)>(r#"["Main",3]"#)
+            .unwrap();
+        assert_eq!(row, Some(("Main".to_string(), 3)));
+        assert_eq!(decoder.decode_line::<Page>("").unwrap(), None);
+    }
+
+    #[test]
+    fn test_csv_rows_by_header_names() {
+        let query = SqlQuery::new("")
+            .result_format(SqlResultFormat::Csv)
+            .header(true);
+        let mut decoder = SqlRowDecoder::new(&query);
+        let header = csv::StringRecord::from(vec!["count", "page"]);
+        assert_eq!(decoder.decode_record::<Page>(header).unwrap(), None);
+        let row = csv::StringRecord::from(vec!["3", "Main, Page"]);
+        let row = decoder.decode_record::<Page>(row).unwrap();
+        assert_eq!(
+            row,
+            Some(Page {
+                page: "Main, Page".into(),
+                count: 3
+            })
+        );
+    }
+}
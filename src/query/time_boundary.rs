@@ -1,5 +1,6 @@
 use super::definitions::Filter;
 use super::DataSource;
+use crate::serialization::string_map;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -7,17 +8,19 @@ use serde::{Deserialize, Serialize};
 #[serde(rename_all = "camelCase")]
 pub struct TimeBoundary {
     pub data_source: DataSource,
-    #[serde(skip_serializing_if = "TimeBoundType::is_both")]
+    #[serde(skip_serializing_if = "TimeBoundType::is_both", default)]
     pub bound: TimeBoundType,
     pub filter: Option<Filter>,
+    #[serde(default, deserialize_with = "string_map")]
     pub context: std::collections::HashMap<String, String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub enum TimeBoundType {
     MaxTime,
     MinTime,
+    #[default]
     MinMaxTime,
 }
 
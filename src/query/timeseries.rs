@@ -3,6 +3,7 @@ use super::definitions::Granularity;
 use super::group_by::PostAggregation;
 use super::DataSource;
 use crate::query::definitions::Aggregation;
+use crate::serialization::{intervals, string_map, tagged_or_untagged};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -10,12 +11,17 @@ use serde::{Deserialize, Serialize};
 #[serde(tag = "queryType", rename = "timeseries")]
 pub struct Timeseries {
     pub data_source: DataSource,
+    #[serde(deserialize_with = "tagged_or_untagged", default)]
     pub granularity: Granularity,
+    #[serde(default)]
     pub descending: bool,
+    #[serde(deserialize_with = "intervals")]
     pub intervals: Vec<String>,
     pub filter: Option<Filter>,
     pub aggregations: Vec<Aggregation>,
+    #[serde(default)]
     pub post_aggregations: Vec<PostAggregation>,
     pub limit: Option<usize>,
+    #[serde(default, deserialize_with = "string_map")]
     pub context: std::collections::HashMap<String, String>,
 }
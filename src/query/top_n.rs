@@ -1,8 +1,13 @@
 use super::definitions::Aggregation;
 use super::definitions::Dimension;
+use super::definitions::Filter;
 use super::definitions::Granularity;
+use super::definitions::SortingOrder;
+use super::group_by::PostAggregation;
 use super::DataSource;
-use serde::{Deserialize, Serialize};
+use crate::serialization::{intervals, string_map, tagged_or_untagged};
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -12,9 +17,76 @@ pub struct TopN {
     pub data_source: DataSource,
     pub dimension: Dimension,
     pub threshold: usize,
-    pub metric: String,
+    #[serde(deserialize_with = "metric_spec")]
+    pub metric: TopNMetricSpec,
     pub aggregations: Vec<Aggregation>,
+    #[serde(default)]
+    pub post_aggregations: Vec<PostAggregation>,
+    #[serde(deserialize_with = "intervals")]
     pub intervals: Vec<String>,
+    pub filter: Option<Filter>,
+    #[serde(deserialize_with = "tagged_or_untagged", default)]
     pub granularity: Granularity,
+    #[serde(default, deserialize_with = "string_map")]
     pub context: std::collections::HashMap<String, String>,
 }
+
+/// How TopN orders the dimension values, Druid also accepts a plain metric name for `Numeric`.
+#[rustfmt::skip]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum TopNMetricSpec {
+    Numeric { metric: String },
+    #[serde(rename_all = "camelCase")]
+    Dimension {
+        #[serde(deserialize_with = "tagged_or_untagged", default)]
+        ordering: SortingOrder,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous_stop: Option<String>,
+    },
+    Inverted {
+        #[serde(deserialize_with = "metric_spec")]
+        metric: Box<TopNMetricSpec>,
+    },
+}
+
+impl TopNMetricSpec {
+    pub fn numeric(metric: &str) -> Self {
+        TopNMetricSpec::Numeric {
+            metric: metric.to_string(),
+        }
+    }
+
+    pub fn dimension(ordering: SortingOrder) -> Self {
+        TopNMetricSpec::Dimension {
+            ordering,
+            previous_stop: None,
+        }
+    }
+
+    pub fn inverted(metric: TopNMetricSpec) -> Self {
+        TopNMetricSpec::Inverted {
+            metric: Box::new(metric),
+        }
+    }
+}
+
+impl From<&str> for TopNMetricSpec {
+    fn from(metric: &str) -> Self {
+        TopNMetricSpec::numeric(metric)
+    }
+}
+
+fn metric_spec<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: From<TopNMetricSpec>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(metric) => Ok(TopNMetricSpec::Numeric { metric }.into()),
+        spec => serde_json::from_value::<TopNMetricSpec>(spec)
+            .map(T::from)
+            .map_err(de::Error::custom),
+    }
+}
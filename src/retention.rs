@@ -0,0 +1,306 @@
+//! Typed [retention rules](https://druid.apache.org/docs/latest/operations/rule-configuration.html) and
+//! [auto-compaction](https://druid.apache.org/docs/latest/data-management/automatic-compaction.html) configs
+//! of a datasource, managed through the [`CoordinatorClient`](crate::coordinator::CoordinatorClient).
+//!
+//! [`DatasourceLifecycle`] describes the desired configuration, `apply_lifecycle` changes
+//! only what differs from the current one so it can be applied repeatedly.
+//!
+//! ```no_run
+//! use druid_io::coordinator::CoordinatorClient;
+//! use druid_io::retention::{CompactionConfig, DatasourceLifecycle, Rule};
+//!
+//! # async fn example() {
+//! let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+//! let lifecycle = DatasourceLifecycle::new("wikipedia")
+//!     .rules(vec![Rule::load_by_period("P1M", 2), Rule::DropForever])
+//!     .compaction(CompactionConfig::new("wikipedia").skip_offset_from_latest("P1D"));
+//! let applied = coordinator.apply_lifecycle(&lifecycle).await.unwrap();
+//! # }
+//! ```
+use crate::serialization::is_subset;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Tier of the historicals without `druid.server.tier` configured.
+pub const DEFAULT_HISTORICAL_TIER: &str = "_default_tier";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Rule {
+    #[serde(rename_all = "camelCase")]
+    LoadForever {
+        #[serde(default)]
+        tiered_replicants: HashMap<String, usize>,
+    },
+    #[serde(rename_all = "camelCase")]
+    LoadByInterval {
+        interval: String,
+        #[serde(default)]
+        tiered_replicants: HashMap<String, usize>,
+    },
+    #[serde(rename_all = "camelCase")]
+    LoadByPeriod {
+        period: String,
+        #[serde(default = "include_future")]
+        include_future: bool,
+        #[serde(default)]
+        tiered_replicants: HashMap<String, usize>,
+    },
+    DropForever,
+    DropByInterval {
+        interval: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    DropByPeriod {
+        period: String,
+        #[serde(default = "include_future")]
+        include_future: bool,
+    },
+    DropBeforeByPeriod {
+        period: String,
+    },
+    BroadcastForever,
+    BroadcastByInterval {
+        interval: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    BroadcastByPeriod {
+        period: String,
+        #[serde(default = "include_future")]
+        include_future: bool,
+    },
+}
+
+fn include_future() -> bool {
+    true
+}
+
+impl Rule {
+    /// Keeps `replicants` copies of segments newer than `period` (e.g. `P1M`) on the default tier.
+    pub fn load_by_period(period: &str, replicants: usize) -> Self {
+        Rule::LoadByPeriod {
+            period: period.to_string(),
+            include_future: true,
+            tiered_replicants: default_tier(replicants),
+        }
+    }
+
+    pub fn load_by_interval(interval: &str, replicants: usize) -> Self {
+        Rule::LoadByInterval {
+            interval: interval.to_string(),
+            tiered_replicants: default_tier(replicants),
+        }
+    }
+
+    pub fn load_forever(replicants: usize) -> Self {
+        Rule::LoadForever {
+            tiered_replicants: default_tier(replicants),
+        }
+    }
+
+    pub fn drop_by_period(period: &str) -> Self {
+        Rule::DropByPeriod {
+            period: period.to_string(),
+            include_future: true,
+        }
+    }
+
+    pub fn drop_before_by_period(period: &str) -> Self {
+        Rule::DropBeforeByPeriod {
+            period: period.to_string(),
+        }
+    }
+}
+
+fn default_tier(replicants: usize) -> HashMap<String, usize> {
+    let mut tiers = HashMap::new();
+    tiers.insert(DEFAULT_HISTORICAL_TIER.to_string(), replicants);
+    tiers
+}
+
+/// Auto-compaction config of a datasource. Unset fields are left to coordinator defaults
+/// and aren't taken into account when comparing with the current config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactionConfig {
+    pub data_source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_priority: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_segment_size_bytes: Option<u64>,
+    /// ISO 8601 period of most recent data left uncompacted, druid's default is `P1D`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_offset_from_latest: Option<String>,
+    /// Compaction tuning config, e.g. `{"partitionsSpec": {"type": "dynamic"}}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tuning_config: Option<Value>,
+    /// e.g. `{"segmentGranularity": "DAY"}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub granularity_spec: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_context: Option<HashMap<String, Value>>,
+}
+
+impl CompactionConfig {
+    pub fn new(data_source: &str) -> Self {
+        CompactionConfig {
+            data_source: data_source.to_string(),
+            task_priority: None,
+            input_segment_size_bytes: None,
+            skip_offset_from_latest: None,
+            tuning_config: None,
+            granularity_spec: None,
+            task_context: None,
+        }
+    }
+
+    pub fn skip_offset_from_latest(mut self, period: &str) -> Self {
+        self.skip_offset_from_latest = Some(period.to_string());
+        self
+    }
+
+    pub fn tuning_config(mut self, tuning_config: Value) -> Self {
+        self.tuning_config = Some(tuning_config);
+        self
+    }
+
+    pub fn granularity_spec(mut self, granularity_spec: Value) -> Self {
+        self.granularity_spec = Some(granularity_spec);
+        self
+    }
+
+    /// Whether every field set in this config has the same value in `current`.
+    pub fn is_satisfied_by(&self, current: &CompactionConfig) -> bool {
+        match (serde_json::to_value(self), serde_json::to_value(current)) {
+            (Ok(desired), Ok(current)) => is_subset(&desired, &current),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CompactionConfigs {
+    #[serde(default)]
+    pub(crate) compaction_configs: Vec<CompactionConfig>,
+}
+
+/// Desired retention and compaction of a datasource, `None` leaves the current setting as is.
+#[derive(Debug, Clone)]
+pub struct DatasourceLifecycle {
+    pub data_source: String,
+    pub rules: Option<Vec<Rule>>,
+    pub compaction: Option<CompactionConfig>,
+}
+
+impl DatasourceLifecycle {
+    pub fn new(data_source: &str) -> Self {
+        DatasourceLifecycle {
+            data_source: data_source.to_string(),
+            rules: None,
+            compaction: None,
+        }
+    }
+
+    pub fn rules(mut self, rules: Vec<Rule>) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
+    pub fn compaction(mut self, compaction: CompactionConfig) -> Self {
+        self.compaction = Some(compaction);
+        self
+    }
+
+    /// Changes needed to get from the current configuration to this one.
+    pub fn diff(
+        &self,
+        current_rules: &[Rule],
+        current_compaction: Option<&CompactionConfig>,
+    ) -> Vec<LifecycleChange> {
+        let mut changes = vec![];
+        if let Some(rules) = &self.rules {
+            if rules.as_slice() != current_rules {
+                changes.push(LifecycleChange::SetRules {
+                    current: current_rules.to_vec(),
+                    desired: rules.clone(),
+                });
+            }
+        }
+        if let Some(compaction) = &self.compaction {
+            let satisfied = current_compaction
+                .map(|current| compaction.is_satisfied_by(current))
+                .unwrap_or(false);
+            if !satisfied {
+                changes.push(LifecycleChange::SetCompaction {
+                    current: current_compaction.cloned().map(Box::new),
+                    desired: Box::new(compaction.clone()),
+                });
+            }
+        }
+        changes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifecycleChange {
+    SetRules {
+        current: Vec<Rule>,
+        desired: Vec<Rule>,
+    },
+    SetCompaction {
+        current: Option<Box<CompactionConfig>>,
+        desired: Box<CompactionConfig>,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_deserialize_rules() {
+        let str = r#"
+            [
+                {"type": "loadByPeriod", "period": "P1M", "includeFuture": true, "tieredReplicants": {"_default_tier": 2}, "useDefaultTierForNull": true},
+                {"type": "dropForever"}
+            ]
+        "#;
+        let rules = serde_json::from_str::<Vec<Rule>>(str).unwrap();
+        assert_eq!(
+            rules,
+            vec![Rule::load_by_period("P1M", 2), Rule::DropForever]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let lifecycle = DatasourceLifecycle::new("wikipedia")
+            .rules(vec![Rule::load_by_period("P1M", 2), Rule::DropForever])
+            .compaction(
+                CompactionConfig::new("wikipedia")
+                    .skip_offset_from_latest("P1D")
+                    .tuning_config(json!({"partitionsSpec": {"type": "dynamic"}})),
+            );
+        let current = serde_json::from_value::<CompactionConfig>(json!({
+            "dataSource": "wikipedia",
+            "taskPriority": 25,
+            "inputSegmentSizeBytes": 100000000000000u64,
+            "skipOffsetFromLatest": "P1D",
+            "tuningConfig": {"partitionsSpec": {"type": "dynamic", "maxRowsPerSegment": 5000000}}
+        }))
+        .unwrap();
+
+        let rules = vec![Rule::load_by_period("P1M", 2), Rule::DropForever];
+        assert_eq!(lifecycle.diff(&rules, Some(&current)), vec![]);
+
+        let changes = lifecycle.diff(&[Rule::load_forever(2)], None);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(
+            &changes[1],
+            LifecycleChange::SetCompaction { current: None, .. }
+        ));
+    }
+}
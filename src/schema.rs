@@ -0,0 +1,239 @@
+//! Tables and column types from Druid SQL
+//! [`INFORMATION_SCHEMA`](https://druid.apache.org/docs/latest/querying/sql.html#information-schema),
+//! cheaper than running a SegmentMetadata query over all intervals.
+//!
+//! ```no_run
+//! use druid_io::client::DruidClient;
+//!
+//! # async fn example() {
+//! let client = DruidClient::new(vec!["localhost:8082".to_string()]);
+//! let schema = client.table_schema("wikipedia").await.unwrap();
+//! // struct definition to paste into code
+//! println!("{}", schema.to_rust_struct());
+//! # }
+//! ```
+use crate::client::{ClientResult, DruidClient};
+use crate::query::sql::SqlQuery;
+use serde::{Deserialize, Serialize};
+
+/// Schema of the datasources.
+pub const DRUID_SCHEMA: &str = "druid";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TableInfo {
+    #[serde(rename = "TABLE_SCHEMA")]
+    pub schema: String,
+    #[serde(rename = "TABLE_NAME")]
+    pub name: String,
+    /// `TABLE` or `SYSTEM_TABLE`
+    #[serde(rename = "TABLE_TYPE")]
+    pub table_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    pub schema: String,
+    pub name: String,
+    pub columns: Vec<ColumnSchema>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColumnSchema {
+    #[serde(rename = "COLUMN_NAME")]
+    pub name: String,
+    /// SQL type, e.g. `VARCHAR`, `BIGINT`, `TIMESTAMP`; complex metrics are `OTHER`
+    #[serde(rename = "DATA_TYPE")]
+    pub data_type: String,
+    #[serde(rename = "IS_NULLABLE", deserialize_with = "yes_no")]
+    pub nullable: bool,
+}
+
+fn yes_no<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(String::deserialize(deserializer)? == "YES")
+}
+
+impl ColumnSchema {
+    /// Rust type the column deserializes into from a Druid SQL response.
+    pub fn rust_type(&self) -> String {
+        let data_type = self.data_type.to_uppercase();
+        let rust_type = match data_type.as_str() {
+            "BIGINT" | "INTEGER" | "SMALLINT" | "TINYINT" => "i64".to_string(),
+            "DOUBLE" | "DECIMAL" | "REAL" => "f64".to_string(),
+            "FLOAT" => "f32".to_string(),
+            "BOOLEAN" => "bool".to_string(),
+            // timestamps are serialized as ISO 8601 strings
+            "VARCHAR" | "CHAR" | "TIMESTAMP" | "DATE" => "String".to_string(),
+            array if array.ends_with(" ARRAY") => {
+                let element = ColumnSchema {
+                    name: self.name.clone(),
+                    data_type: array.trim_end_matches(" ARRAY").to_string(),
+                    nullable: false,
+                };
+                format!("Vec<{}>", element.rust_type())
+            }
+            _ => "serde_json::Value".to_string(),
+        };
+        if self.nullable && rust_type != "serde_json::Value" {
+            format!("Option<{}>", rust_type)
+        } else {
+            rust_type
+        }
+    }
+}
+
+impl TableSchema {
+    /// Definition of a struct named after the table with a field per column.
+    pub fn to_rust_struct(&self) -> String {
+        let mut definition = format!(
+            "#[derive(Serialize, Deserialize, Debug)]\npub struct {} {{\n",
+            type_name(&self.name)
+        );
+        for column in &self.columns {
+            let field = field_name(&column.name);
+            if field.trim_start_matches("r#") != column.name {
+                definition.push_str(&format!("    #[serde(rename = {:?})]\n", column.name));
+            }
+            definition.push_str(&format!("    pub {}: {},\n", field, column.rust_type()));
+        }
+        definition.push_str("}\n");
+        definition
+    }
+}
+
+fn words(name: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut previous_lowercase = false;
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            previous_lowercase = false;
+            continue;
+        }
+        if c.is_ascii_uppercase() && previous_lowercase {
+            words.push(std::mem::take(&mut word));
+        }
+        previous_lowercase = c.is_ascii_lowercase() || c.is_ascii_digit();
+        word.push(c.to_ascii_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn type_name(table: &str) -> String {
+    let name: String = words(table)
+        .iter()
+        .map(|word| word[..1].to_uppercase() + &word[1..])
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("Table{}", name),
+    }
+}
+
+fn field_name(column: &str) -> String {
+    let name = words(column).join("_");
+    match name.as_str() {
+        "" => "column".to_string(),
+        name if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", name),
+        "type" | "match" | "ref" | "use" | "where" | "in" | "fn" | "loop" | "move" | "self"
+        | "super" | "crate" | "mod" | "struct" | "enum" | "trait" | "impl" | "let" | "mut"
+        | "static" | "const" | "return" | "async" | "await" | "dyn" | "as" | "box" | "true"
+        | "false" | "if" | "else" | "for" | "while" | "break" | "continue" | "extern" | "pub"
+        | "unsafe" | "priv" | "final" | "override" | "virtual" | "yield" | "try" => {
+            format!("r#{}", name)
+        }
+        name => name.to_string(),
+    }
+}
+
+impl DruidClient {
+    /// Tables of the schema, e.g. [`DRUID_SCHEMA`], or of all schemas.
+    pub async fn tables(&self, schema: Option<&str>) -> ClientResult<Vec<TableInfo>> {
+        let mut sql = "SELECT TABLE_SCHEMA, TABLE_NAME, TABLE_TYPE FROM INFORMATION_SCHEMA.TABLES"
+            .to_string();
+        if schema.is_some() {
+            sql.push_str(" WHERE TABLE_SCHEMA = ?");
+        }
+        sql.push_str(" ORDER BY TABLE_SCHEMA, TABLE_NAME");
+        let mut query = SqlQuery::new(&sql);
+        if let Some(schema) = schema {
+            query = query.bind(schema);
+        }
+        Ok(self.sql::<TableInfo>(&query).await?.rows)
+    }
+
+    /// Columns of the datasource, in order.
+    pub async fn table_schema(&self, data_source: &str) -> ClientResult<TableSchema> {
+        self.schema_of(DRUID_SCHEMA, data_source).await
+    }
+
+    pub async fn schema_of(&self, schema: &str, table: &str) -> ClientResult<TableSchema> {
+        let query = SqlQuery::new(
+            "SELECT COLUMN_NAME, DATA_TYPE, IS_NULLABLE FROM INFORMATION_SCHEMA.COLUMNS \
+             WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ? ORDER BY ORDINAL_POSITION",
+        )
+        .bind(schema)
+        .bind(table);
+        let columns = self.sql::<ColumnSchema>(&query).await?.rows;
+        Ok(TableSchema {
+            schema: schema.to_string(),
+            name: table.to_string(),
+            columns,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn column(name: &str, data_type: &str, nullable: bool) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable,
+        }
+    }
+
+    #[test]
+    fn test_rust_struct() {
+        let schema = TableSchema {
+            schema: DRUID_SCHEMA.into(),
+            name: "wikipedia".into(),
+            columns: vec![
+                column("__time", "TIMESTAMP", false),
+                column("isRobot", "VARCHAR", true),
+                column("type", "VARCHAR", true),
+                column("added", "BIGINT", false),
+                column("tags", "VARCHAR ARRAY", true),
+                column("unique_users", "OTHER", true),
+            ],
+        };
+        assert_eq!(
+            schema.to_rust_struct(),
+            r#"#[derive(Serialize, Deserialize, Debug)]
+pub struct Wikipedia {
+    #[serde(rename = "__time")]
+    pub time: String,
+    #[serde(rename = "isRobot")]
+    pub is_robot: Option<String>,
+    pub r#type: Option<String>,
+    pub added: i64,
+    pub tags: Option<Vec<String>>,
+    pub unique_users: serde_json::Value,
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_type_name() {
+        assert_eq!(type_name("kttm-nested"), "KttmNested");
+        assert_eq!(type_name("2020_sales"), "Table2020Sales");
+    }
+}
@@ -1,6 +1,8 @@
+use serde::de::DeserializeOwned;
 use serde::de::IntoDeserializer;
 use serde::de::{self, MapAccess, Visitor};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
 use std::fmt;
 use std::marker::PhantomData;
 
@@ -50,12 +52,18 @@ where
         where
             M: MapAccess<'de>,
         {
-            while let Some((key, value)) = map.next_entry::<String, String>()? {
+            let mut tag = None;
+            while let Some(key) = map.next_key::<String>()? {
                 if key == "type" {
-                    return self.visit_str(&value);
+                    tag = Some(map.next_value::<String>()?);
+                } else {
+                    map.next_value::<de::IgnoredAny>()?;
                 }
             }
-            Err(de::Error::missing_field("type"))
+            match tag {
+                Some(tag) => self.visit_str(&tag),
+                None => Err(de::Error::missing_field("type")),
+            }
         }
         fn visit_unit<E>(self) -> Result<Self::Value, E>
         where
@@ -68,6 +76,137 @@ where
     deserializer.deserialize_any(StringOrStruct(PhantomData))
 }
 
+/// Segment dimensions and metrics are reported as a single comma separated string.
+pub(crate) fn comma_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let str = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    Ok(str
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Accepts both plain list of intervals and druid's `{"type": "intervals", "intervals": [..]}` spec.
+pub(crate) fn intervals<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Intervals {
+        List(Vec<String>),
+        Spec { intervals: Vec<String> },
+    }
+    Ok(match Intervals::deserialize(deserializer)? {
+        Intervals::List(intervals) | Intervals::Spec { intervals } => intervals,
+    })
+}
+
+/// Query context as returned by druid may contain numbers and booleans, those are kept as strings.
+pub(crate) fn string_map<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let map = Option::<HashMap<String, serde_json::Value>>::deserialize(deserializer)?;
+    Ok(map
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(key, value)| match value {
+            serde_json::Value::String(str) => (key, str),
+            other => (key, other.to_string()),
+        })
+        .collect())
+}
+
+/// Druid reports an absent limit spec as `{"type": "NoopLimitSpec"}`.
+pub(crate) fn noop_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(value) if value.get("type").and_then(|t| t.as_str()) == Some("NoopLimitSpec") => {
+            Ok(None)
+        }
+        Some(value) => serde_json::from_value(value)
+            .map(Some)
+            .map_err(de::Error::custom),
+    }
+}
+
+/// A json object with its keys in document order, `serde_json::Map` sorts them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct OrderedObject(pub(crate) Vec<(String, serde_json::Value)>);
+
+impl<'de> Deserialize<'de> for OrderedObject {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ObjectVisitor;
+
+        impl<'de> Visitor<'de> for ObjectVisitor {
+            type Value = OrderedObject;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a json object")
+            }
+
+            fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<OrderedObject, M::Error> {
+                let mut entries = vec![];
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(OrderedObject(entries))
+            }
+        }
+
+        deserializer.deserialize_map(ObjectVisitor)
+    }
+}
+
+impl Serialize for OrderedObject {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+    }
+}
+
+impl From<OrderedObject> for serde_json::Value {
+    fn from(object: OrderedObject) -> Self {
+        serde_json::Value::Object(object.0.into_iter().collect())
+    }
+}
+
+/// Flags of druid `sys` tables are `BIGINT` 0 or 1, `null` counts as false.
+pub(crate) fn int_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::Bool(flag)) => Ok(flag),
+        Some(serde_json::Value::Number(n)) => Ok(n.as_i64() != Some(0)),
+        Some(serde_json::Value::String(str)) => Ok(str == "1" || str == "true"),
+        _ => Ok(false),
+    }
+}
+
+/// Every field of `desired` objects is in `current` with the same value, other values are equal.
+pub(crate) fn is_subset(desired: &serde_json::Value, current: &serde_json::Value) -> bool {
+    match (desired, current) {
+        (serde_json::Value::Object(desired), serde_json::Value::Object(current)) => {
+            desired.iter().all(|(key, value)| {
+                current
+                    .get(key)
+                    .map(|current| is_subset(value, current))
+                    .unwrap_or(false)
+            })
+        }
+        _ => desired == current,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -129,4 +268,40 @@ mod test {
         let test_struct = serde_json::from_str::<Wrap>(str);
         assert_eq!(test_struct.unwrap().field, Tagged::Two)
     }
+
+    #[derive(Deserialize, Debug)]
+    struct Lenient {
+        #[serde(deserialize_with = "intervals")]
+        intervals: Vec<String>,
+        #[serde(default, deserialize_with = "string_map")]
+        context: HashMap<String, String>,
+    }
+
+    #[test]
+    fn test_lenient_query_fields() {
+        let str = r#"
+            {
+                "intervals": {"type": "intervals", "intervals": ["2015-09-12/2015-09-13"]},
+                "context": {"sqlOuterLimit": 100, "useCache": false, "queryId": "q1", "nothing": null}
+            }
+       "#;
+        let lenient = serde_json::from_str::<Lenient>(str).unwrap();
+        assert_eq!(lenient.intervals, vec!["2015-09-12/2015-09-13"]);
+        assert_eq!(lenient.context["sqlOuterLimit"], "100");
+        assert_eq!(lenient.context["useCache"], "false");
+        assert_eq!(lenient.context["queryId"], "q1");
+        assert!(!lenient.context.contains_key("nothing"));
+    }
+
+    #[test]
+    fn test_tagged_with_extra_fields() {
+        let str = r#"
+            {
+                "field": {"type": "Two", "timeZone": null}
+            }
+       "#;
+
+        let test_struct = serde_json::from_str::<Wrap>(str);
+        assert_eq!(test_struct.unwrap().field, Tagged::Two)
+    }
 }
@@ -0,0 +1,371 @@
+//! Typed [Kafka](https://druid.apache.org/docs/latest/development/extensions-core/kafka-ingestion.html)
+//! and [Kinesis](https://druid.apache.org/docs/latest/development/extensions-core/kinesis-ingestion.html)
+//! supervisor specs for streaming ingestion, managed through the [`OverlordClient`](crate::overlord::OverlordClient).
+//!
+//! ```no_run
+//! use druid_io::ingestion::*;
+//! use druid_io::overlord::OverlordClient;
+//! use druid_io::query::definitions::{Aggregation, Granularity};
+//! use druid_io::supervisor::*;
+//!
+//! # async fn example() {
+//! let supervisor = SupervisorSpec::kafka(
+//!     DataSchema {
+//!         data_source: "wikipedia".into(),
+//!         timestamp_spec: TimestampSpec::new("time", "auto"),
+//!         dimensions_spec: DimensionsSpec::names(vec!["channel", "page", "user"]),
+//!         metrics_spec: vec![Aggregation::count("count")],
+//!         granularity_spec: GranularitySpec::uniform(Granularity::Hour, Granularity::None),
+//!         transform_spec: None,
+//!     },
+//!     KafkaIoConfig::new("wikipedia", "localhost:9092", InputFormat::Json {}),
+//! );
+//! let overlord = OverlordClient::new(vec!["localhost:8090".to_string()]);
+//! let supervisor_id = overlord.submit_supervisor(&supervisor).await.unwrap();
+//! let status = overlord.supervisor_status(&supervisor_id).await.unwrap();
+//! # }
+//! ```
+use crate::ingestion::{DataSchema, InputFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SupervisorType {
+    Kafka,
+    Kinesis,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SupervisorSpec {
+    Kafka {
+        spec: StreamingSpec<KafkaIoConfig>,
+    },
+    Kinesis {
+        spec: StreamingSpec<KinesisIoConfig>,
+    },
+}
+
+impl SupervisorSpec {
+    pub fn kafka(data_schema: DataSchema, io_config: KafkaIoConfig) -> Self {
+        SupervisorSpec::Kafka {
+            spec: StreamingSpec {
+                data_schema,
+                io_config,
+                tuning_config: None,
+            },
+        }
+    }
+
+    pub fn kinesis(data_schema: DataSchema, io_config: KinesisIoConfig) -> Self {
+        SupervisorSpec::Kinesis {
+            spec: StreamingSpec {
+                data_schema,
+                io_config,
+                tuning_config: None,
+            },
+        }
+    }
+
+    pub fn data_source(&self) -> &str {
+        match self {
+            SupervisorSpec::Kafka { spec } => &spec.data_schema.data_source,
+            SupervisorSpec::Kinesis { spec } => &spec.data_schema.data_source,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamingSpec<T> {
+    pub data_schema: DataSchema,
+    pub io_config: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tuning_config: Option<StreamingTuningConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KafkaIoConfig {
+    pub topic: String,
+    /// Kafka consumer properties, `bootstrap.servers` is required
+    pub consumer_properties: HashMap<String, serde_json::Value>,
+    pub input_format: InputFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<usize>,
+    /// ISO 8601 period, e.g. `PT1H`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_duration: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_delay: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_earliest_offset: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub late_message_rejection_period: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub early_message_rejection_period: Option<String>,
+}
+
+impl KafkaIoConfig {
+    pub fn new(topic: &str, bootstrap_servers: &str, input_format: InputFormat) -> Self {
+        let mut consumer_properties = HashMap::new();
+        consumer_properties.insert(
+            "bootstrap.servers".to_string(),
+            serde_json::Value::String(bootstrap_servers.to_string()),
+        );
+        KafkaIoConfig {
+            topic: topic.to_string(),
+            consumer_properties,
+            input_format,
+            task_count: None,
+            replicas: None,
+            task_duration: None,
+            start_delay: None,
+            period: None,
+            use_earliest_offset: None,
+            completion_timeout: None,
+            late_message_rejection_period: None,
+            early_message_rejection_period: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KinesisIoConfig {
+    pub stream: String,
+    pub input_format: InputFormat,
+    /// e.g. `kinesis.us-east-1.amazonaws.com`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_duration: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_delay: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_earliest_sequence_number: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aws_assumed_role_arn: Option<String>,
+}
+
+impl KinesisIoConfig {
+    pub fn new(stream: &str, endpoint: &str, input_format: InputFormat) -> Self {
+        KinesisIoConfig {
+            stream: stream.to_string(),
+            input_format,
+            endpoint: Some(endpoint.to_string()),
+            task_count: None,
+            replicas: None,
+            task_duration: None,
+            start_delay: None,
+            period: None,
+            use_earliest_sequence_number: None,
+            completion_timeout: None,
+            aws_assumed_role_arn: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamingTuningConfig {
+    #[serde(rename = "type")]
+    pub config_type: SupervisorType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rows_in_memory: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rows_per_segment: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_total_rows: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intermediate_persist_period: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_offset_automatically: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker_threads: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handoff_condition_timeout: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SupervisorStatus {
+    pub id: String,
+    pub generation_time: Option<String>,
+    pub payload: SupervisorStatusPayload,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SupervisorStatusPayload {
+    pub data_source: String,
+    /// Kafka topic or Kinesis stream
+    pub stream: Option<String>,
+    pub partitions: Option<usize>,
+    pub replicas: Option<usize>,
+    pub duration_seconds: Option<u64>,
+    #[serde(default)]
+    pub active_tasks: Vec<SupervisorTask>,
+    #[serde(default)]
+    pub publishing_tasks: Vec<SupervisorTask>,
+    #[serde(default)]
+    pub latest_offsets: HashMap<String, serde_json::Value>,
+    /// Lag per partition, in messages for Kafka
+    #[serde(default)]
+    pub minimum_lag: HashMap<String, i64>,
+    pub aggregate_lag: Option<i64>,
+    /// Lag per shard, in milliseconds for Kinesis
+    #[serde(default)]
+    pub minimum_lag_millis: HashMap<String, i64>,
+    pub aggregate_lag_millis: Option<i64>,
+    pub offsets_last_updated: Option<String>,
+    #[serde(default)]
+    pub suspended: bool,
+    #[serde(default)]
+    pub healthy: bool,
+    /// e.g. `RUNNING`, `SUSPENDED`, `UNHEALTHY_SUPERVISOR`
+    pub state: Option<String>,
+    pub detailed_state: Option<String>,
+    #[serde(default)]
+    pub recent_errors: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SupervisorTask {
+    pub id: String,
+    pub start_time: Option<String>,
+    pub remaining_seconds: Option<i64>,
+    #[serde(default)]
+    pub starting_offsets: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub current_offsets: HashMap<String, serde_json::Value>,
+}
+
+/// Consumer lag of a supervisor, see [`SupervisorStatusPayload`] for its unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupervisorLag {
+    pub aggregate: Option<i64>,
+    pub partitions: HashMap<String, i64>,
+}
+
+impl SupervisorStatusPayload {
+    pub fn lag(&self) -> SupervisorLag {
+        if self.aggregate_lag.is_none() && self.aggregate_lag_millis.is_some() {
+            SupervisorLag {
+                aggregate: self.aggregate_lag_millis,
+                partitions: self.minimum_lag_millis.clone(),
+            }
+        } else {
+            SupervisorLag {
+                aggregate: self.aggregate_lag,
+                partitions: self.minimum_lag.clone(),
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct SupervisorHealth {
+    pub(crate) healthy: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct SupervisorIdResponse {
+    pub(crate) id: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ingestion::*;
+    use crate::query::definitions::{Aggregation, Granularity};
+
+    #[test]
+    fn test_serialize_kafka_spec() {
+        let mut io_config = KafkaIoConfig::new("wikipedia", "kafka:9092", InputFormat::Json {});
+        io_config.use_earliest_offset = Some(true);
+        let spec = SupervisorSpec::kafka(
+            DataSchema {
+                data_source: "wikipedia".into(),
+                timestamp_spec: TimestampSpec::new("time", "auto"),
+                dimensions_spec: DimensionsSpec::names(vec!["page"]),
+                metrics_spec: vec![Aggregation::count("count")],
+                granularity_spec: GranularitySpec::uniform(Granularity::Hour, Granularity::None),
+                transform_spec: None,
+            },
+            io_config,
+        );
+        let json = serde_json::to_value(&spec).unwrap();
+        assert_eq!(json["type"], "kafka");
+        assert_eq!(json["spec"]["ioConfig"]["topic"], "wikipedia");
+        assert_eq!(
+            json["spec"]["ioConfig"]["consumerProperties"]["bootstrap.servers"],
+            "kafka:9092"
+        );
+        assert_eq!(json["spec"]["ioConfig"]["useEarliestOffset"], true);
+        assert_eq!(
+            json["spec"]["dataSchema"]["granularitySpec"]["segmentGranularity"],
+            "HOUR"
+        );
+
+        let spec = serde_json::from_value::<SupervisorSpec>(json).unwrap();
+        assert_eq!(spec.data_source(), "wikipedia");
+    }
+
+    #[test]
+    fn test_deserialize_status() {
+        let str = r#"
+            {
+                "id": "wikipedia",
+                "generationTime": "2020-09-06T18:48:27.396Z",
+                "payload": {
+                    "dataSource": "wikipedia",
+                    "stream": "wikipedia",
+                    "partitions": 2,
+                    "replicas": 1,
+                    "durationSeconds": 3600,
+                    "activeTasks": [
+                        {
+                            "id": "index_kafka_wikipedia_ab12",
+                            "startTime": "2020-09-06T18:48:30.000Z",
+                            "remainingSeconds": 3500,
+                            "startingOffsets": {"0": 0, "1": 0},
+                            "currentOffsets": {"0": 120, "1": 80}
+                        }
+                    ],
+                    "publishingTasks": [],
+                    "latestOffsets": {"0": 150, "1": 80},
+                    "minimumLag": {"0": 30, "1": 0},
+                    "aggregateLag": 30,
+                    "offsetsLastUpdated": "2020-09-06T18:50:00.000Z",
+                    "suspended": false,
+                    "healthy": true,
+                    "state": "RUNNING",
+                    "detailedState": "RUNNING",
+                    "recentErrors": []
+                }
+            }
+        "#;
+        let status = serde_json::from_str::<SupervisorStatus>(str).unwrap();
+        assert_eq!(status.payload.active_tasks.len(), 1);
+        let lag = status.payload.lag();
+        assert_eq!(lag.aggregate, Some(30));
+        assert_eq!(lag.partitions["0"], 30);
+    }
+}
@@ -0,0 +1,274 @@
+//! Typed rows of the [`sys` schema](https://druid.apache.org/docs/latest/querying/sql.html#system-schema)
+//! tables, queried through Druid SQL on the broker.
+//!
+//! ```no_run
+//! use druid_io::client::DruidClient;
+//! use druid_io::system::SysFilter;
+//!
+//! # async fn example() {
+//! let client = DruidClient::new(vec!["localhost:8082".to_string()]);
+//! let segments = client
+//!     .sys_segments(
+//!         SysFilter::new()
+//!             .eq("datasource", "wikipedia")
+//!             .eq("is_available", 0)
+//!             .limit(100),
+//!     )
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use crate::client::{ClientResult, DruidClient};
+use crate::query::sql::{SqlParameter, SqlQuery};
+use crate::serialization::int_bool;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Conditions, ordering and limit of a `sys` table query. Column names are static strings
+/// and values are sent as bound parameters, so neither can alter the query.
+#[derive(Debug, Clone, Default)]
+pub struct SysFilter {
+    conditions: Vec<(&'static str, &'static str, SqlParameter)>,
+    order_by: Vec<(&'static str, bool)>,
+    limit: Option<usize>,
+}
+
+impl SysFilter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn eq<P: Into<SqlParameter>>(self, column: &'static str, value: P) -> Self {
+        self.condition(column, "=", value)
+    }
+
+    pub fn not_eq<P: Into<SqlParameter>>(self, column: &'static str, value: P) -> Self {
+        self.condition(column, "<>", value)
+    }
+
+    /// SQL `LIKE` pattern, e.g. `index_kafka_%`
+    pub fn like(self, column: &'static str, pattern: &str) -> Self {
+        self.condition(column, "LIKE", pattern)
+    }
+
+    pub fn greater_than<P: Into<SqlParameter>>(self, column: &'static str, value: P) -> Self {
+        self.condition(column, ">", value)
+    }
+
+    pub fn less_than<P: Into<SqlParameter>>(self, column: &'static str, value: P) -> Self {
+        self.condition(column, "<", value)
+    }
+
+    pub fn order_by(mut self, column: &'static str, descending: bool) -> Self {
+        self.order_by.push((column, descending));
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn condition<P: Into<SqlParameter>>(
+        mut self,
+        column: &'static str,
+        operator: &'static str,
+        value: P,
+    ) -> Self {
+        self.conditions.push((column, operator, value.into()));
+        self
+    }
+
+    fn query(&self, table: &str) -> SqlQuery {
+        let mut sql = format!("SELECT * FROM sys.{}", table);
+        for (i, (column, operator, _)) in self.conditions.iter().enumerate() {
+            let keyword = if i == 0 { "WHERE" } else { "AND" };
+            sql.push_str(&format!(" {} \"{}\" {} ?", keyword, column, operator));
+        }
+        for (i, (column, descending)) in self.order_by.iter().enumerate() {
+            let keyword = if i == 0 { " ORDER BY" } else { "," };
+            let direction = if *descending { "DESC" } else { "ASC" };
+            sql.push_str(&format!("{} \"{}\" {}", keyword, column, direction));
+        }
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+        let mut query = SqlQuery::new(&sql);
+        for (_, _, value) in &self.conditions {
+            query = query.bind(value.clone());
+        }
+        query
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SysSegment {
+    pub segment_id: String,
+    pub datasource: String,
+    pub start: String,
+    pub end: String,
+    pub size: i64,
+    pub version: String,
+    pub partition_num: Option<i64>,
+    pub num_replicas: i64,
+    /// Not known for segments that aren't loaded yet
+    pub num_rows: Option<i64>,
+    #[serde(deserialize_with = "int_bool", default)]
+    pub is_published: bool,
+    #[serde(deserialize_with = "int_bool", default)]
+    pub is_available: bool,
+    #[serde(deserialize_with = "int_bool", default)]
+    pub is_realtime: bool,
+    #[serde(deserialize_with = "int_bool", default)]
+    pub is_overshadowed: bool,
+    /// JSON serialized shard spec
+    pub shard_spec: Option<String>,
+    /// JSON serialized list of dimensions
+    pub dimensions: Option<String>,
+    /// JSON serialized list of metrics
+    pub metrics: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SysServerSegment {
+    pub server: String,
+    pub segment_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SysServer {
+    pub server: String,
+    pub host: String,
+    pub plaintext_port: i64,
+    pub tls_port: i64,
+    /// e.g. `historical`, `broker`, `coordinator`
+    pub server_type: String,
+    pub tier: Option<String>,
+    pub curr_size: Option<i64>,
+    pub max_size: Option<i64>,
+    #[serde(deserialize_with = "int_bool", default)]
+    pub is_leader: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SysTask {
+    pub task_id: String,
+    pub group_id: Option<String>,
+    #[serde(rename = "type")]
+    pub task_type: Option<String>,
+    pub datasource: Option<String>,
+    pub created_time: Option<String>,
+    pub queue_insertion_time: Option<String>,
+    /// `RUNNING`, `SUCCESS` or `FAILED`
+    pub status: Option<String>,
+    pub runner_status: Option<String>,
+    pub duration: Option<i64>,
+    pub location: Option<String>,
+    pub host: Option<String>,
+    pub plaintext_port: Option<i64>,
+    pub tls_port: Option<i64>,
+    pub error_msg: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SysSupervisor {
+    pub supervisor_id: String,
+    pub state: Option<String>,
+    pub detailed_state: Option<String>,
+    #[serde(deserialize_with = "int_bool", default)]
+    pub healthy: bool,
+    #[serde(rename = "type")]
+    pub supervisor_type: Option<String>,
+    /// Kafka topic or Kinesis stream
+    pub source: Option<String>,
+    #[serde(deserialize_with = "int_bool", default)]
+    pub suspended: bool,
+    /// JSON serialized supervisor spec
+    pub spec: Option<String>,
+}
+
+impl DruidClient {
+    pub async fn sys_segments(&self, filter: SysFilter) -> ClientResult<Vec<SysSegment>> {
+        self.sys_table("segments", filter).await
+    }
+
+    pub async fn sys_server_segments(
+        &self,
+        filter: SysFilter,
+    ) -> ClientResult<Vec<SysServerSegment>> {
+        self.sys_table("server_segments", filter).await
+    }
+
+    pub async fn sys_servers(&self, filter: SysFilter) -> ClientResult<Vec<SysServer>> {
+        self.sys_table("servers", filter).await
+    }
+
+    pub async fn sys_tasks(&self, filter: SysFilter) -> ClientResult<Vec<SysTask>> {
+        self.sys_table("tasks", filter).await
+    }
+
+    pub async fn sys_supervisors(&self, filter: SysFilter) -> ClientResult<Vec<SysSupervisor>> {
+        self.sys_table("supervisors", filter).await
+    }
+
+    async fn sys_table<T: DeserializeOwned>(
+        &self,
+        table: &str,
+        filter: SysFilter,
+    ) -> ClientResult<Vec<T>> {
+        let response = self.sql::<T>(&filter.query(table)).await?;
+        Ok(response.rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_filter_query() {
+        let query = SysFilter::new()
+            .eq("datasource", "wikipedia")
+            .eq("is_available", 1)
+            .order_by("start", true)
+            .limit(10)
+            .query("segments");
+        assert_eq!(
+            query.query,
+            "SELECT * FROM sys.segments WHERE \"datasource\" = ? AND \"is_available\" = ? ORDER BY \"start\" DESC LIMIT 10"
+        );
+        assert_eq!(
+            query.parameters,
+            vec![SqlParameter::from("wikipedia"), SqlParameter::from(1)]
+        );
+    }
+
+    #[test]
+    fn test_deserialize_segment() {
+        let str = r#"
+            {
+                "segment_id": "wikipedia_2015-09-12T00:00:00.000Z_2015-09-13T00:00:00.000Z_2020-09-06T18:48:27.396Z",
+                "datasource": "wikipedia",
+                "start": "2015-09-12T00:00:00.000Z",
+                "end": "2015-09-13T00:00:00.000Z",
+                "size": 4821291,
+                "version": "2020-09-06T18:48:27.396Z",
+                "partition_num": 0,
+                "num_replicas": 1,
+                "num_rows": 39244,
+                "is_published": 1,
+                "is_available": 1,
+                "is_realtime": 0,
+                "is_overshadowed": 0,
+                "shard_spec": "{\"type\":\"numbered\",\"partitionNum\":0,\"partitions\":0}",
+                "dimensions": "[\"channel\",\"page\"]",
+                "metrics": "[\"count\"]",
+                "last_compaction_state": null
+            }
+        "#;
+        let segment = serde_json::from_str::<SysSegment>(str).unwrap();
+        assert!(segment.is_published && segment.is_available);
+        assert!(!segment.is_overshadowed);
+        assert_eq!(segment.num_rows, Some(39244));
+    }
+}
@@ -0,0 +1,355 @@
+use super::filter::{compile_filter, Predicate};
+use super::{number_value, string_value, unsupported, Row};
+use crate::client::ClientResult;
+use crate::query::definitions::Aggregation;
+use crate::query::group_by::{PostAggregation, PostAggregator};
+use crate::query::JsonAny;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+/// Aggregation of a column over the rows of a bucket.
+pub(crate) struct Aggregator {
+    pub(crate) name: String,
+    kind: Kind,
+    field_name: String,
+    filter: Option<Predicate>,
+}
+
+enum Kind {
+    Count,
+    Sum(ValueType),
+    Min(ValueType),
+    Max(ValueType),
+    First(ValueType),
+    Last(ValueType),
+    Any(ValueType),
+    /// Exact distinct count standing in for sketches and cardinality
+    Distinct {
+        fields: Vec<String>,
+        by_row: bool,
+        round: bool,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum ValueType {
+    Long,
+    Double,
+    /// with max bytes
+    String(usize),
+}
+
+#[derive(Clone)]
+pub(crate) enum State {
+    Long(i64),
+    Double(f64),
+    Extreme(Option<f64>),
+    Timed(Option<(i64, Value)>),
+    Distinct(HashSet<String>),
+}
+
+impl Aggregator {
+    pub(crate) fn compile(aggregation: &Aggregation) -> ClientResult<Self> {
+        use ValueType::*;
+        let (kind, field_name) = match aggregation {
+            Aggregation::Count { .. } => (Kind::Count, ""),
+            Aggregation::LongSum { field_name, .. } => (Kind::Sum(Long), field_name.as_str()),
+            Aggregation::DoubleSum { field_name, .. }
+            | Aggregation::FloatSum { field_name, .. } => (Kind::Sum(Double), field_name.as_str()),
+            Aggregation::LongMin { field_name, .. } => (Kind::Min(Long), field_name.as_str()),
+            Aggregation::DoubleMin { field_name, .. }
+            | Aggregation::FloatMin { field_name, .. } => (Kind::Min(Double), field_name.as_str()),
+            Aggregation::LongMax { field_name, .. } => (Kind::Max(Long), field_name.as_str()),
+            Aggregation::DoubleMax { field_name, .. }
+            | Aggregation::FloatMax { field_name, .. } => (Kind::Max(Double), field_name.as_str()),
+            Aggregation::LongFirst { field_name, .. } => (Kind::First(Long), field_name.as_str()),
+            Aggregation::DoubleFirst { field_name, .. }
+            | Aggregation::FloatFirst { field_name, .. } => {
+                (Kind::First(Double), field_name.as_str())
+            }
+            Aggregation::StringFirst {
+                field_name,
+                max_string_bytes,
+                ..
+            } => (Kind::First(String(*max_string_bytes)), field_name.as_str()),
+            Aggregation::LongLast { field_name, .. } => (Kind::Last(Long), field_name.as_str()),
+            Aggregation::DoubleLast { field_name, .. }
+            | Aggregation::FloatLast { field_name, .. } => {
+                (Kind::Last(Double), field_name.as_str())
+            }
+            Aggregation::StringLast {
+                field_name,
+                max_string_bytes,
+                ..
+            } => (Kind::Last(String(*max_string_bytes)), field_name.as_str()),
+            Aggregation::LongAny { field_name, .. } => (Kind::Any(Long), field_name.as_str()),
+            Aggregation::DoubleAny { field_name, .. }
+            | Aggregation::FloatAny { field_name, .. } => (Kind::Any(Double), field_name.as_str()),
+            Aggregation::StringAny { field_name, .. } => {
+                (Kind::Any(String(1024)), field_name.as_str())
+            }
+            Aggregation::Cardinality {
+                fields,
+                by_row,
+                round,
+                ..
+            } => (
+                Kind::Distinct {
+                    fields: fields.clone(),
+                    by_row: *by_row,
+                    round: *round,
+                },
+                "",
+            ),
+            Aggregation::HyperUnique {
+                field_name,
+                is_input_hyper_unique: false,
+                round,
+                ..
+            }
+            | Aggregation::HLLSketchBuild {
+                field_name, round, ..
+            } => (distinct(field_name, *round), field_name.as_str()),
+            Aggregation::ThetaSketch {
+                field_name,
+                is_input_theta_sketch: false,
+                ..
+            } => (distinct(field_name, false), field_name.as_str()),
+            Aggregation::Filtered { filter, aggregator } => {
+                let aggregator = Aggregator::compile(aggregator)?;
+                let filter = compile_filter(Some(filter))?;
+                let filter: Predicate = match aggregator.filter {
+                    Some(inner) => Box::new(move |row| filter(row) && inner(row)),
+                    None => filter,
+                };
+                return Ok(Aggregator {
+                    filter: Some(filter),
+                    ..aggregator
+                });
+            }
+            Aggregation::HyperUnique { .. } | Aggregation::ThetaSketch { .. } => {
+                return Err(unsupported("aggregation of sketch columns"))
+            }
+            Aggregation::Javascript { .. } => return Err(unsupported("javascript aggregation")),
+        };
+        Ok(Aggregator {
+            name: aggregation.name().to_string(),
+            kind,
+            field_name: field_name.to_string(),
+            filter: None,
+        })
+    }
+
+    pub(crate) fn initial(&self) -> State {
+        match &self.kind {
+            Kind::Count | Kind::Sum(ValueType::Long) => State::Long(0),
+            Kind::Sum(_) => State::Double(0.0),
+            Kind::Min(_) | Kind::Max(_) => State::Extreme(None),
+            Kind::First(_) | Kind::Last(_) | Kind::Any(_) => State::Timed(None),
+            Kind::Distinct { .. } => State::Distinct(HashSet::new()),
+        }
+    }
+
+    pub(crate) fn add(&self, state: &mut State, row: &Row) {
+        if let Some(filter) = &self.filter {
+            if !filter(row) {
+                return;
+            }
+        }
+        let value = || row.get(&self.field_name);
+        match (&self.kind, state) {
+            (Kind::Count, State::Long(count)) => *count += 1,
+            // nulls and values that aren't numbers count as zeros
+            (Kind::Sum(_), State::Long(sum)) => {
+                *sum += number_value(&value())
+                    .map(|number| number as i64)
+                    .unwrap_or(0)
+            }
+            (Kind::Sum(_), State::Double(sum)) => *sum += number_value(&value()).unwrap_or(0.0),
+            (Kind::Min(_), State::Extreme(extreme)) | (Kind::Max(_), State::Extreme(extreme)) => {
+                if let Some(number) = number_value(&value()) {
+                    let is_min = matches!(self.kind, Kind::Min(_));
+                    *extreme = Some(match *extreme {
+                        Some(current) if is_min => current.min(number),
+                        Some(current) => current.max(number),
+                        None => number,
+                    });
+                }
+            }
+            (Kind::First(value_type), State::Timed(timed))
+            | (Kind::Last(value_type), State::Timed(timed))
+            | (Kind::Any(value_type), State::Timed(timed)) => {
+                let replace = match (&self.kind, &timed) {
+                    (_, None) => true,
+                    (Kind::First(_), Some((time, _))) => row.time < *time,
+                    (Kind::Last(_), Some((time, _))) => row.time >= *time,
+                    _ => false,
+                };
+                if replace {
+                    if let Some(value) = typed_value(&value(), *value_type) {
+                        *timed = Some((row.time, value));
+                    }
+                }
+            }
+            (
+                Kind::Distinct {
+                    fields,
+                    by_row: true,
+                    ..
+                },
+                State::Distinct(values),
+            ) => {
+                let key: Vec<String> = fields
+                    .iter()
+                    .map(|field| row.string(field).unwrap_or_default())
+                    .collect();
+                values.insert(key.join("\u{1}"));
+            }
+            (Kind::Distinct { fields, .. }, State::Distinct(values)) => {
+                for field in fields {
+                    if let Some(value) = row.string(field) {
+                        values.insert(value);
+                    }
+                }
+            }
+            _ => unreachable!("state doesn't match the aggregation"),
+        }
+    }
+
+    /// Final value of the aggregation.
+    pub(crate) fn finish(&self, state: &State) -> Value {
+        match (&self.kind, state) {
+            (_, State::Long(value)) => Value::from(*value),
+            (_, State::Double(value)) => Value::from(*value),
+            (Kind::Min(ValueType::Long), State::Extreme(Some(value)))
+            | (Kind::Max(ValueType::Long), State::Extreme(Some(value))) => {
+                Value::from(*value as i64)
+            }
+            (_, State::Extreme(value)) => value.map(Value::from).unwrap_or(Value::Null),
+            (_, State::Timed(value)) => value
+                .as_ref()
+                .map(|(_, value)| value.clone())
+                .unwrap_or(Value::Null),
+            (Kind::Distinct { round: true, .. }, State::Distinct(values)) => {
+                Value::from(values.len())
+            }
+            (_, State::Distinct(values)) => Value::from(values.len() as f64),
+        }
+    }
+}
+
+fn distinct(field_name: &str, round: bool) -> Kind {
+    Kind::Distinct {
+        fields: vec![field_name.to_string()],
+        by_row: false,
+        round,
+    }
+}
+
+fn typed_value(value: &Value, value_type: ValueType) -> Option<Value> {
+    match value_type {
+        ValueType::Long => number_value(value).map(|number| Value::from(number as i64)),
+        ValueType::Double => number_value(value).map(Value::from),
+        ValueType::String(max_bytes) => string_value(value).map(|mut value| {
+            if value.len() > max_bytes {
+                let mut end = max_bytes;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                value.truncate(end);
+            }
+            Value::from(value)
+        }),
+    }
+}
+
+/// Fails for post aggregations the engine can't compute.
+pub(crate) fn check_post_aggregation(post_aggregation: &PostAggregation) -> ClientResult<()> {
+    match post_aggregation {
+        PostAggregation::Javascript { .. } => Err(unsupported("javascript post aggregation")),
+        PostAggregation::DoubleGreatest { fields, .. }
+        | PostAggregation::LongGreatest { fields, .. }
+        | PostAggregation::LongLeast { fields, .. }
+        | PostAggregation::DoubleLeast { fields, .. } => {
+            fields.iter().try_for_each(check_post_aggregation)
+        }
+        PostAggregation::Arithmetic { function, .. } => match function.as_str() {
+            "+" | "-" | "*" | "/" | "quotient" | "pow" => Ok(()),
+            function => Err(unsupported(&format!("arithmetic function `{}`", function))),
+        },
+    }
+}
+
+/// Value of a post aggregation over the aggregated values of a result row.
+pub(crate) fn post_aggregate(
+    post_aggregation: &PostAggregation,
+    event: &Map<String, Value>,
+) -> Value {
+    let numbers = |fields: &[PostAggregation]| {
+        fields
+            .iter()
+            .filter_map(|field| number_value(&post_aggregate(field, event)))
+            .collect::<Vec<_>>()
+    };
+    match post_aggregation {
+        PostAggregation::Arithmetic {
+            function, fields, ..
+        } => {
+            let mut values = fields.iter().map(|field| post_aggregator(field, event));
+            let first = values.next().unwrap_or(0.0);
+            let result = values.fold(first, |left, right| match function.as_str() {
+                "+" => left + right,
+                "-" => left - right,
+                "*" => left * right,
+                // druid's division by zero is zero, unlike `quotient`
+                "/" if right == 0.0 => 0.0,
+                "/" | "quotient" => left / right,
+                _ => left.powf(right),
+            });
+            Value::from(result)
+        }
+        PostAggregation::DoubleGreatest { fields, .. } => numbers(fields)
+            .into_iter()
+            .fold(None, |max: Option<f64>, value| {
+                Some(max.map_or(value, |max| max.max(value)))
+            })
+            .map(Value::from)
+            .unwrap_or(Value::Null),
+        PostAggregation::DoubleLeast { fields, .. } => numbers(fields)
+            .into_iter()
+            .fold(None, |min: Option<f64>, value| {
+                Some(min.map_or(value, |min| min.min(value)))
+            })
+            .map(Value::from)
+            .unwrap_or(Value::Null),
+        PostAggregation::LongGreatest { fields, .. } => numbers(fields)
+            .into_iter()
+            .map(|value| value as i64)
+            .max()
+            .map(Value::from)
+            .unwrap_or(Value::Null),
+        PostAggregation::LongLeast { fields, .. } => numbers(fields)
+            .into_iter()
+            .map(|value| value as i64)
+            .min()
+            .map(Value::from)
+            .unwrap_or(Value::Null),
+        PostAggregation::Javascript { .. } => Value::Null,
+    }
+}
+
+fn post_aggregator(post_aggregator: &PostAggregator, event: &Map<String, Value>) -> f64 {
+    match post_aggregator {
+        PostAggregator::FieldAccess { field_name, .. }
+        | PostAggregator::FinalizingFieldAccess { field_name, .. }
+        | PostAggregator::HyperUniqueCardinality { field_name } => {
+            event.get(field_name).and_then(number_value).unwrap_or(0.0)
+        }
+        PostAggregator::Constant { value, .. } => match value {
+            JsonAny::Float(value) => *value as f64,
+            JsonAny::Integer(value) => *value as f64,
+            JsonAny::STRING(value) => value.parse().unwrap_or(0.0),
+            JsonAny::Boolean(value) => *value as i64 as f64,
+        },
+    }
+}
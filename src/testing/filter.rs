@@ -0,0 +1,536 @@
+use super::{unsupported, Row};
+use crate::client::ClientResult;
+use crate::query::definitions::{
+    Dimension, ExtractFN, Filter, FilterQuerySpec, NullHandling, OutputType, SortingOrder,
+};
+use crate::query::search::SearchQuerySpec;
+use crate::time::parse_interval;
+use regex::Regex;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+pub(crate) type Predicate = Box<dyn Fn(&Row) -> bool>;
+type Extraction = Box<dyn Fn(Option<String>) -> Option<String>>;
+type Column = Box<dyn Fn(&Row) -> Option<String>>;
+pub(crate) type Matcher = Box<dyn Fn(&str) -> bool>;
+
+/// Predicate over rows, `None` matches every row.
+pub(crate) fn compile_filter(filter: Option<&Filter>) -> ClientResult<Predicate> {
+    Ok(match filter {
+        Some(filter) => compile(filter)?,
+        None => Box::new(|_| true),
+    })
+}
+
+fn compile(filter: &Filter) -> ClientResult<Predicate> {
+    Ok(match filter {
+        Filter::Selector {
+            dimension,
+            value,
+            extract_fn,
+        } => {
+            let column = column(dimension, extract_fn.as_ref())?;
+            let value = value.clone();
+            // druid doesn't tell empty strings from nulls
+            Box::new(move |row| column(row).unwrap_or_default() == value)
+        }
+        Filter::ColumnComparison { dimensions } => {
+            let dimensions = dimensions.clone();
+            Box::new(move |row| {
+                let mut values = dimensions.iter().map(|dimension| row.string(dimension));
+                match values.next() {
+                    Some(first) => values.all(|value| value == first),
+                    None => true,
+                }
+            })
+        }
+        Filter::Regex { dimension, pattern } => {
+            let regex = regex(pattern)?;
+            let dimension = dimension.clone();
+            Box::new(move |row| {
+                row.string(&dimension)
+                    .map(|value| regex.is_match(&value))
+                    .unwrap_or(false)
+            })
+        }
+        Filter::And { fields } => {
+            let fields = fields
+                .iter()
+                .map(compile)
+                .collect::<ClientResult<Vec<_>>>()?;
+            Box::new(move |row| fields.iter().all(|field| field(row)))
+        }
+        Filter::Or { fields } => {
+            let fields = fields
+                .iter()
+                .map(compile)
+                .collect::<ClientResult<Vec<_>>>()?;
+            Box::new(move |row| fields.iter().any(|field| field(row)))
+        }
+        Filter::Not { field } => {
+            let field = compile(field)?;
+            Box::new(move |row| !field(row))
+        }
+        Filter::Search { dimension, query } => {
+            let matches = search_query(query);
+            let dimension = dimension.clone();
+            Box::new(move |row| {
+                row.string(&dimension)
+                    .map(|value| matches(&value))
+                    .unwrap_or(false)
+            })
+        }
+        Filter::In { dimension, values } => {
+            let dimension = dimension.clone();
+            let values = values.clone();
+            Box::new(move |row| values.contains(&row.string(&dimension).unwrap_or_default()))
+        }
+        Filter::Like {
+            dimension,
+            pattern,
+            escape,
+            extraction_fn,
+        } => {
+            let column = column(dimension, extraction_fn.as_ref())?;
+            let regex = regex(&like_to_regex(pattern, escape.as_deref()))?;
+            Box::new(move |row| {
+                column(row)
+                    .map(|value| regex.is_match(&value))
+                    .unwrap_or(false)
+            })
+        }
+        Filter::Bound {
+            dimension,
+            lower,
+            upper,
+            lower_strict,
+            upper_strict,
+            ordering,
+            extraction_fn,
+        } => {
+            let column = column(dimension, extraction_fn.as_ref())?;
+            let (lower, upper) = (lower.clone(), upper.clone());
+            let (lower_strict, upper_strict) = (*lower_strict, *upper_strict);
+            let ordering = *ordering;
+            Box::new(move |row| {
+                let value = match column(row) {
+                    Some(value) => value,
+                    None => return false,
+                };
+                // empty bounds are open
+                let above = lower.is_empty()
+                    || match compare(&value, &lower, &ordering) {
+                        Ordering::Greater => true,
+                        Ordering::Equal => !lower_strict,
+                        Ordering::Less => false,
+                    };
+                let below = upper.is_empty()
+                    || match compare(&value, &upper, &ordering) {
+                        Ordering::Less => true,
+                        Ordering::Equal => !upper_strict,
+                        Ordering::Greater => false,
+                    };
+                above && below
+            })
+        }
+        Filter::Interval {
+            dimension,
+            intervals,
+            extraction_fn,
+        } => {
+            let column = column(dimension, extraction_fn.as_ref())?;
+            let intervals = intervals
+                .iter()
+                .map(|interval| parse_interval(interval))
+                .collect::<ClientResult<Vec<_>>>()?;
+            Box::new(move |row| {
+                let millis = column(row).and_then(|value| value.parse::<i64>().ok());
+                match millis {
+                    Some(millis) => intervals
+                        .iter()
+                        .any(|(start, end)| *start <= millis && millis < *end),
+                    None => false,
+                }
+            })
+        }
+        Filter::True => Box::new(|_| true),
+        Filter::Javascript { .. } => return Err(unsupported("javascript filter")),
+    })
+}
+
+/// Row value of a dimension as druid would see it.
+pub(crate) struct DimensionSpec {
+    pub(crate) output_name: String,
+    column: Column,
+    output: fn(&str) -> Value,
+}
+
+impl DimensionSpec {
+    pub(crate) fn compile(dimension: &Dimension) -> ClientResult<Self> {
+        let (output_name, column, output_type): (&str, Column, &OutputType) = match dimension {
+            Dimension::Default {
+                dimension,
+                output_name,
+                output_type,
+            } => (output_name, column(dimension, None)?, output_type),
+            Dimension::Extraction {
+                dimenstion,
+                output_name,
+                output_type,
+                extraction_fn,
+            } => (
+                output_name,
+                column(dimenstion, Some(extraction_fn))?,
+                output_type,
+            ),
+            Dimension::ListFiltered {
+                delegate,
+                values,
+                is_whitelist,
+            } => {
+                let values = values.clone();
+                let is_whitelist = *is_whitelist;
+                return Ok(DimensionSpec::compile(delegate)?
+                    .filtered(move |value| values.iter().any(|v| v == value) == is_whitelist));
+            }
+            Dimension::RegexFiltered { delegate, pattern } => {
+                let regex = regex(pattern)?;
+                return Ok(
+                    DimensionSpec::compile(delegate)?.filtered(move |value| regex.is_match(value))
+                );
+            }
+            Dimension::PrefixFiltered { delegate, prefix } => {
+                let prefix = prefix.clone();
+                return Ok(DimensionSpec::compile(delegate)?
+                    .filtered(move |value| value.starts_with(&prefix)));
+            }
+            Dimension::LookupMap { .. } | Dimension::Lookup { .. } => {
+                return Err(unsupported("lookup dimension"))
+            }
+        };
+        let output: fn(&str) -> Value = match output_type {
+            OutputType::STRING => |value| Value::from(value),
+            OutputType::LONG => |value| {
+                value
+                    .parse::<f64>()
+                    .map(|number| Value::from(number as i64))
+                    .unwrap_or(Value::Null)
+            },
+            OutputType::FLOAT => {
+                |value| value.parse::<f64>().map(Value::from).unwrap_or(Value::Null)
+            }
+        };
+        Ok(DimensionSpec {
+            output_name: output_name.to_string(),
+            column,
+            output,
+        })
+    }
+
+    fn filtered(self, keep: impl Fn(&str) -> bool + 'static) -> Self {
+        let column = self.column;
+        DimensionSpec {
+            output_name: self.output_name,
+            column: Box::new(move |row| column(row).filter(|value| keep(value))),
+            output: self.output,
+        }
+    }
+
+    pub(crate) fn value(&self, row: &Row) -> Option<String> {
+        (self.column)(row)
+    }
+
+    pub(crate) fn output(&self, value: &Option<String>) -> Value {
+        match value {
+            Some(value) => (self.output)(value),
+            None => Value::Null,
+        }
+    }
+}
+
+fn column(dimension: &str, extraction_fn: Option<&ExtractFN>) -> ClientResult<Column> {
+    let dimension = dimension.to_string();
+    Ok(match extraction_fn {
+        Some(extraction_fn) => {
+            let extraction = extraction(extraction_fn)?;
+            Box::new(move |row| extraction(row.string(&dimension)))
+        }
+        None => Box::new(move |row| row.string(&dimension)),
+    })
+}
+
+fn extraction(extraction_fn: &ExtractFN) -> ClientResult<Extraction> {
+    Ok(match extraction_fn {
+        ExtractFN::Regex {
+            expr,
+            index,
+            replace_missing_value,
+            replace_missing_value_with,
+        } => {
+            let regex = regex(expr)?;
+            let index = *index;
+            let missing = if *replace_missing_value {
+                replace_missing_value_with.clone()
+            } else {
+                None
+            };
+            Box::new(move |value| {
+                let value = value?;
+                match regex.captures(&value) {
+                    Some(captures) => captures.get(index).map(|group| group.as_str().to_string()),
+                    None => missing.clone().or(Some(value)),
+                }
+            })
+        }
+        ExtractFN::Partial { expr } => {
+            let regex = regex(expr)?;
+            Box::new(move |value| value.filter(|value| regex.is_match(value)))
+        }
+        ExtractFN::Substring { index, length } => {
+            let (index, length) = (*index, *length);
+            Box::new(move |value| {
+                let chars = value?.chars().skip(index).collect::<Vec<_>>();
+                let end = length.map(|length| length.min(chars.len()));
+                let substring: String = chars[..end.unwrap_or(chars.len())].iter().collect();
+                Some(substring).filter(|substring| !substring.is_empty())
+            })
+        }
+        ExtractFN::Strlen => Box::new(|value| {
+            Some(
+                value
+                    .map(|value| value.chars().count())
+                    .unwrap_or_default()
+                    .to_string(),
+            )
+        }),
+        ExtractFN::Cascade { extraction_fns } => {
+            let extractions = extraction_fns
+                .iter()
+                .map(extraction)
+                .collect::<ClientResult<Vec<_>>>()?;
+            Box::new(move |value| {
+                extractions
+                    .iter()
+                    .fold(value, |value, extraction| extraction(value))
+            })
+        }
+        ExtractFN::StringFormat {
+            format,
+            null_handling,
+        } => {
+            let format = format.clone();
+            let null_handling = match null_handling {
+                Some(NullHandling::EmptyString) => Some(String::new()),
+                Some(NullHandling::ReturnNull) => None,
+                Some(NullHandling::NullString) | None => Some("null".to_string()),
+            };
+            Box::new(move |value| {
+                value
+                    .or_else(|| null_handling.clone())
+                    .map(|value| format.replacen("%s", &value, 1))
+            })
+        }
+        ExtractFN::Upper { .. } => Box::new(|value| value.map(|value| value.to_uppercase())),
+        ExtractFN::Lower { .. } => Box::new(|value| value.map(|value| value.to_lowercase())),
+        ExtractFN::Bucket { size, offset } => {
+            let (size, offset) = (*size as f64, *offset as f64);
+            Box::new(move |value| {
+                let number = value?.parse::<f64>().ok()?;
+                let bucket = ((number - offset) / size).floor() * size + offset;
+                Some((bucket as i64).to_string())
+            })
+        }
+        ExtractFN::TimeFormat { .. } => return Err(unsupported("timeFormat extraction")),
+        ExtractFN::Time { .. } => return Err(unsupported("time extraction")),
+        ExtractFN::Javascript { .. } => return Err(unsupported("javascript extraction")),
+        ExtractFN::RegisteredLookup { .. } | ExtractFN::Lookup { .. } => {
+            return Err(unsupported("lookup extraction"))
+        }
+    })
+}
+
+fn search_query(query: &FilterQuerySpec) -> Matcher {
+    match query {
+        FilterQuerySpec::Contains {
+            value,
+            case_sensitive,
+        } => contains(value, *case_sensitive),
+        FilterQuerySpec::InsensitiveContains { value } => contains(value, false),
+        FilterQuerySpec::Fragment {
+            values,
+            case_sensitive,
+        } => fragments(values, *case_sensitive),
+    }
+}
+
+/// Matcher of a Search query spec.
+pub(crate) fn search_spec(query: &SearchQuerySpec) -> ClientResult<Matcher> {
+    Ok(match query {
+        SearchQuerySpec::Contains {
+            value,
+            case_sensitive,
+        } => contains(value, *case_sensitive),
+        SearchQuerySpec::InsensitiveContains { value } => contains(value, false),
+        SearchQuerySpec::Fragment {
+            values,
+            case_sensitive,
+        } => fragments(values, *case_sensitive),
+        SearchQuerySpec::Regex { pattern } => {
+            let regex = regex(pattern)?;
+            Box::new(move |candidate| regex.is_match(candidate))
+        }
+    })
+}
+
+fn contains(value: &str, case_sensitive: bool) -> Matcher {
+    if case_sensitive {
+        let value = value.to_string();
+        Box::new(move |candidate| candidate.contains(&value))
+    } else {
+        let value = value.to_lowercase();
+        Box::new(move |candidate| candidate.to_lowercase().contains(&value))
+    }
+}
+
+fn fragments(values: &[String], case_sensitive: bool) -> Matcher {
+    let fragments: Vec<_> = values
+        .iter()
+        .map(|value| contains(value, case_sensitive))
+        .collect();
+    Box::new(move |candidate| fragments.iter().all(|fragment| fragment(candidate)))
+}
+
+pub(crate) fn regex(pattern: &str) -> ClientResult<Regex> {
+    Regex::new(pattern).map_err(|_| unsupported(&format!("regular expression `{}`", pattern)))
+}
+
+// `%` matches any string and `_` any character, unless preceded by the escape
+fn like_to_regex(pattern: &str, escape: Option<&str>) -> String {
+    let escape = escape.and_then(|escape| escape.chars().next());
+    let mut regex = String::from("(?s)^");
+    let mut escaped = false;
+    for c in pattern.chars() {
+        match c {
+            _ if escaped => {
+                regex.push_str(&regex::escape(&c.to_string()));
+                escaped = false;
+            }
+            c if Some(c) == escape => escaped = true,
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Compares dimension values the way druid's sorting orders do.
+pub(crate) fn compare(left: &str, right: &str, ordering: &SortingOrder) -> Ordering {
+    match ordering {
+        SortingOrder::Lexicographic => left.cmp(right),
+        SortingOrder::Strlen => left
+            .chars()
+            .count()
+            .cmp(&right.chars().count())
+            .then_with(|| left.cmp(right)),
+        // values that aren't numbers come first
+        SortingOrder::Numeric => match (left.parse::<f64>(), right.parse::<f64>()) {
+            (Ok(left), Ok(right)) => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
+            (Ok(_), Err(_)) => Ordering::Greater,
+            (Err(_), Ok(_)) => Ordering::Less,
+            (Err(_), Err(_)) => left.cmp(right),
+        },
+        SortingOrder::Alphanumeric => compare_alphanumeric(left, right),
+    }
+}
+
+// runs of digits are compared by their numeric value, e.g. `a2` < `a10`
+fn compare_alphanumeric(left: &str, right: &str) -> Ordering {
+    fn chunks(value: &str) -> Vec<(bool, &str)> {
+        let mut chunks = vec![];
+        let mut start = 0;
+        let mut digits = None;
+        for (index, c) in value.char_indices() {
+            let is_digit = c.is_ascii_digit();
+            if digits.is_some() && digits != Some(is_digit) {
+                chunks.push((digits.unwrap_or_default(), &value[start..index]));
+                start = index;
+            }
+            digits = Some(is_digit);
+        }
+        if let Some(digits) = digits {
+            chunks.push((digits, &value[start..]));
+        }
+        chunks
+    }
+    for (left, right) in chunks(left).into_iter().zip(chunks(right)) {
+        let ordering = match (left, right) {
+            ((true, left), (true, right)) => {
+                let (left, right) = (left.trim_start_matches('0'), right.trim_start_matches('0'));
+                left.len().cmp(&right.len()).then_with(|| left.cmp(right))
+            }
+            ((_, left), (_, right)) => left.cmp(right),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    left.len().cmp(&right.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn row(value: Value) -> Row {
+        Row {
+            time: 0,
+            columns: value.as_object().unwrap().clone(),
+        }
+    }
+
+    #[test]
+    fn test_filters() {
+        let row = row(json!({"name": "Estonia", "code": "372", "capital": "Tallinn"}));
+        let filter = |json: Value| compile(&serde_json::from_value(json).unwrap()).unwrap();
+        assert!(filter(
+            json!({"type": "like", "dimension": "name", "pattern": "Est%"})
+        )(&row));
+        assert!(!filter(
+            json!({"type": "like", "dimension": "name", "pattern": "Est_"})
+        )(&row));
+        assert!(filter(json!({
+            "type": "bound", "dimension": "code", "lower": "100", "upper": "1000",
+            "lowerStrict": false, "upperStrict": true, "ordering": "numeric"
+        }))(&row));
+        assert!(!filter(json!({
+            "type": "bound", "dimension": "code", "lower": "100", "upper": "1000",
+            "lowerStrict": false, "upperStrict": true, "ordering": "lexicographic"
+        }))(&row));
+        assert!(filter(json!({
+            "type": "not",
+            "field": {"type": "in", "dimension": "capital", "values": ["Riga", "Vilnius"]}
+        }))(&row));
+        let upper = Filter::Selector {
+            dimension: "name".into(),
+            value: "ESTONIA".into(),
+            extract_fn: Some(ExtractFN::Upper { locale: None }),
+        };
+        assert!(compile(&upper).unwrap()(&row));
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(
+            compare("a2", "a10", &SortingOrder::Alphanumeric),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare("a2", "a10", &SortingOrder::Lexicographic),
+            Ordering::Greater
+        );
+        assert_eq!(compare("9", "10", &SortingOrder::Numeric), Ordering::Less);
+    }
+}
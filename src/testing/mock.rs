@@ -0,0 +1,515 @@
+//! A mock Druid broker for testing code that talks to Druid over HTTP.
+//!
+//! [`MockDruid`] listens on an ephemeral localhost port and serves `/druid/v2/` and
+//! `/druid/v2/sql/`. Responses are programmed per [`QueryMatcher`]: json bodies,
+//! errors in Druid's format, injected latency or dropped connections. Every request
+//! the server receives is recorded, so tests can assert on the queries that were sent.
+//!
+//! The server runs on its own thread and runtime and stops when the `MockDruid` is dropped.
+//!
+//! ```rust
+//! use druid_io::testing::mock::{MockDruid, MockResponse, QueryMatcher};
+//! use serde_json::json;
+//!
+//! let druid = MockDruid::start().unwrap();
+//! druid.respond(
+//!     QueryMatcher::native().query_type("timeBoundary"),
+//!     MockResponse::json(json!([{
+//!         "timestamp": "2013-05-09T18:24:00.000Z",
+//!         "result": {
+//!             "minTime": "2013-05-09T18:24:00.000Z",
+//!             "maxTime": "2013-05-09T18:37:00.000Z"
+//!         }
+//!     }])),
+//! );
+//! druid.respond(QueryMatcher::sql(), MockResponse::query_timeout());
+//!
+//! let client = druid.client();
+//! // ... run the code under test against `client`
+//! assert!(druid.received().is_empty());
+//! ```
+use crate::client::DruidClient;
+use crate::serialization::is_subset;
+pub use crate::transport::Endpoint;
+use futures::channel::oneshot;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct ReceivedQuery {
+    pub endpoint: Endpoint,
+    /// Path and query string, e.g. `/druid/v2/?pretty`.
+    pub path: String,
+    pub body: String,
+}
+
+impl ReceivedQuery {
+    /// The body parsed as json, `None` when it isn't valid json.
+    pub fn json(&self) -> Option<Value> {
+        serde_json::from_str(&self.body).ok()
+    }
+}
+
+/// Selects the requests a response is served for. All the conditions set must hold.
+#[derive(Debug, Clone, Default)]
+pub struct QueryMatcher {
+    endpoint: Option<Endpoint>,
+    query_type: Option<String>,
+    data_source: Option<String>,
+    sql_contains: Option<String>,
+    body: Option<Value>,
+}
+
+impl QueryMatcher {
+    /// Matches any request.
+    pub fn any() -> Self {
+        Default::default()
+    }
+
+    /// Matches native queries.
+    pub fn native() -> Self {
+        QueryMatcher {
+            endpoint: Some(Endpoint::Native),
+            ..Default::default()
+        }
+    }
+
+    /// Matches SQL queries.
+    pub fn sql() -> Self {
+        QueryMatcher {
+            endpoint: Some(Endpoint::Sql),
+            ..Default::default()
+        }
+    }
+
+    /// Native queries of the type, e.g. `topN` or `groupBy`.
+    pub fn query_type(mut self, query_type: &str) -> Self {
+        self.query_type = Some(query_type.to_string());
+        self
+    }
+
+    /// Native queries on the table datasource.
+    pub fn data_source(mut self, data_source: &str) -> Self {
+        self.data_source = Some(data_source.to_string());
+        self
+    }
+
+    /// SQL queries whose statement contains the text.
+    pub fn sql_contains(mut self, text: &str) -> Self {
+        self.sql_contains = Some(text.to_string());
+        self
+    }
+
+    /// Requests whose json body has every field set in `body` with the same value.
+    pub fn body_contains(mut self, body: Value) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn matches(&self, query: &ReceivedQuery) -> bool {
+        if self.endpoint.map(|e| e != query.endpoint).unwrap_or(false) {
+            return false;
+        }
+        let json = query.json().unwrap_or(Value::Null);
+        let field = |name: &str| json.get(name).and_then(Value::as_str);
+        if let Some(query_type) = &self.query_type {
+            if field("queryType") != Some(query_type.as_str()) {
+                return false;
+            }
+        }
+        if let Some(data_source) = &self.data_source {
+            let table = match json.get("dataSource") {
+                Some(Value::String(name)) => Some(name.as_str()),
+                Some(data_source) => data_source.get("name").and_then(Value::as_str),
+                None => None,
+            };
+            if table != Some(data_source.as_str()) {
+                return false;
+            }
+        }
+        if let Some(text) = &self.sql_contains {
+            if !field("query")
+                .map(|sql| sql.contains(text.as_str()))
+                .unwrap_or(false)
+            {
+                return false;
+            }
+        }
+        self.body
+            .as_ref()
+            .map(|body| is_subset(body, &json))
+            .unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Reply {
+    Body { status: u16, body: String },
+    Drop,
+}
+
+/// What the mock server answers a matching request with.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    reply: Reply,
+    delay: Option<Duration>,
+}
+
+impl MockResponse {
+    /// `200 OK` with the json body.
+    pub fn json(body: Value) -> Self {
+        MockResponse::status(200, &body.to_string())
+    }
+
+    /// A response with any status and body.
+    pub fn status(status: u16, body: &str) -> Self {
+        MockResponse {
+            reply: Reply::Body {
+                status,
+                body: body.to_string(),
+            },
+            delay: None,
+        }
+    }
+
+    /// An error in the format Druid reports failed queries with.
+    pub fn druid_error(status: u16, error: &str, message: &str, class: &str) -> Self {
+        let body = json!({
+            "error": error,
+            "errorMessage": message,
+            "errorClass": class,
+            "host": null,
+        });
+        MockResponse::status(status, &body.to_string())
+    }
+
+    /// The error of a query that ran longer than its `timeout` context parameter.
+    pub fn query_timeout() -> Self {
+        MockResponse::druid_error(
+            504,
+            "Query timeout",
+            "Query timeout",
+            "java.util.concurrent.TimeoutException",
+        )
+    }
+
+    /// The error of a query rejected because the broker is at capacity.
+    pub fn query_capacity_exceeded() -> Self {
+        MockResponse::druid_error(
+            429,
+            "Query capacity exceeded",
+            "Too many concurrent queries",
+            "org.apache.druid.query.QueryCapacityExceededException",
+        )
+    }
+
+    /// Closes the connection without sending a response.
+    pub fn drop_connection() -> Self {
+        MockResponse {
+            reply: Reply::Drop,
+            delay: None,
+        }
+    }
+
+    /// Waits for `delay` before answering.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+type Responder = Box<dyn Fn(&ReceivedQuery) -> MockResponse + Send>;
+
+struct Rule {
+    matcher: QueryMatcher,
+    responder: Responder,
+    remaining: Option<usize>,
+}
+
+#[derive(Default)]
+struct State {
+    rules: Vec<Rule>,
+    received: Vec<ReceivedQuery>,
+}
+
+impl State {
+    // the first rule that matches and isn't used up answers
+    fn response(&mut self, query: &ReceivedQuery) -> MockResponse {
+        let rule = self.rules.iter_mut().find(|rule| {
+            rule.remaining.map(|n| n > 0).unwrap_or(true) && rule.matcher.matches(query)
+        });
+        match rule {
+            Some(rule) => {
+                if let Some(remaining) = rule.remaining.as_mut() {
+                    *remaining -= 1;
+                }
+                (rule.responder)(query)
+            }
+            None => MockResponse::druid_error(
+                500,
+                "Unknown exception",
+                &format!("no mock response matches {}", query.body),
+                "java.lang.IllegalStateException",
+            ),
+        }
+    }
+}
+
+/// A Druid broker answering with programmed responses, see the [module docs](self).
+pub struct MockDruid {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockDruid {
+    /// Starts a server on an ephemeral localhost port.
+    pub fn start() -> io::Result<Self> {
+        let state = Arc::new(Mutex::new(State::default()));
+        let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+        let (bound, address) = mpsc::channel();
+        let server_state = state.clone();
+        let thread = std::thread::spawn(move || {
+            let mut runtime = match tokio::runtime::Builder::new()
+                .basic_scheduler()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(error) => {
+                    let _ = bound.send(Err(error));
+                    return;
+                }
+            };
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let state = server_state.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request| {
+                            handle(state.clone(), request)
+                        }))
+                    }
+                });
+                let server = match Server::try_bind(&([127, 0, 0, 1], 0).into()) {
+                    Ok(builder) => builder.serve(make_service),
+                    Err(error) => {
+                        let _ = bound.send(Err(io::Error::other(error)));
+                        return;
+                    }
+                };
+                let _ = bound.send(Ok(server.local_addr()));
+                let _ = server
+                    .with_graceful_shutdown(async {
+                        let _ = shutdown_signal.await;
+                    })
+                    .await;
+            });
+        });
+        let address = address
+            .recv()
+            .map_err(|_| io::Error::other("mock druid server failed to start"))??;
+        Ok(MockDruid {
+            address,
+            state,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        })
+    }
+
+    /// `host:port` of the server, as passed to [`DruidClient::new`].
+    pub fn address(&self) -> String {
+        self.address.to_string()
+    }
+
+    /// A client sending its queries to this server.
+    pub fn client(&self) -> DruidClient {
+        DruidClient::new(vec![self.address()])
+    }
+
+    /// Answers every matching request with the response.
+    pub fn respond(&self, matcher: QueryMatcher, response: MockResponse) {
+        self.add_rule(matcher, Box::new(move |_| response.clone()), None);
+    }
+
+    /// Answers the next `times` matching requests with the response, later ones fall
+    /// through to the rules added after it, e.g. to fail a query before it succeeds.
+    pub fn respond_times(&self, matcher: QueryMatcher, response: MockResponse, times: usize) {
+        self.add_rule(matcher, Box::new(move |_| response.clone()), Some(times));
+    }
+
+    /// Answers every matching request with the response computed from it.
+    pub fn respond_with<F>(&self, matcher: QueryMatcher, responder: F)
+    where
+        F: Fn(&ReceivedQuery) -> MockResponse + Send + 'static,
+    {
+        self.add_rule(matcher, Box::new(responder), None);
+    }
+
+    fn add_rule(&self, matcher: QueryMatcher, responder: Responder, remaining: Option<usize>) {
+        self.lock().rules.push(Rule {
+            matcher,
+            responder,
+            remaining,
+        });
+    }
+
+    /// Requests received so far, in order of arrival.
+    pub fn received(&self) -> Vec<ReceivedQuery> {
+        self.lock().received.clone()
+    }
+
+    /// Removes the programmed responses and the recorded requests.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.rules.clear();
+        state.received.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // a panicking responder must not hide the requests from the test
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for MockDruid {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+async fn handle(state: Arc<Mutex<State>>, request: Request<Body>) -> io::Result<Response<Body>> {
+    let path = request
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+    let endpoint = match request.uri().path().trim_end_matches('/') {
+        "/druid/v2" => Endpoint::Native,
+        "/druid/v2/sql" => Endpoint::Sql,
+        _ => return Ok(reply(404, String::new())),
+    };
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .map_err(io::Error::other)?;
+    let query = ReceivedQuery {
+        endpoint,
+        path,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+
+    let response = {
+        let mut state = state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.received.push(query.clone());
+        state.response(&query)
+    };
+    if let Some(delay) = response.delay {
+        tokio::time::delay_for(delay).await;
+    }
+    match response.reply {
+        Reply::Body { status, body } => Ok(reply(status, body)),
+        // hyper closes the connection when the service fails
+        Reply::Drop => Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "mock connection drop",
+        )),
+    }
+}
+
+fn reply(status: u16, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() =
+        StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn native(body: Value) -> ReceivedQuery {
+        ReceivedQuery {
+            endpoint: Endpoint::Native,
+            path: "/druid/v2/?pretty".into(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_matcher() {
+        let top_n = native(json!({
+            "queryType": "topN",
+            "dataSource": {"type": "table", "name": "wikipedia"},
+            "threshold": 3
+        }));
+        assert!(QueryMatcher::any().matches(&top_n));
+        assert!(QueryMatcher::native()
+            .query_type("topN")
+            .data_source("wikipedia")
+            .matches(&top_n));
+        assert!(!QueryMatcher::sql().matches(&top_n));
+        assert!(!QueryMatcher::native().query_type("scan").matches(&top_n));
+        assert!(QueryMatcher::any()
+            .body_contains(json!({"threshold": 3}))
+            .matches(&top_n));
+        assert!(!QueryMatcher::any()
+            .body_contains(json!({"threshold": 4}))
+            .matches(&top_n));
+
+        let sql = ReceivedQuery {
+            endpoint: Endpoint::Sql,
+            path: "/druid/v2/sql/".into(),
+            body: json!({"query": "SELECT page FROM wikipedia"}).to_string(),
+        };
+        assert!(QueryMatcher::sql()
+            .sql_contains("FROM wikipedia")
+            .matches(&sql));
+        assert!(!QueryMatcher::sql()
+            .sql_contains("FROM countries")
+            .matches(&sql));
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut state = State::default();
+        state.rules.push(Rule {
+            matcher: QueryMatcher::any(),
+            responder: Box::new(|_| MockResponse::query_timeout()),
+            remaining: Some(1),
+        });
+        state.rules.push(Rule {
+            matcher: QueryMatcher::native(),
+            responder: Box::new(|_| MockResponse::json(json!([]))),
+            remaining: None,
+        });
+        let query = native(json!({"queryType": "scan"}));
+        let status = |response: MockResponse| match response.reply {
+            Reply::Body { status, .. } => status,
+            Reply::Drop => 0,
+        };
+        assert_eq!(status(state.response(&query)), 504);
+        assert_eq!(status(state.response(&query)), 200);
+        assert_eq!(status(state.response(&query)), 200);
+    }
+}
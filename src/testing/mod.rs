@@ -0,0 +1,1012 @@
+//! In-memory execution of native queries, so tests can run without a Druid cluster.
+//!
+//! Available with the `testing` feature. [`InMemoryDruid`] keeps rows per datasource and
+//! answers Scan, Timeseries, TopN, GroupBy, Search and TimeBoundary queries with the json
+//! Druid would return, so results deserialize into the usual response types. The common
+//! filters, dimensions, aggregations, granularities and post aggregations are implemented,
+//! sketches and cardinality are counted exactly. Javascript, lookups and time format
+//! extractions fail with `DruidClientError::UnsupportedInMemory`.
+//!
+//! Results of the `all` granularity are timestamped with the start of the query interval and
+//! timeseries fill empty buckets up to its end, within unbounded intervals like `ETERNITY` both
+//! go by the rows instead, the way Druid only reads the segments that have data.
+//!
+//! ```rust
+//! use druid_io::ingestion::TimestampSpec;
+//! use druid_io::query::definitions::{Aggregation, Dimension, Granularity};
+//! use druid_io::query::top_n::TopN;
+//! use druid_io::query::DataSource;
+//! use druid_io::testing::InMemoryDruid;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug)]
+//! struct Edit {
+//!     timestamp: String,
+//!     page: String,
+//!     added: i64,
+//! }
+//!
+//! let mut druid = InMemoryDruid::new();
+//! let edits = vec![
+//!     Edit { timestamp: "2015-09-12T00:46:58Z".into(), page: "Rust".into(), added: 10 },
+//!     Edit { timestamp: "2015-09-12T01:12:04Z".into(), page: "Druid".into(), added: 3 },
+//!     Edit { timestamp: "2015-09-12T02:30:45Z".into(), page: "Rust".into(), added: 7 },
+//! ];
+//! druid
+//!     .insert_rows("wikipedia", &TimestampSpec::new("timestamp", "iso"), &edits)
+//!     .unwrap();
+//!
+//! #[derive(Deserialize, Debug)]
+//! struct PageEdits {
+//!     page: String,
+//!     added: i64,
+//! }
+//!
+//! let top_n = TopN {
+//!     data_source: DataSource::table("wikipedia"),
+//!     dimension: Dimension::default("page"),
+//!     threshold: 1,
+//!     metric: "added".into(),
+//!     aggregations: vec![Aggregation::long_sum("added", "added")],
+//!     post_aggregations: vec![],
+//!     intervals: vec!["2015-09-12/2015-09-13".into()],
+//!     filter: None,
+//!     granularity: Granularity::All,
+//!     context: Default::default(),
+//! };
+//! let result = druid.top_n::<PageEdits>(&top_n).unwrap();
+//! assert_eq!(result[0].result[0].page, "Rust");
+//! assert_eq!(result[0].result[0].added, 17);
+//! ```
+mod aggregate;
+mod filter;
+pub mod mock;
+mod time;
+
+use crate::client::{ClientResult, DruidClientError};
+use crate::ingestion::TimestampSpec;
+use crate::query::definitions::{Filter, Granularity, Ordering as SortDirection, SortingOrder};
+use crate::query::group_by::{GroupBy, HavingSpec, LimitSpec};
+use crate::query::response::{
+    GroupByResponse, ScanResponse, SearchResponse, TimeBoundaryResponse, TimeseriesResponse,
+    TopNResponse,
+};
+use crate::query::scan::{ResultFormat, Scan};
+use crate::query::search::Search;
+use crate::query::time_boundary::{TimeBoundType, TimeBoundary};
+use crate::query::timeseries::Timeseries;
+use crate::query::top_n::{TopN, TopNMetricSpec};
+use crate::query::{DataSource, JsonAny, JsonNumber, Query};
+use crate::time::{format_instant, parse_instant, parse_interval, MAX_INSTANT, MIN_INSTANT};
+use crate::typed::TypedQuery;
+use aggregate::{check_post_aggregation, post_aggregate, Aggregator, State};
+use filter::{compare, compile_filter, search_spec, DimensionSpec, Predicate};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use time::{bucket_start, check_granularity, next_bucket};
+
+#[derive(Debug, Clone)]
+pub(crate) struct Row {
+    pub(crate) time: i64,
+    pub(crate) columns: Map<String, Value>,
+}
+
+impl Row {
+    /// Value of a column, `__time` is the row timestamp in epoch millis.
+    pub(crate) fn get(&self, column: &str) -> Value {
+        if column == "__time" {
+            return Value::from(self.time);
+        }
+        self.columns.get(column).cloned().unwrap_or(Value::Null)
+    }
+
+    /// Value of a column as a dimension.
+    pub(crate) fn string(&self, column: &str) -> Option<String> {
+        string_value(&self.get(column))
+    }
+}
+
+/// Druid dimension value, empty strings are nulls.
+pub(crate) fn string_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(value) if value.is_empty() => None,
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+/// Numeric value of a metric, strings are parsed like druid does for CSV inputs.
+pub(crate) fn number_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+pub(crate) fn unsupported(feature: &str) -> DruidClientError {
+    DruidClientError::UnsupportedInMemory {
+        feature: feature.to_string(),
+    }
+}
+
+/// Datasources held in memory, see the [module](self) docs.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryDruid {
+    data_sources: HashMap<String, Vec<Row>>,
+}
+
+impl InMemoryDruid {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Appends rows serialized as json objects. The timestamp column isn't kept as a dimension.
+    pub fn insert_rows<T: Serialize>(
+        &mut self,
+        data_source: &str,
+        timestamp: &TimestampSpec,
+        rows: &[T],
+    ) -> ClientResult<()> {
+        let rows = rows
+            .iter()
+            .map(|row| match serde_json::to_value(row) {
+                Ok(Value::Object(columns)) => Ok(columns),
+                Ok(_) => Err(unsupported("rows that aren't json objects")),
+                Err(source) => Err(DruidClientError::ParsingError { source }),
+            })
+            .collect::<ClientResult<Vec<_>>>()?;
+        self.append(data_source, timestamp, rows)
+    }
+
+    /// Appends the rows of a CSV with a header line, e.g. `docker/druid/config/countries.csv`.
+    /// Values are kept as strings and parsed when aggregated, empty values are nulls, like
+    /// druid's CSV input format does.
+    pub fn load_csv<R: Read>(
+        &mut self,
+        data_source: &str,
+        timestamp: &TimestampSpec,
+        reader: R,
+    ) -> ClientResult<()> {
+        let csv_error = |source| DruidClientError::ParsingCsvResponseError { source };
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader.headers().map_err(csv_error)?.clone();
+        let mut rows = vec![];
+        for record in reader.records() {
+            let record = record.map_err(csv_error)?;
+            let columns = headers
+                .iter()
+                .zip(record.iter())
+                .map(|(header, value)| {
+                    let value = if value.is_empty() {
+                        Value::Null
+                    } else {
+                        Value::from(value)
+                    };
+                    (header.to_string(), value)
+                })
+                .collect();
+            rows.push(columns);
+        }
+        self.append(data_source, timestamp, rows)
+    }
+
+    fn append(
+        &mut self,
+        data_source: &str,
+        timestamp: &TimestampSpec,
+        rows: Vec<Map<String, Value>>,
+    ) -> ClientResult<()> {
+        let mut parsed = Vec::with_capacity(rows.len());
+        for columns in rows {
+            let time = row_timestamp(timestamp, columns.get(&timestamp.column).cloned())?;
+            // keeps the order of the remaining columns
+            let columns = columns
+                .into_iter()
+                .filter(|(column, _)| *column != timestamp.column)
+                .collect();
+            parsed.push(Row { time, columns });
+        }
+        self.data_sources
+            .entry(data_source.to_string())
+            .or_default()
+            .extend(parsed);
+        Ok(())
+    }
+
+    /// Druid's json response to the query.
+    pub fn run(&self, query: &Query) -> ClientResult<Value> {
+        match query {
+            Query::GroupBy(query) => self.run_group_by(query),
+            Query::Scan(query) => self.run_scan(query),
+            Query::Search(query) => self.run_search(query),
+            Query::TimeBoundary(query) => self.run_time_boundary(query),
+            Query::Timeseries(query) => self.run_timeseries(query),
+            Query::TopN(query) => self.run_top_n(query),
+            Query::SegmentMetadata(_) => Err(unsupported("segmentMetadata query")),
+        }
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, query: &Query) -> ClientResult<Vec<T>> {
+        parse(self.run(query)?)
+    }
+
+    pub fn top_n<T: DeserializeOwned>(&self, query: &TopN) -> ClientResult<Vec<TopNResponse<T>>> {
+        parse(self.run_top_n(query)?)
+    }
+
+    pub fn search(&self, query: &Search) -> ClientResult<Vec<SearchResponse>> {
+        parse(self.run_search(query)?)
+    }
+
+    pub fn group_by<T: DeserializeOwned>(
+        &self,
+        query: &GroupBy,
+    ) -> ClientResult<Vec<GroupByResponse<T>>> {
+        parse(self.run_group_by(query)?)
+    }
+
+    pub fn scan<T: DeserializeOwned>(&self, query: &Scan) -> ClientResult<Vec<ScanResponse<T>>> {
+        parse(self.run_scan(query)?)
+    }
+
+    pub fn time_boundary(&self, query: &TimeBoundary) -> ClientResult<Vec<TimeBoundaryResponse>> {
+        parse(self.run_time_boundary(query)?)
+    }
+
+    pub fn timeseries<T: DeserializeOwned>(
+        &self,
+        query: &Timeseries,
+    ) -> ClientResult<Vec<TimeseriesResponse<T>>> {
+        parse(self.run_timeseries(query)?)
+    }
+
+    /// Counterpart of [`DruidClient::execute`](crate::client::DruidClient::execute).
+    pub fn execute<Q: TypedQuery>(&self, query: &Q) -> ClientResult<Q::Response> {
+        let query = serde_json::to_value(query.query())
+            .and_then(serde_json::from_value::<Query>)
+            .map_err(|source| DruidClientError::ParsingError { source })?;
+        parse(self.run(&query)?)
+    }
+
+    fn rows(&self, data_source: &DataSource) -> ClientResult<Vec<Row>> {
+        let table = |name: &str| self.data_sources.get(name).cloned().unwrap_or_default();
+        match data_source {
+            DataSource::Table { name } => Ok(table(name)),
+            DataSource::Union { data_sources } => {
+                Ok(data_sources.iter().flat_map(|name| table(name)).collect())
+            }
+            DataSource::Inline { column_names, rows } => Ok(rows
+                .iter()
+                .map(|values| {
+                    let columns: Map<String, Value> = column_names
+                        .iter()
+                        .cloned()
+                        .zip(values.iter().map(|value| Value::from(value.as_str())))
+                        .collect();
+                    let time = columns
+                        .get("__time")
+                        .and_then(number_value)
+                        .unwrap_or_default() as i64;
+                    Row { time, columns }
+                })
+                .collect()),
+            DataSource::Lookup { .. } => Err(unsupported("lookup datasource")),
+            DataSource::Query { .. } => Err(unsupported("query datasource")),
+            DataSource::Join { .. } => Err(unsupported("join datasource")),
+        }
+    }
+
+    // rows of the datasource within the intervals that match the filter
+    fn select(
+        &self,
+        data_source: &DataSource,
+        intervals: &[(i64, i64)],
+        filter: Option<&Filter>,
+    ) -> ClientResult<Vec<Row>> {
+        let filter = compile_filter(filter)?;
+        Ok(self
+            .rows(data_source)?
+            .into_iter()
+            .filter(|row| {
+                intervals
+                    .iter()
+                    .any(|(start, end)| *start <= row.time && row.time < *end)
+            })
+            .filter(|row| filter(row))
+            .collect())
+    }
+
+    fn run_timeseries(&self, query: &Timeseries) -> ClientResult<Value> {
+        check_granularity(&query.granularity)?;
+        let aggregators = compile_aggregations(&query.aggregations)?;
+        query
+            .post_aggregations
+            .iter()
+            .try_for_each(check_post_aggregation)?;
+        let intervals = parse_intervals(&query.intervals)?;
+        let rows = self.select(&query.data_source, &intervals, query.filter.as_ref())?;
+        let all = all_start(&intervals, &rows);
+        let mut buckets = group(&rows, &aggregators, |row| {
+            bucket_start(&query.granularity, row.time).unwrap_or(all)
+        });
+
+        let skip_empty = query
+            .context
+            .get("skipEmptyBuckets")
+            .map(|skip| skip == "true")
+            .unwrap_or(false);
+        if !skip_empty && !matches!(query.granularity, Granularity::None) {
+            let bounds = (
+                buckets.keys().next().copied(),
+                buckets.keys().next_back().copied(),
+            );
+            for &(start, end) in &intervals {
+                // like druid, which only fills within segments, unbounded intervals
+                // are filled between the buckets with rows
+                let first = match start {
+                    MIN_INSTANT => bounds.0,
+                    start => Some(bucket_start(&query.granularity, start).unwrap_or(all)),
+                };
+                let end = match end {
+                    MAX_INSTANT => bounds.1.map(|last| last + 1),
+                    end => Some(end),
+                };
+                let mut bucket = first;
+                while let Some(start) = bucket.filter(|start| Some(*start) < end) {
+                    buckets
+                        .entry(start)
+                        .or_insert_with(|| initial(&aggregators));
+                    bucket = next_bucket(&query.granularity, start);
+                }
+            }
+        }
+
+        let mut results: Vec<Value> = buckets
+            .into_iter()
+            .map(|(time, states)| {
+                let mut event = Map::new();
+                finish(&aggregators, &states, &mut event);
+                post_aggregations(&query.post_aggregations, &mut event);
+                json!({"timestamp": format_instant(time), "result": event})
+            })
+            .collect();
+        if query.descending {
+            results.reverse();
+        }
+        if let Some(limit) = query.limit {
+            results.truncate(limit);
+        }
+        Ok(Value::from(results))
+    }
+
+    fn run_top_n(&self, query: &TopN) -> ClientResult<Value> {
+        check_granularity(&query.granularity)?;
+        let aggregators = compile_aggregations(&query.aggregations)?;
+        let dimension = DimensionSpec::compile(&query.dimension)?;
+        let (key, descending) = top_n_order(&query.metric, &dimension.output_name)?;
+        query
+            .post_aggregations
+            .iter()
+            .try_for_each(check_post_aggregation)?;
+        let intervals = parse_intervals(&query.intervals)?;
+        let rows = self.select(&query.data_source, &intervals, query.filter.as_ref())?;
+        let all = all_start(&intervals, &rows);
+        let groups = group(&rows, &aggregators, |row| {
+            let bucket = bucket_start(&query.granularity, row.time).unwrap_or(all);
+            (bucket, dimension.value(row))
+        });
+
+        let mut buckets: BTreeMap<i64, Vec<Map<String, Value>>> = BTreeMap::new();
+        for ((time, value), states) in groups {
+            let mut event = Map::new();
+            event.insert(dimension.output_name.clone(), dimension.output(&value));
+            finish(&aggregators, &states, &mut event);
+            post_aggregations(&query.post_aggregations, &mut event);
+            buckets.entry(time).or_default().push(event);
+        }
+        let results: Vec<Value> = buckets
+            .into_iter()
+            .map(|(time, mut events)| {
+                let key = |event: &Map<String, Value>| event.get(&key).cloned();
+                events.sort_by(|left, right| {
+                    let order = compare_values(
+                        &key(left).unwrap_or_default(),
+                        &key(right).unwrap_or_default(),
+                    );
+                    if descending {
+                        order.reverse()
+                    } else {
+                        order
+                    }
+                });
+                events.truncate(query.threshold);
+                json!({"timestamp": format_instant(time), "result": events})
+            })
+            .collect();
+        Ok(Value::from(results))
+    }
+
+    fn run_group_by(&self, query: &GroupBy) -> ClientResult<Value> {
+        check_granularity(&query.granularity)?;
+        if !query.subtotal_spec.is_empty() {
+            return Err(unsupported("groupBy subtotals"));
+        }
+        let aggregators = compile_aggregations(&query.aggregations)?;
+        let dimensions = query
+            .dimensions
+            .iter()
+            .map(DimensionSpec::compile)
+            .collect::<ClientResult<Vec<_>>>()?;
+        query
+            .post_aggregations
+            .iter()
+            .try_for_each(check_post_aggregation)?;
+        let having = match &query.having {
+            Some(having) => Some(compile_having(having)?),
+            None => None,
+        };
+        let intervals = parse_intervals(&query.intervals)?;
+        let rows = self.select(&query.data_source, &intervals, query.filter.as_ref())?;
+        let all = all_start(&intervals, &rows);
+        let groups = group(&rows, &aggregators, |row| {
+            let bucket = bucket_start(&query.granularity, row.time).unwrap_or(all);
+            let values: Vec<_> = dimensions
+                .iter()
+                .map(|dimension| dimension.value(row))
+                .collect();
+            (bucket, values)
+        });
+
+        let mut results = vec![];
+        for ((time, values), states) in groups {
+            let mut event = Map::new();
+            for (dimension, value) in dimensions.iter().zip(&values) {
+                event.insert(dimension.output_name.clone(), dimension.output(value));
+            }
+            finish(&aggregators, &states, &mut event);
+            post_aggregations(&query.post_aggregations, &mut event);
+            let row = Row {
+                time,
+                columns: event,
+            };
+            if having.as_ref().map(|having| having(&row)).unwrap_or(true) {
+                results.push(row);
+            }
+        }
+        if let Some(limit_spec) = &query.limit_spec {
+            sort_and_limit(&mut results, limit_spec);
+        }
+        let results: Vec<Value> = results
+            .into_iter()
+            .map(|row| {
+                json!({"version": "v1", "timestamp": format_instant(row.time), "event": row.columns})
+            })
+            .collect();
+        Ok(Value::from(results))
+    }
+
+    fn run_scan(&self, query: &Scan) -> ClientResult<Value> {
+        let intervals = parse_intervals(&query.intervals)?;
+        let mut rows = self.select(&query.data_source, &intervals, query.filter.as_ref())?;
+        match query.ordering {
+            Some(SortDirection::Ascending) => rows.sort_by_key(|row| row.time),
+            Some(SortDirection::Descending) => rows.sort_by_key(|row| std::cmp::Reverse(row.time)),
+            _ => {}
+        }
+        if let Some(limit) = query.limit {
+            rows.truncate(limit);
+        }
+        if rows.is_empty() {
+            return Ok(Value::from(Vec::<Value>::new()));
+        }
+
+        let columns = if query.columns.is_empty() {
+            let mut columns = vec!["__time".to_string()];
+            for row in &rows {
+                for column in row.columns.keys() {
+                    if !columns.contains(column) {
+                        columns.push(column.clone());
+                    }
+                }
+            }
+            columns
+        } else {
+            query.columns.clone()
+        };
+        let events = rows
+            .iter()
+            .map(|row| match query.result_format {
+                ResultFormat::List => Ok(Value::from(
+                    columns
+                        .iter()
+                        .map(|column| (column.clone(), row.get(column)))
+                        .collect::<Map<_, _>>(),
+                )),
+                ResultFormat::CompactedList => Ok(Value::from(
+                    columns
+                        .iter()
+                        .map(|column| row.get(column))
+                        .collect::<Vec<_>>(),
+                )),
+                ResultFormat::ValueVector => Err(unsupported("valueVector scan results")),
+            })
+            .collect::<ClientResult<Vec<_>>>()?;
+
+        let segment_id = format!("{}_inmemory", data_source_name(&query.data_source));
+        let batch_size = if query.batch_size == 0 {
+            events.len()
+        } else {
+            query.batch_size
+        };
+        let batches: Vec<Value> = events
+            .chunks(batch_size)
+            .map(|events| json!({"segmentId": segment_id, "columns": columns, "events": events}))
+            .collect();
+        Ok(Value::from(batches))
+    }
+
+    fn run_search(&self, query: &Search) -> ClientResult<Value> {
+        check_granularity(&query.granularity)?;
+        let matches = search_spec(&query.query)?;
+        let intervals = parse_intervals(&query.intervals)?;
+        let rows = self.select(&query.data_source, &intervals, query.filter.as_ref())?;
+        let dimensions = if query.search_dimensions.is_empty() {
+            let mut dimensions: Vec<String> = vec![];
+            for row in &rows {
+                for column in row.columns.keys() {
+                    if !dimensions.contains(column) {
+                        dimensions.push(column.clone());
+                    }
+                }
+            }
+            dimensions
+        } else {
+            query.search_dimensions.clone()
+        };
+
+        let all = all_start(&intervals, &rows);
+        let mut counts: BTreeMap<i64, BTreeMap<(String, String), usize>> = BTreeMap::new();
+        for row in &rows {
+            let bucket = bucket_start(&query.granularity, row.time).unwrap_or(all);
+            for dimension in &dimensions {
+                if let Some(value) = row.string(dimension).filter(|value| matches(value)) {
+                    *counts
+                        .entry(bucket)
+                        .or_default()
+                        .entry((dimension.clone(), value))
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        let sort = query.sort.unwrap_or_default();
+        let results: Vec<Value> = counts
+            .into_iter()
+            .map(|(time, counts)| {
+                let mut hits: Vec<_> = counts.into_iter().collect();
+                hits.sort_by(
+                    |((left_dimension, left), _), ((right_dimension, right), _)| {
+                        compare(left, right, &sort)
+                            .then_with(|| left_dimension.cmp(right_dimension))
+                    },
+                );
+                hits.truncate(query.limit);
+                let hits: Vec<Value> = hits
+                    .into_iter()
+                    .map(|((dimension, value), count)| {
+                        json!({"dimension": dimension, "value": value, "count": count})
+                    })
+                    .collect();
+                json!({"timestamp": format_instant(time), "result": hits})
+            })
+            .collect();
+        Ok(Value::from(results))
+    }
+
+    fn run_time_boundary(&self, query: &TimeBoundary) -> ClientResult<Value> {
+        let filter = compile_filter(query.filter.as_ref())?;
+        let rows = self.rows(&query.data_source)?;
+        let times = rows.iter().filter(|row| filter(row)).map(|row| row.time);
+        let (min, max) = match (times.clone().min(), times.max()) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return Ok(Value::from(Vec::<Value>::new())),
+        };
+        let (timestamp, result) = match query.bound {
+            TimeBoundType::MinTime => (min, json!({"minTime": format_instant(min)})),
+            TimeBoundType::MaxTime => (max, json!({"maxTime": format_instant(max)})),
+            TimeBoundType::MinMaxTime => (
+                min,
+                json!({"minTime": format_instant(min), "maxTime": format_instant(max)}),
+            ),
+        };
+        Ok(json!([{"timestamp": format_instant(timestamp), "result": result}]))
+    }
+}
+
+fn parse<T: DeserializeOwned>(value: Value) -> ClientResult<T> {
+    serde_json::from_value(value)
+        .map_err(|source| DruidClientError::ParsingResponseError { source })
+}
+
+fn row_timestamp(spec: &TimestampSpec, value: Option<Value>) -> ClientResult<i64> {
+    let value = match value.filter(|value| string_value(value).is_some()) {
+        Some(value) => value,
+        None => {
+            return match &spec.missing_value {
+                Some(missing) => parse_instant(missing),
+                None => Err(DruidClientError::InvalidTimestamp {
+                    value: format!("missing `{}` column", spec.column),
+                }),
+            }
+        }
+    };
+    let invalid = || DruidClientError::InvalidTimestamp {
+        value: string_value(&value).unwrap_or_default(),
+    };
+    let number = || number_value(&value).ok_or_else(invalid);
+    match spec.format.as_deref().unwrap_or("auto") {
+        "millis" => Ok(number()? as i64),
+        "posix" => Ok((number()? * 1000.0) as i64),
+        "micro" => Ok((number()? / 1000.0) as i64),
+        "nano" => Ok((number()? / 1_000_000.0) as i64),
+        "iso" => parse_instant(&string_value(&value).unwrap_or_default()),
+        "auto" => match number_value(&value) {
+            Some(millis) => Ok(millis as i64),
+            None => parse_instant(&string_value(&value).unwrap_or_default()),
+        },
+        format => Err(unsupported(&format!("timestamp format `{}`", format))),
+    }
+}
+
+fn data_source_name(data_source: &DataSource) -> String {
+    match data_source {
+        DataSource::Table { name } => name.clone(),
+        DataSource::Union { data_sources } => data_sources.join("_"),
+        _ => "inline".to_string(),
+    }
+}
+
+fn compile_aggregations(
+    aggregations: &[crate::query::definitions::Aggregation],
+) -> ClientResult<Vec<Aggregator>> {
+    aggregations.iter().map(Aggregator::compile).collect()
+}
+
+fn initial(aggregators: &[Aggregator]) -> Vec<State> {
+    aggregators.iter().map(Aggregator::initial).collect()
+}
+
+fn parse_intervals(intervals: &[String]) -> ClientResult<Vec<(i64, i64)>> {
+    intervals
+        .iter()
+        .map(|interval| parse_interval(interval))
+        .collect()
+}
+
+// timestamp of results with `all` granularity, the start of the earliest interval or, when that's
+// unbounded, of the earliest row, like druid which then only reads the segments with data
+fn all_start(intervals: &[(i64, i64)], rows: &[Row]) -> i64 {
+    match intervals.iter().map(|(start, _)| *start).min() {
+        Some(MIN_INSTANT) | None => rows.iter().map(|row| row.time).min().unwrap_or_default(),
+        Some(start) => start,
+    }
+}
+
+// aggregates the rows by key
+fn group<K: Ord>(
+    rows: &[Row],
+    aggregators: &[Aggregator],
+    key: impl Fn(&Row) -> K,
+) -> BTreeMap<K, Vec<State>> {
+    let mut groups = BTreeMap::new();
+    for row in rows {
+        let states = groups
+            .entry(key(row))
+            .or_insert_with(|| initial(aggregators));
+        for (aggregator, state) in aggregators.iter().zip(states.iter_mut()) {
+            aggregator.add(state, row);
+        }
+    }
+    groups
+}
+
+fn finish(aggregators: &[Aggregator], states: &[State], event: &mut Map<String, Value>) {
+    for (aggregator, state) in aggregators.iter().zip(states) {
+        event.insert(aggregator.name.clone(), aggregator.finish(state));
+    }
+}
+
+// later post aggregations can refer to earlier ones
+fn post_aggregations(
+    post_aggregations: &[crate::query::group_by::PostAggregation],
+    event: &mut Map<String, Value>,
+) {
+    for post_aggregation in post_aggregations {
+        let value = post_aggregate(post_aggregation, event);
+        event.insert(post_aggregation.name().to_string(), value);
+    }
+}
+
+fn compile_having(having: &HavingSpec) -> ClientResult<Predicate> {
+    let number = |value: &JsonNumber| match value {
+        JsonNumber::Float(value) => *value as f64,
+        JsonNumber::Integer(value) => *value as f64,
+    };
+    let comparison = |aggregation: &str, value: &JsonNumber, expected: Ordering| -> Predicate {
+        let aggregation = aggregation.to_string();
+        let value = number(value);
+        Box::new(move |row| {
+            number_value(&row.get(&aggregation))
+                .and_then(|actual| actual.partial_cmp(&value))
+                .map(|ordering| ordering == expected)
+                .unwrap_or(false)
+        })
+    };
+    Ok(match having {
+        HavingSpec::Filter { filter } => compile_filter(Some(filter))?,
+        HavingSpec::GreaterThan { aggregation, value } => {
+            comparison(aggregation, value, Ordering::Greater)
+        }
+        HavingSpec::EqualTo { aggregation, value } => {
+            comparison(aggregation, value, Ordering::Equal)
+        }
+        HavingSpec::LessThan { aggregation, value } => {
+            comparison(aggregation, value, Ordering::Less)
+        }
+        HavingSpec::DimSelector { dimension, value } => {
+            let output_name = dimension.output_name().to_string();
+            let value = match value {
+                JsonAny::Float(value) => value.to_string(),
+                JsonAny::Integer(value) => value.to_string(),
+                JsonAny::STRING(value) => value.clone(),
+                JsonAny::Boolean(value) => value.to_string(),
+            };
+            Box::new(move |row| row.string(&output_name).unwrap_or_default() == value)
+        }
+        HavingSpec::And { having_specs } => {
+            let specs = having_specs
+                .iter()
+                .map(compile_having)
+                .collect::<ClientResult<Vec<_>>>()?;
+            Box::new(move |row| specs.iter().all(|spec| spec(row)))
+        }
+        HavingSpec::Or { having_specs } => {
+            let specs = having_specs
+                .iter()
+                .map(compile_having)
+                .collect::<ClientResult<Vec<_>>>()?;
+            Box::new(move |row| specs.iter().any(|spec| spec(row)))
+        }
+        HavingSpec::Not { having_specs } => {
+            let spec = compile_having(having_specs)?;
+            Box::new(move |row| !spec(row))
+        }
+    })
+}
+
+// metrics are compared as numbers, dimensions in the column's order
+fn sort_and_limit(rows: &mut Vec<Row>, limit_spec: &LimitSpec) {
+    rows.sort_by(|left, right| {
+        for column in &limit_spec.columns {
+            let (left, right) = (left.get(&column.dimension), right.get(&column.dimension));
+            let ordering = match (&left, &right) {
+                (Value::Number(_), Value::Number(_)) => compare_values(&left, &right),
+                _ => match (string_value(&left), string_value(&right)) {
+                    (Some(left), Some(right)) => compare(&left, &right, &column.dimension_order),
+                    (left, right) => left.cmp(&right),
+                },
+            };
+            let ordering = match column.direction {
+                SortDirection::Descending => ordering.reverse(),
+                _ => ordering,
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+    rows.truncate(limit_spec.limit);
+}
+
+// the event field TopN orders by, and whether the order is descending
+fn top_n_order(spec: &TopNMetricSpec, dimension: &str) -> ClientResult<(String, bool)> {
+    match spec {
+        TopNMetricSpec::Numeric { metric } => Ok((metric.clone(), true)),
+        TopNMetricSpec::Dimension {
+            ordering: SortingOrder::Lexicographic,
+            previous_stop: None,
+        } => Ok((dimension.to_string(), false)),
+        TopNMetricSpec::Dimension { .. } => Err(unsupported("topN dimension ordering")),
+        TopNMetricSpec::Inverted { metric } => {
+            let (key, descending) = top_n_order(metric, dimension)?;
+            Ok((key, !descending))
+        }
+    }
+}
+
+fn compare_values(left: &Value, right: &Value) -> Ordering {
+    match (number_value(left), number_value(right)) {
+        (Some(left), Some(right)) => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
+        _ => string_value(left).cmp(&string_value(right)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::definitions::{Aggregation, Dimension, OutputType};
+    use crate::query::group_by::{
+        GroupByBuilder, OrderByColumnSpec, PostAggregation, PostAggregator,
+    };
+
+    fn druid() -> InMemoryDruid {
+        let mut druid = InMemoryDruid::new();
+        let rows = json!([
+            {"ts": "2015-09-12T00:10:00Z", "page": "Rust", "user": "ann", "added": 10},
+            {"ts": "2015-09-12T00:40:00Z", "page": "Druid", "user": "bob", "added": 3},
+            {"ts": "2015-09-12T02:30:00Z", "page": "Rust", "user": "bob", "added": 7},
+            {"ts": "2015-09-13T01:00:00Z", "page": "Rust", "user": "cid", "added": 1}
+        ]);
+        druid
+            .insert_rows(
+                "wikipedia",
+                &TimestampSpec::new("ts", "iso"),
+                rows.as_array().unwrap(),
+            )
+            .unwrap();
+        druid
+    }
+
+    fn eternity() -> Vec<String> {
+        vec!["1000-01-01/3000-01-01".into()]
+    }
+
+    #[test]
+    fn test_timeseries() {
+        let timeseries = Timeseries {
+            data_source: DataSource::table("wikipedia"),
+            granularity: Granularity::Hour,
+            descending: false,
+            intervals: vec!["2015-09-12T00:00/2015-09-12T04:00".into()],
+            filter: None,
+            aggregations: vec![
+                Aggregation::count("edits"),
+                Aggregation::long_sum("added", "added"),
+                Aggregation::StringLast {
+                    name: "user".into(),
+                    field_name: "user".into(),
+                    max_string_bytes: 1024,
+                },
+            ],
+            post_aggregations: vec![PostAggregation::Arithmetic {
+                name: "per_edit".into(),
+                function: "/".into(),
+                fields: vec![
+                    PostAggregator::field_access("added", "added"),
+                    PostAggregator::field_access("edits", "edits"),
+                ],
+                ordering: None,
+            }],
+            limit: None,
+            context: Default::default(),
+        };
+        let result = druid().run(&Query::Timeseries(timeseries)).unwrap();
+        assert_eq!(
+            result,
+            json!([
+                {"timestamp": "2015-09-12T00:00:00.000Z", "result": {"edits": 2, "added": 13, "user": "bob", "per_edit": 6.5}},
+                {"timestamp": "2015-09-12T01:00:00.000Z", "result": {"edits": 0, "added": 0, "user": null, "per_edit": 0.0}},
+                {"timestamp": "2015-09-12T02:00:00.000Z", "result": {"edits": 1, "added": 7, "user": "bob", "per_edit": 7.0}},
+                // empty buckets are filled up to the end of the interval
+                {"timestamp": "2015-09-12T03:00:00.000Z", "result": {"edits": 0, "added": 0, "user": null, "per_edit": 0.0}}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_group_by() {
+        let group_by = GroupByBuilder::new(DataSource::table("wikipedia"))
+            .dimensions(vec![Dimension::Default {
+                dimension: "page".into(),
+                output_name: "page".into(),
+                output_type: OutputType::STRING,
+            }])
+            .aggregations(vec![
+                Aggregation::long_sum("added", "added"),
+                Aggregation::Cardinality {
+                    name: "users".into(),
+                    fields: vec!["user".into()],
+                    by_row: false,
+                    round: true,
+                },
+            ])
+            .having(HavingSpec::greater_than("added", 5.into()))
+            .limit(LimitSpec {
+                limit: 10,
+                columns: vec![OrderByColumnSpec::new(
+                    "added",
+                    SortDirection::Descending,
+                    Default::default(),
+                )],
+            })
+            .granularity(Granularity::All)
+            .intervals(vec!["2015-09-12/2015-09-14"])
+            .build();
+        let result = druid().run(&Query::GroupBy(group_by)).unwrap();
+        assert_eq!(
+            result,
+            json!([
+                {"version": "v1", "timestamp": "2015-09-12T00:00:00.000Z", "event": {"page": "Rust", "added": 18, "users": 3}}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_scan_and_time_boundary() {
+        let scan = Scan {
+            data_source: DataSource::table("wikipedia"),
+            intervals: eternity(),
+            result_format: ResultFormat::CompactedList,
+            filter: Some(Filter::selector("user", "bob")),
+            columns: vec!["__time".into(), "page".into()],
+            batch_size: 1,
+            limit: None,
+            ordering: Some(SortDirection::Descending),
+            context: Default::default(),
+        };
+        let result = druid().run(&Query::Scan(scan)).unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 2);
+        assert_eq!(result[0]["events"], json!([[1_442_025_000_000i64, "Rust"]]));
+
+        let time_boundary = TimeBoundary {
+            data_source: DataSource::table("wikipedia"),
+            bound: TimeBoundType::MaxTime,
+            filter: None,
+            context: Default::default(),
+        };
+        let result = druid().run(&Query::TimeBoundary(time_boundary)).unwrap();
+        assert_eq!(
+            result,
+            json!([{"timestamp": "2015-09-13T01:00:00.000Z", "result": {"maxTime": "2015-09-13T01:00:00.000Z"}}])
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut timeseries = Timeseries {
+            data_source: DataSource::table("wikipedia"),
+            granularity: Granularity::All,
+            descending: false,
+            intervals: eternity(),
+            filter: Some(Filter::javascript("page", "function(x) { return true; }")),
+            aggregations: vec![],
+            post_aggregations: vec![],
+            limit: None,
+            context: Default::default(),
+        };
+        assert!(matches!(
+            druid().timeseries::<Value>(&timeseries),
+            Err(DruidClientError::UnsupportedInMemory { .. })
+        ));
+        timeseries.filter = None;
+        timeseries.granularity = Granularity::Duration { duration: 0 };
+        assert!(matches!(
+            druid().timeseries::<Value>(&timeseries),
+            Err(DruidClientError::UnsupportedInMemory { .. })
+        ));
+        timeseries.granularity = Granularity::All;
+        timeseries.data_source = DataSource::table("no_such_table");
+        // `all` granularity has a row even without data
+        assert_eq!(
+            druid().run(&Query::Timeseries(timeseries)).unwrap(),
+            json!([{"timestamp": "1000-01-01T00:00:00.000Z", "result": {}}])
+        );
+    }
+}
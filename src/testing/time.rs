@@ -0,0 +1,101 @@
+use crate::client::{ClientResult, DruidClientError};
+use crate::query::definitions::Granularity;
+use crate::time::{civil_from_days, days_from_civil, DAY, HOUR, MINUTE, SECOND};
+
+/// Fails on a `duration` granularity without a length, buckets of it can't be computed.
+pub(crate) fn check_granularity(granularity: &Granularity) -> ClientResult<()> {
+    match granularity {
+        Granularity::Duration { duration: 0 } => Err(DruidClientError::UnsupportedInMemory {
+            feature: "duration granularity of 0 ms".to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+// length of buckets that don't depend on the calendar
+fn fixed_size(granularity: &Granularity) -> Option<i64> {
+    Some(match granularity {
+        Granularity::None => 1,
+        Granularity::Second => SECOND,
+        Granularity::Minute => MINUTE,
+        Granularity::FifteenMinute => 15 * MINUTE,
+        Granularity::ThirtyMinute => 30 * MINUTE,
+        Granularity::Hour => HOUR,
+        Granularity::Day => DAY,
+        Granularity::Week => 7 * DAY,
+        Granularity::Duration { duration } => *duration as i64,
+        _ => return None,
+    })
+}
+
+// months in a calendar bucket
+fn months(granularity: &Granularity) -> Option<i64> {
+    match granularity {
+        Granularity::Month => Some(1),
+        Granularity::Quarter => Some(3),
+        Granularity::Year => Some(12),
+        _ => None,
+    }
+}
+
+/// Start of the granularity bucket the instant falls into, `None` for `all`.
+pub(crate) fn bucket_start(granularity: &Granularity, millis: i64) -> Option<i64> {
+    if let Granularity::Week = granularity {
+        // weeks start on monday, 1970-01-01 was a thursday
+        let days = millis.div_euclid(DAY);
+        return Some((days - (days + 3).rem_euclid(7)) * DAY);
+    }
+    if let Some(size) = fixed_size(granularity) {
+        return Some(millis.div_euclid(size) * size);
+    }
+    let months = months(granularity)?;
+    let (year, month, _) = civil_from_days(millis.div_euclid(DAY));
+    Some(days_from_civil(year, (month - 1) / months * months + 1, 1) * DAY)
+}
+
+/// Start of the bucket following the one starting at `start`, `None` for `all`.
+pub(crate) fn next_bucket(granularity: &Granularity, start: i64) -> Option<i64> {
+    if let Some(size) = fixed_size(granularity) {
+        return Some(start + size);
+    }
+    let months = months(granularity)?;
+    let (year, month, _) = civil_from_days(start.div_euclid(DAY));
+    let month_index = year * 12 + month - 1 + months;
+    Some(
+        days_from_civil(
+            month_index.div_euclid(12),
+            month_index.rem_euclid(12) + 1,
+            1,
+        ) * DAY,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::time::{format_instant, parse_instant};
+
+    #[test]
+    fn test_buckets() {
+        let millis = parse_instant("2015-09-12T10:20:30Z").unwrap();
+        let bucket = |granularity| format_instant(bucket_start(&granularity, millis).unwrap());
+        assert_eq!(bucket(Granularity::Hour), "2015-09-12T10:00:00.000Z");
+        assert_eq!(bucket(Granularity::Week), "2015-09-07T00:00:00.000Z");
+        assert_eq!(bucket(Granularity::Quarter), "2015-07-01T00:00:00.000Z");
+        assert_eq!(bucket(Granularity::Year), "2015-01-01T00:00:00.000Z");
+        assert_eq!(bucket_start(&Granularity::All, millis), None);
+        assert!(check_granularity(&Granularity::Duration { duration: 0 }).is_err());
+
+        let next = |granularity, start: &str| {
+            format_instant(next_bucket(&granularity, parse_instant(start).unwrap()).unwrap())
+        };
+        assert_eq!(
+            next(Granularity::Month, "2015-12-01"),
+            "2016-01-01T00:00:00.000Z"
+        );
+        assert_eq!(
+            next(Granularity::Day, "2015-09-12"),
+            "2015-09-13T00:00:00.000Z"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! ISO 8601 instants and intervals the way Druid writes and accepts them.
+use crate::client::{ClientResult, DruidClientError};
+
+pub(crate) const SECOND: i64 = 1000;
+pub(crate) const MINUTE: i64 = 60 * SECOND;
+pub(crate) const HOUR: i64 = 60 * MINUTE;
+pub(crate) const DAY: i64 = 24 * HOUR;
+/// Bounds of [`ETERNITY`](crate::query::definitions::ETERNITY), joda's min and max instants.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) const MIN_INSTANT: i64 = i64::MIN / 2;
+#[cfg(any(test, feature = "testing"))]
+pub(crate) const MAX_INSTANT: i64 = i64::MAX / 2;
+
+/// Epoch millis of an ISO 8601 instant, e.g. `2015-09-12`, `2015-09-12T10:00:00.000Z`
+/// or `2015-09-12T12:00+02:00`. Instants without an offset are UTC.
+pub(crate) fn parse_instant(instant: &str) -> ClientResult<i64> {
+    let invalid = || DruidClientError::InvalidTimestamp {
+        value: instant.to_string(),
+    };
+    let (date, time) = match instant.trim().find('T') {
+        Some(index) => (&instant.trim()[..index], &instant.trim()[index + 1..]),
+        None => (instant.trim(), ""),
+    };
+
+    // the year may be signed and longer than 4 digits, e.g. `-146136543-09-08`
+    let (sign, unsigned) = match date.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, date.strip_prefix('+').unwrap_or(date)),
+    };
+    let mut parts = unsigned.split('-');
+    let year = parts
+        .next()
+        .and_then(|year| year.parse::<i64>().ok())
+        .ok_or_else(invalid)?
+        * sign;
+    let mut date_part = |default: i64| match parts.next() {
+        Some(part) => part.parse::<i64>().map_err(|_| invalid()),
+        None => Ok(default),
+    };
+    let month = date_part(1)?;
+    let day = date_part(1)?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+
+    let (time, offset) = split_offset(time).ok_or_else(invalid)?;
+    let mut millis = 0;
+    if !time.is_empty() {
+        let (time, fraction) = match time.find('.') {
+            Some(index) => (&time[..index], &time[index + 1..]),
+            None => (time, ""),
+        };
+        for (part, unit) in time.split(':').zip(&[HOUR, MINUTE, SECOND]) {
+            millis += part.parse::<i64>().map_err(|_| invalid())? * unit;
+        }
+        if !fraction.is_empty() {
+            let digits: String = fraction.chars().chain("00".chars()).take(3).collect();
+            millis += digits.parse::<i64>().map_err(|_| invalid())?;
+        }
+    }
+    Ok(days_from_civil(year, month, day) * DAY + millis - offset)
+}
+
+// splits `10:00:00+02:00` into the time and the offset in millis
+fn split_offset(time: &str) -> Option<(&str, i64)> {
+    if let Some(time) = time.strip_suffix('Z') {
+        return Some((time, 0));
+    }
+    match time.rfind(['+', '-']) {
+        Some(index) => {
+            let sign = if &time[index..index + 1] == "-" {
+                -1
+            } else {
+                1
+            };
+            let mut parts = time[index + 1..].splitn(2, ':');
+            let hours = parts.next()?.parse::<i64>().ok()?;
+            let minutes = match parts.next() {
+                Some(minutes) => minutes.parse::<i64>().ok()?,
+                None => 0,
+            };
+            Some((&time[..index], sign * (hours * HOUR + minutes * MINUTE)))
+        }
+        None => Some((time, 0)),
+    }
+}
+
+/// Druid's format of an instant, e.g. `2015-09-12T00:00:00.000Z`.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn format_instant(millis: i64) -> String {
+    let days = millis.div_euclid(DAY);
+    let millis = millis.rem_euclid(DAY);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis / HOUR,
+        millis % HOUR / MINUTE,
+        millis % MINUTE / SECOND,
+        millis % SECOND
+    )
+}
+
+/// Start and end (exclusive) of an ISO 8601 interval, `start/end`, `start/period` or `period/end`.
+pub(crate) fn parse_interval(interval: &str) -> ClientResult<(i64, i64)> {
+    let invalid = || DruidClientError::InvalidInterval {
+        interval: interval.to_string(),
+    };
+    let mut parts = interval.trim().splitn(2, '/');
+    let (start, end) = match (parts.next(), parts.next()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Err(invalid()),
+    };
+    let (start, end) = match (start.starts_with('P'), end.starts_with('P')) {
+        (false, false) => (parse_instant(start)?, parse_instant(end)?),
+        (false, true) => {
+            let start = parse_instant(start)?;
+            let period = Period::parse(end).ok_or_else(invalid)?;
+            (start, period.shift(start, 1))
+        }
+        (true, false) => {
+            let end = parse_instant(end)?;
+            let period = Period::parse(start).ok_or_else(invalid)?;
+            (period.shift(end, -1), end)
+        }
+        (true, true) => return Err(invalid()),
+    };
+    if end < start {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+/// ISO 8601 period like `P1M`, `P2W` or `PT1.5S`, months are kept apart since their length
+/// depends on the date they're added to.
+#[derive(Debug, Default, PartialEq)]
+struct Period {
+    months: i64,
+    days: i64,
+    millis: i64,
+}
+
+impl Period {
+    fn parse(period: &str) -> Option<Period> {
+        let rest = period.trim().strip_prefix('P')?;
+        let (date, time) = match rest.find('T') {
+            Some(index) => (&rest[..index], Some(&rest[index + 1..])),
+            None => (rest, None),
+        };
+        let mut result = Period::default();
+        let mut components = 0;
+        for (part, in_time) in std::iter::once((date, false)).chain(time.map(|time| (time, true))) {
+            let mut number = String::new();
+            for char in part.chars() {
+                if char.is_ascii_digit() || (in_time && (char == '.' || char == ',')) {
+                    number.push(if char == ',' { '.' } else { char });
+                    continue;
+                }
+                let value = number.parse::<f64>().ok()?;
+                number.clear();
+                let whole = value as i64;
+                match (in_time, char) {
+                    (false, 'Y') => result.months += 12 * whole,
+                    (false, 'M') => result.months += whole,
+                    (false, 'W') => result.days += 7 * whole,
+                    (false, 'D') => result.days += whole,
+                    (true, 'H') => result.millis += whole * HOUR,
+                    (true, 'M') => result.millis += whole * MINUTE,
+                    (true, 'S') => result.millis += (value * SECOND as f64).round() as i64,
+                    _ => return None,
+                }
+                components += 1;
+            }
+            if !number.is_empty() {
+                return None;
+            }
+        }
+        if components == 0 {
+            return None;
+        }
+        Some(result)
+    }
+
+    // adds (`sign` 1) or subtracts (-1) the period, the day of month is clamped like joda does
+    fn shift(&self, millis: i64, sign: i64) -> i64 {
+        let (year, month, day) = civil_from_days(millis.div_euclid(DAY));
+        let month_index = year * 12 + month - 1 + sign * self.months;
+        let (year, month) = (month_index.div_euclid(12), month_index.rem_euclid(12) + 1);
+        let day = day.min(days_in_month(year, month));
+        let days = days_from_civil(year, month, day) + sign * self.days;
+        days * DAY + millis.rem_euclid(DAY) + sign * self.millis
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let next = year * 12 + month;
+    days_from_civil(next.div_euclid(12), next.rem_euclid(12) + 1, 1)
+        - days_from_civil(year, month, 1)
+}
+
+// days since 1970-01-01 of a proleptic gregorian date,
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::definitions::ETERNITY;
+
+    #[test]
+    fn test_instants() {
+        let millis = parse_instant("2015-09-12T10:20:30.5Z").unwrap();
+        assert_eq!(millis, 1_442_053_230_500);
+        assert_eq!(format_instant(millis), "2015-09-12T10:20:30.500Z");
+        assert_eq!(
+            parse_instant("2015-09-12T12:20:30.500+02:00").unwrap(),
+            millis
+        );
+        assert_eq!(parse_instant("1970-01-01").unwrap(), 0);
+        let (start, end) = parse_interval(ETERNITY).unwrap();
+        assert_eq!((start, end), (MIN_INSTANT, MAX_INSTANT));
+        assert!(parse_instant("2015-13-01").is_err());
+    }
+
+    #[test]
+    fn test_periods() {
+        let interval = |interval| {
+            let (start, end) = parse_interval(interval).unwrap();
+            (format_instant(start), format_instant(end))
+        };
+        assert_eq!(
+            interval("2020-01-31/P1M"),
+            (
+                "2020-01-31T00:00:00.000Z".to_string(),
+                "2020-02-29T00:00:00.000Z".to_string()
+            )
+        );
+        assert_eq!(
+            interval("P1DT1.5S/2020-01-02T00:00:01.500Z").0,
+            "2020-01-01T00:00:00.000Z"
+        );
+        assert_eq!(interval("2020-01-01/P1Y2W").1, "2021-01-15T00:00:00.000Z");
+        for invalid in &[
+            "2020-01-01/P",
+            "2020-01-01/P1H",
+            "P1D/P1D",
+            "2020-01-02/2020-01-01",
+        ] {
+            assert!(parse_interval(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
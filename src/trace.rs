@@ -0,0 +1,260 @@
+//! Spans of the `tracing` feature.
+//!
+//! Queries of [`DruidClient`](crate::client::DruidClient) run in a `druid_query` span
+//! carrying `query_type`, `data_source` and `query_id` (when the context sets a `queryId`),
+//! the http request to the broker in a nested `druid_request` span carrying `broker`,
+//! `trace_id` and `response_size`. Failed queries record the [`kind`] of the error in
+//! the `error` field of both spans.
+//!
+//! Requests carry the W3C `traceparent` (and `tracestate`) headers of the current span's
+//! OpenTelemetry context, so with a `tracing_opentelemetry` layer installed the broker's spans
+//! continue the application's trace. Without a layer the current OpenTelemetry context is used,
+//! and only without any active context a new trace is started for the query. A `traceparent`
+//! header set by an [`Interceptor`](crate::interceptor::Interceptor) is sent as is.
+//!
+//! `query_id` is recorded after the interceptors ran, so it includes a `queryId` they set.
+//!
+//! [`kind`]: crate::client::DruidClientError::kind
+use crate::client::ClientResult;
+use crate::interceptor::OutgoingQuery;
+use crate::transport::TransportRequest;
+use opentelemetry::propagation::{Injector, TextMapPropagator};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::Context;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub const TRACEPARENT: &str = "traceparent";
+
+pub(crate) fn query_span(request: &str) -> Span {
+    let query = serde_json::from_str::<Value>(request).unwrap_or(Value::Null);
+    let data_source = match query.get("dataSource") {
+        Some(Value::String(name)) => Some(name.as_str()),
+        Some(data_source) => data_source.get("name").and_then(Value::as_str),
+        None => None,
+    };
+    let query_type = query.get("queryType").and_then(Value::as_str);
+    tracing::info_span!(
+        "druid_query",
+        query_type = query_type.unwrap_or(""),
+        data_source = data_source.unwrap_or(""),
+        query_id = Empty,
+        error = Empty,
+    )
+}
+
+/// Records the `queryId` of the query, as the interceptors left it, on the current span.
+pub(crate) fn record_query(query: &OutgoingQuery) {
+    let query_id = query
+        .query
+        .get("context")
+        .and_then(|context| context.get("queryId"))
+        .and_then(Value::as_str);
+    if let Some(query_id) = query_id {
+        Span::current().record("query_id", query_id);
+    }
+}
+
+pub(crate) fn request_span() -> Span {
+    tracing::info_span!(
+        "druid_request",
+        broker = Empty,
+        trace_id = Empty,
+        response_size = Empty,
+        error = Empty,
+    )
+}
+
+/// Runs the future in the span, recording the kind of the error it fails with.
+pub(crate) async fn instrument<T, F>(span: Span, future: F) -> ClientResult<T>
+where
+    F: Future<Output = ClientResult<T>>,
+{
+    let result = future.instrument(span.clone()).await;
+    if let Err(error) = &result {
+        span.record("error", error.kind());
+        tracing::debug!(parent: &span, error = %error, "druid query failed");
+    }
+    result
+}
+
+pub(crate) fn record_response_size(size: usize) {
+    Span::current().record("response_size", size);
+}
+
+/// Adds the trace context headers unless the request has a `traceparent`, and records
+/// the broker and trace id on the current span.
+pub(crate) fn propagate(request: &mut TransportRequest) {
+    let existing = request
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(TRACEPARENT))
+        .map(|(_, value)| value.clone());
+    let traceparent = match existing {
+        Some(traceparent) => traceparent,
+        None => {
+            match active_context() {
+                Some(context) => TraceContextPropagator::new()
+                    .inject_context(&context, &mut Headers(&mut request.headers)),
+                None => {
+                    let traceparent = format!(
+                        "00-{:016x}{:016x}-{:016x}-01",
+                        random_id(),
+                        random_id(),
+                        random_id()
+                    );
+                    Headers(&mut request.headers).set(TRACEPARENT, traceparent);
+                }
+            }
+            request
+                .headers
+                .iter()
+                .find(|(name, _)| name == TRACEPARENT)
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default()
+        }
+    };
+    let span = Span::current();
+    span.record("broker", request.node.as_str());
+    if let Some(trace_id) = traceparent.split('-').nth(1) {
+        span.record("trace_id", trace_id);
+    }
+}
+
+// the context of the current span, when a `tracing_opentelemetry` layer tracks it,
+// or else the current OpenTelemetry context
+fn active_context() -> Option<Context> {
+    let valid = |context: &Context| context.span().span_context().is_valid();
+    Some(Span::current().context())
+        .filter(valid)
+        .or_else(|| Some(Context::current()).filter(valid))
+}
+
+struct Headers<'a>(&'a mut Vec<(String, String)>);
+
+impl Injector for Headers<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+        // the propagator sets an empty `tracestate` when the context has none
+        if !value.is_empty() {
+            self.0.push((key.to_string(), value));
+        }
+    }
+}
+
+// ids only have to be unique, std's randomly keyed hasher avoids a dependency on `rand`
+fn random_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default();
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(nanos);
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    // all zero ids are invalid
+    hasher.finish().max(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::DruidClient;
+    use crate::query::sql::SqlQuery;
+    use crate::transport::stub::StubTransport;
+    use crate::transport::Method;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceFlags, TraceId, TraceState, TracerProvider,
+    };
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_client_propagates_trace_context() {
+        let broker = Arc::new(StubTransport::new().otherwise(200, "[]"));
+        let client = DruidClient::new(vec!["localhost:8082".into()]).with_transport(broker.clone());
+        let query = SqlQuery::new("SELECT 1");
+        tokio_test::block_on(client.sql::<serde_json::Value>(&query)).unwrap();
+        let headers = &broker.requests()[0].headers;
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].0, TRACEPARENT);
+    }
+
+    #[test]
+    fn test_propagate() {
+        let mut request = TransportRequest::new(Method::Post, "broker:8082", "/druid/v2/", None);
+        propagate(&mut request);
+        let (name, traceparent) = &request.headers[0];
+        assert_eq!(name, TRACEPARENT);
+        let parts: Vec<&str> = traceparent.split('-').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(
+            (parts[0], parts[1].len(), parts[2].len(), parts[3]),
+            ("00", 32, 16, "01")
+        );
+
+        let mut other = TransportRequest::new(Method::Post, "broker:8082", "/druid/v2/", None);
+        propagate(&mut other);
+        assert_ne!(other.headers[0].1, *traceparent);
+
+        let parent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let mut continued = TransportRequest::new(Method::Post, "broker:8082", "/druid/v2/", None);
+        continued
+            .headers
+            .push(("Traceparent".to_string(), parent.to_string()));
+        propagate(&mut continued);
+        assert_eq!(continued.headers.len(), 1);
+        assert_eq!(continued.headers[0].1, parent);
+    }
+
+    #[test]
+    fn test_propagate_span_context() {
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("application");
+            let _entered = span.enter();
+            let mut request =
+                TransportRequest::new(Method::Post, "broker:8082", "/druid/v2/", None);
+            propagate(&mut request);
+            let trace_id = span.context().span().span_context().trace_id();
+            let (name, traceparent) = &request.headers[0];
+            assert_eq!(name, TRACEPARENT);
+            assert_eq!(
+                traceparent.split('-').nth(1),
+                Some(&*format!("{:032x}", trace_id))
+            );
+        });
+    }
+
+    #[test]
+    fn test_propagate_current_context() {
+        let parent = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let _attached = Context::new().with_remote_span_context(parent).attach();
+        let mut request = TransportRequest::new(Method::Post, "broker:8082", "/druid/v2/", None);
+        propagate(&mut request);
+        assert_eq!(
+            request.headers,
+            vec![(
+                TRACEPARENT.to_string(),
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Recording of Druid traffic to a fixture file and replaying it without a cluster.
+//!
+//! [`RecordingTransport`] passes requests on to another transport and writes every request
+//! with its response to a json file when it is flushed or dropped. [`ReplayTransport`] answers requests from that file
+//! and fails with `DruidClientError::UnmatchedFixture` on requests that weren't recorded.
+//!
+//! Requests are matched on method, path and normalized json body: object keys are sorted
+//! and the `queryId` and `sqlQueryId` context parameters, which differ between runs, are removed.
+//!
+//! ```rust,no_run
+//! use druid_io::client::DruidClient;
+//! use druid_io::transport::fixtures::{RecordingTransport, ReplayTransport};
+//! use druid_io::transport::ReqwestTransport;
+//!
+//! // once, against a real broker
+//! let recording =
+//!     RecordingTransport::new("tests/fixtures/wikipedia.json", ReqwestTransport::default()).unwrap();
+//! let client = DruidClient::new(vec!["localhost:8082".to_string()]).with_transport(recording);
+//! // ... run the queries, the fixtures are written when the client is dropped
+//! drop(client);
+//!
+//! // in CI
+//! let replay = ReplayTransport::load("tests/fixtures/wikipedia.json").unwrap();
+//! let client = DruidClient::new(vec!["localhost:8082".to_string()]).with_transport(replay);
+//! ```
+use super::{Method, Transport, TransportRequest, TransportResponse};
+use crate::client::{ClientResult, DruidClientError};
+use futures::future::{BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const VOLATILE_CONTEXT: &[&str] = &["queryId", "sqlQueryId"];
+
+/// A recorded request and the response to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Fixture {
+    pub method: Method,
+    pub path: String,
+    /// The normalized body, `null` for requests without one.
+    pub body: Value,
+    pub status: u16,
+    pub response: String,
+}
+
+impl Fixture {
+    fn key(&self) -> (Method, String, String) {
+        (self.method, self.path.clone(), self.body.to_string())
+    }
+}
+
+fn request_key(request: &TransportRequest) -> (Method, String, String) {
+    let body = request
+        .body
+        .as_deref()
+        .map(normalize)
+        .unwrap_or(Value::Null);
+    (request.method, request.path.clone(), body.to_string())
+}
+
+/// Query json with sorted keys and without volatile context parameters,
+/// bodies that aren't json are kept as a string.
+pub fn normalize(body: &str) -> Value {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut query) => {
+            if let Some(Value::Object(context)) = query.get_mut("context") {
+                let kept = std::mem::take(context)
+                    .into_iter()
+                    .filter(|(key, _)| !VOLATILE_CONTEXT.contains(&key.as_str()))
+                    .collect();
+                *context = kept;
+            }
+            sorted(query)
+        }
+        Err(_) => Value::String(body.to_string()),
+    }
+}
+
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object.into_iter().collect();
+            entries.sort_by(|(left, _), (right, _)| left.cmp(right));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted(value)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sorted).collect()),
+        value => value,
+    }
+}
+
+/// Sends requests with the inner transport and records them with their responses.
+///
+/// Recording starts from the fixtures already in the file, a request sent again replaces
+/// its earlier recording. The file is written by [`flush`](RecordingTransport::flush) and
+/// when the transport is dropped with recordings that weren't flushed yet.
+pub struct RecordingTransport<T> {
+    inner: T,
+    path: PathBuf,
+    fixtures: Mutex<Vec<Fixture>>,
+    // recorded since the last flush
+    dirty: AtomicBool,
+}
+
+impl<T> RecordingTransport<T> {
+    pub fn new<P: Into<PathBuf>>(path: P, inner: T) -> ClientResult<Self> {
+        let path = path.into();
+        let fixtures = if path.exists() {
+            read_fixtures(&path)?
+        } else {
+            vec![]
+        };
+        Ok(RecordingTransport {
+            inner,
+            path,
+            fixtures: Mutex::new(fixtures),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Fixtures recorded so far, including the ones loaded from the file.
+    pub fn fixtures(&self) -> Vec<Fixture> {
+        self.fixtures.lock().unwrap().clone()
+    }
+
+    /// Writes the fixtures to the file.
+    pub fn flush(&self) -> ClientResult<()> {
+        let fixtures = self.fixtures.lock().unwrap();
+        let json = serde_json::to_string_pretty(&*fixtures)
+            .map_err(|source| DruidClientError::ParsingError { source })?;
+        std::fs::write(&self.path, json).map_err(|source| DruidClientError::FixtureIo {
+            path: self.path.clone(),
+            source,
+        })?;
+        self.dirty.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn record(&self, fixture: Fixture) {
+        let mut fixtures = self.fixtures.lock().unwrap();
+        fixtures.retain(|recorded| recorded.key() != fixture.key());
+        fixtures.push(fixture);
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Writes the fixtures recorded since the last [`flush`](RecordingTransport::flush).
+/// Panics when they can't be written, unless the thread is panicking already, then the
+/// error is printed to stderr instead.
+impl<T> Drop for RecordingTransport<T> {
+    fn drop(&mut self) {
+        if !self.dirty.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Err(error) = self.flush() {
+            if std::thread::panicking() {
+                eprintln!("couldn't write fixtures to {:?}: {}", self.path, error);
+            } else {
+                panic!("couldn't write fixtures to {:?}: {}", self.path, error);
+            }
+        }
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, ClientResult<TransportResponse>> {
+        async move {
+            let method = request.method;
+            let path = request.path.clone();
+            let body = request
+                .body
+                .as_deref()
+                .map(normalize)
+                .unwrap_or(Value::Null);
+            let response = self.inner.send(request).await?;
+            let status = response.status;
+            let text = response.text().await?;
+            self.record(Fixture {
+                method,
+                path,
+                body,
+                status,
+                response: text.clone(),
+            });
+            Ok(TransportResponse::new(status, text))
+        }
+        .boxed()
+    }
+}
+
+/// Answers requests with recorded responses.
+pub struct ReplayTransport {
+    fixtures: HashMap<(Method, String, String), Fixture>,
+}
+
+impl ReplayTransport {
+    pub fn new(fixtures: Vec<Fixture>) -> Self {
+        ReplayTransport {
+            fixtures: fixtures
+                .into_iter()
+                .map(|fixture| (fixture.key(), fixture))
+                .collect(),
+        }
+    }
+
+    /// Reads fixtures written by a [`RecordingTransport`].
+    pub fn load<P: AsRef<Path>>(path: P) -> ClientResult<Self> {
+        Ok(ReplayTransport::new(read_fixtures(path.as_ref())?))
+    }
+}
+
+fn read_fixtures(path: &Path) -> ClientResult<Vec<Fixture>> {
+    let json = std::fs::read_to_string(path).map_err(|source| DruidClientError::FixtureIo {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_str(&json).map_err(|source| DruidClientError::ParsingResponseError { source })
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, ClientResult<TransportResponse>> {
+        let response = match self.fixtures.get(&request_key(&request)) {
+            Some(fixture) => Ok(TransportResponse::new(
+                fixture.status,
+                fixture.response.clone(),
+            )),
+            None => Err(DruidClientError::UnmatchedFixture {
+                method: request.method,
+                path: request.path,
+                body: request.body.unwrap_or_default(),
+            }),
+        };
+        futures::future::ready(response).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    struct Echo;
+
+    impl Transport for Echo {
+        fn send(
+            &self,
+            request: TransportRequest,
+        ) -> BoxFuture<'_, ClientResult<TransportResponse>> {
+            let body = request.body.unwrap_or_default();
+            futures::future::ready(Ok(TransportResponse::new(200, body))).boxed()
+        }
+    }
+
+    fn request(body: Value) -> TransportRequest {
+        TransportRequest::new(
+            Method::Post,
+            "localhost:8082",
+            "/druid/v2/?pretty",
+            Some(body.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_normalize() {
+        let query = normalize(
+            &json!({
+                "queryType": "scan",
+                "context": {"queryId": "a1", "timeout": 100},
+                "columns": [{"b": 1, "a": 2}]
+            })
+            .to_string(),
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"{"columns":[{"a":2,"b":1}],"context":{"timeout":100},"queryType":"scan"}"#
+        );
+        assert_eq!(normalize("SELECT 1"), json!("SELECT 1"));
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!(
+            "druid-io-fixtures-{}-record.json",
+            std::process::id()
+        ));
+        let recording = RecordingTransport::new(&path, Echo).unwrap();
+        let query = json!({"queryType": "timeBoundary", "context": {"queryId": "1"}});
+        let response = tokio_test::block_on(async {
+            recording.send(request(query.clone())).await?.text().await
+        })
+        .unwrap();
+        assert_eq!(response, query.to_string());
+        assert_eq!(recording.fixtures().len(), 1);
+        assert!(!path.exists());
+        drop(recording);
+
+        // recording again keeps the fixtures of the file
+        let recording = RecordingTransport::new(&path, Echo).unwrap();
+        let scan = json!({"queryType": "scan"});
+        tokio_test::block_on(recording.send(request(scan))).unwrap();
+        recording.flush().unwrap();
+        assert_eq!(ReplayTransport::load(&path).unwrap().fixtures.len(), 2);
+        drop(recording);
+
+        let replay = ReplayTransport::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let same_query = json!({"context": {"queryId": "2"}, "queryType": "timeBoundary"});
+        let response =
+            tokio_test::block_on(async { replay.send(request(same_query)).await?.text().await })
+                .unwrap();
+        assert_eq!(response, query.to_string());
+
+        let other_query = json!({"queryType": "search"});
+        match tokio_test::block_on(replay.send(request(other_query))) {
+            Err(DruidClientError::UnmatchedFixture { method, path, .. }) => {
+                assert_eq!((method, path.as_str()), (Method::Post, "/druid/v2/?pretty"))
+            }
+            _ => panic!("expected an unmatched query"),
+        }
+    }
+
+    #[test]
+    fn test_drop() {
+        let path = std::env::temp_dir().join(format!(
+            "druid-io-fixtures-{}-drop.json",
+            std::process::id()
+        ));
+        let recording = RecordingTransport::new(&path, Echo).unwrap();
+        tokio_test::block_on(recording.send(request(json!({"queryType": "scan"})))).unwrap();
+        recording.flush().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // nothing was recorded since the flush
+        drop(recording);
+        assert!(!path.exists());
+
+        let missing = std::env::temp_dir()
+            .join("druid-io-no-such-dir")
+            .join("fixtures.json");
+        let recording = RecordingTransport::new(&missing, Echo).unwrap();
+        tokio_test::block_on(recording.send(request(json!({"queryType": "scan"})))).unwrap();
+        let dropped = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(recording)));
+        assert!(dropped.is_err());
+    }
+}
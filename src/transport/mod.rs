@@ -0,0 +1,260 @@
+//! How the clients send http requests to Druid services.
+//!
+//! [`DruidClient`](crate::client::DruidClient), [`CoordinatorClient`](crate::coordinator::CoordinatorClient)
+//! and [`OverlordClient`](crate::overlord::OverlordClient) send every request through a
+//! [`Transport`], [`ReqwestTransport`] by default. Replacing it with `with_transport`
+//! allows bringing another http stack, wrapping requests in middleware, answering them
+//! in-process, or recording traffic to fixtures and replaying it, see [`fixtures`].
+//!
+//! ```rust
+//! use druid_io::client::{ClientResult, DruidClient};
+//! use druid_io::transport::{ReqwestTransport, Transport, TransportRequest, TransportResponse};
+//! use futures::future::BoxFuture;
+//!
+//! // adds basic auth credentials to every request
+//! struct BasicAuth<T> {
+//!     inner: T,
+//!     credentials: String,
+//! }
+//!
+//! impl<T: Transport> Transport for BasicAuth<T> {
+//!     fn send(&self, mut request: TransportRequest) -> BoxFuture<'_, ClientResult<TransportResponse>> {
+//!         request.headers.push(("Authorization".into(), format!("Basic {}", self.credentials)));
+//!         self.inner.send(request)
+//!     }
+//! }
+//!
+//! let transport = BasicAuth { inner: ReqwestTransport::default(), credentials: "ZHJ1aWQ6ZHJ1aWQ=".into() };
+//! let client = DruidClient::new(vec!["localhost:8082".to_string()]).with_transport(transport);
+//! ```
+use crate::client::{ClientResult, DruidClientError};
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{self, BoxStream, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[cfg(feature = "testing")]
+pub mod fixtures;
+#[cfg(test)]
+pub(crate) mod stub;
+
+/// The broker endpoint a query is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Endpoint {
+    /// `/druid/v2/`
+    Native,
+    /// `/druid/v2/sql/`
+    Sql,
+}
+
+impl Endpoint {
+    pub fn path(&self) -> &'static str {
+        match self {
+            Endpoint::Native => "/druid/v2/?pretty",
+            Endpoint::Sql => "/druid/v2/sql/",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Method {
+    Get,
+    Post,
+    Delete,
+}
+
+/// An http request to one of the nodes of a Druid service.
+#[derive(Debug, Clone)]
+pub struct TransportRequest {
+    pub method: Method,
+    /// `host:port` of the node.
+    pub node: String,
+    /// Path and query string, e.g. `/druid/v2/?pretty`.
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    /// Json body, sent with `Content-Type: application/json`.
+    pub body: Option<String>,
+}
+
+impl TransportRequest {
+    pub fn new(method: Method, node: &str, path: &str, body: Option<String>) -> Self {
+        TransportRequest {
+            method,
+            node: node.to_string(),
+            path: path.to_string(),
+            headers: vec![],
+            body,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}{}", self.node, self.path)
+    }
+
+    /// The broker endpoint of a query, `None` for requests to other apis.
+    pub fn endpoint(&self) -> Option<Endpoint> {
+        [Endpoint::Native, Endpoint::Sql]
+            .iter()
+            .copied()
+            .find(|endpoint| self.method == Method::Post && self.path == endpoint.path())
+    }
+}
+
+pub type BodyStream = BoxStream<'static, ClientResult<Vec<u8>>>;
+
+/// Status and body of a response, the body is received as it is read.
+pub struct TransportResponse {
+    pub status: u16,
+    pub body: BodyStream,
+}
+
+impl TransportResponse {
+    /// A response with the whole body at hand.
+    pub fn new(status: u16, body: String) -> Self {
+        TransportResponse {
+            status,
+            body: stream::once(async move { Ok(body.into_bytes()) }).boxed(),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Receives the rest of the body.
+    pub async fn text(mut self) -> ClientResult<String> {
+        let mut body = vec![];
+        while let Some(chunk) = self.body.next().await {
+            body.extend_from_slice(&chunk?);
+        }
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+}
+
+/// Sends http requests and returns the responses with their body as a stream.
+///
+/// Transports that don't use `reqwest` report their failures with
+/// `DruidClientError::Transport`.
+pub trait Transport: Send + Sync {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, ClientResult<TransportResponse>>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, ClientResult<TransportResponse>> {
+        (**self).send(request)
+    }
+}
+
+/// Shares one transport, e.g. its connection pool, between several clients.
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, ClientResult<TransportResponse>> {
+        (**self).send(request)
+    }
+}
+
+/// The default transport, sending requests with a `reqwest` client.
+#[derive(Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    /// Uses a configured client, e.g. with timeouts or tls settings.
+    pub fn new(client: Client) -> Self {
+        ReqwestTransport { client }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, ClientResult<TransportResponse>> {
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+            Method::Delete => reqwest::Method::DELETE,
+        };
+        let mut builder = self.client.request(method, &request.url());
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = request.body {
+            builder = builder
+                .body(body)
+                .header(reqwest::header::CONTENT_TYPE, "application/json");
+        }
+        async move {
+            let response = builder
+                .send()
+                .await
+                .map_err(|source| DruidClientError::HttpConnection { source })?;
+            Ok(TransportResponse {
+                status: response.status().as_u16(),
+                body: response
+                    .bytes_stream()
+                    .map(|chunk| {
+                        chunk
+                            .map(|bytes| bytes.to_vec())
+                            .map_err(|source| DruidClientError::HttpConnection { source })
+                    })
+                    .boxed(),
+            })
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::DruidClient;
+    use crate::coordinator::CoordinatorClient;
+    use crate::query::sql::SqlQuery;
+    use crate::transport::stub::StubTransport;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug)]
+    struct Page {
+        page: String,
+    }
+
+    #[test]
+    fn test_custom_transport() {
+        let transport = Arc::new(
+            StubTransport::new()
+                .on_path("/druid/coordinator/v1/datasources", 200, r#"["wikipedia"]"#)
+                .on_path(
+                    "/druid/v2/sql/",
+                    200,
+                    "{\"page\":\"Rust\"}\n{\"page\":\"Druid\"}\n\n",
+                ),
+        );
+        let coordinator = CoordinatorClient::new(vec!["coordinator:8081".into()])
+            .with_transport(transport.clone());
+        let broker = DruidClient::new(vec!["broker:8082".into()]).with_transport(transport.clone());
+
+        let datasources = tokio_test::block_on(coordinator.datasources()).unwrap();
+        assert_eq!(datasources, vec!["wikipedia".to_string()]);
+        let query = SqlQuery::new("SELECT page FROM wikipedia")
+            .result_format(crate::query::sql::SqlResultFormat::ObjectLines);
+        let pages = tokio_test::block_on(broker.sql::<Page>(&query)).unwrap();
+        assert_eq!(pages.rows[1].page, "Druid");
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].method, Method::Get);
+        assert_eq!(
+            requests[0].url(),
+            "http://coordinator:8081/druid/coordinator/v1/datasources"
+        );
+        assert_eq!(requests[0].endpoint(), None);
+        assert_eq!(requests[1].endpoint(), Some(Endpoint::Sql));
+        assert_eq!(requests[1].node, "broker:8082");
+
+        let unexpected = tokio_test::block_on(coordinator.rules("wikipedia"));
+        assert!(matches!(
+            unexpected,
+            Err(DruidClientError::Transport { .. })
+        ));
+    }
+}
@@ -0,0 +1,56 @@
+//! Transport for unit tests, answering in-process and remembering the requests.
+use super::{Transport, TransportRequest, TransportResponse};
+use crate::client::{ClientResult, DruidClientError};
+use futures::future::{BoxFuture, FutureExt};
+use std::sync::Mutex;
+
+type Matcher = Box<dyn Fn(&TransportRequest) -> bool + Send + Sync>;
+
+/// Answers with the response of the first route matching the request,
+/// requests without a route fail with `DruidClientError::Transport`.
+#[derive(Default)]
+pub(crate) struct StubTransport {
+    routes: Vec<(Matcher, u16, String)>,
+    requests: Mutex<Vec<TransportRequest>>,
+}
+
+impl StubTransport {
+    pub(crate) fn new() -> Self {
+        StubTransport::default()
+    }
+
+    pub(crate) fn on<F>(mut self, matches: F, status: u16, body: &str) -> Self
+    where
+        F: Fn(&TransportRequest) -> bool + Send + Sync + 'static,
+    {
+        self.routes
+            .push((Box::new(matches), status, body.to_string()));
+        self
+    }
+
+    pub(crate) fn on_path(self, path: &'static str, status: u16, body: &str) -> Self {
+        self.on(move |request| request.path == path, status, body)
+    }
+
+    /// Answers every request that no route before matched.
+    pub(crate) fn otherwise(self, status: u16, body: &str) -> Self {
+        self.on(|_| true, status, body)
+    }
+
+    pub(crate) fn requests(&self) -> Vec<TransportRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for StubTransport {
+    fn send(&self, request: TransportRequest) -> BoxFuture<'_, ClientResult<TransportResponse>> {
+        let response = match self.routes.iter().find(|(matches, _, _)| matches(&request)) {
+            Some((_, status, body)) => Ok(TransportResponse::new(*status, body.clone())),
+            None => Err(DruidClientError::Transport {
+                source: format!("unexpected request to {}", request.path).into(),
+            }),
+        };
+        self.requests.lock().unwrap().push(request);
+        futures::future::ready(response).boxed()
+    }
+}
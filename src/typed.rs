@@ -0,0 +1,364 @@
+//! Query parts derived from the struct the results are deserialized into, so that
+//! aggregations and dimensions of a query can't drift apart from the result fields.
+//!
+//! ```rust
+//! use druid_io::query::timeseries::Timeseries;
+//! use druid_io::query::definitions::Granularity;
+//! use druid_io::query::DataSource;
+//! use druid_io::DruidResult;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, DruidResult, Debug)]
+//! pub struct TimeAggr {
+//!     #[druid(count)]
+//!     count: usize,
+//!     #[druid(long_sum = "added")]
+//!     added: i64,
+//!     #[druid(string_first = "user")]
+//!     user: String,
+//! }
+//!
+//! let timeseries = Timeseries {
+//!     data_source: DataSource::table("wikipedia"),
+//!     limit: Some(10),
+//!     descending: false,
+//!     granularity: Granularity::All,
+//!     filter: None,
+//!     aggregations: TimeAggr::aggregations(),
+//!     post_aggregations: vec![],
+//!     intervals: vec!["-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z".into()],
+//!     context: Default::default(),
+//! };
+//! ```
+//!
+//! Queries can also carry the type of their result rows, [`DruidClient::execute`] then
+//! infers it and [`DruidClient::execute_checked`] verifies before sending that every output
+//! of the query has a field to be deserialized into:
+//!
+//! ```no_run
+//! use druid_io::client::DruidClient;
+//! use druid_io::query::definitions::{Aggregation, Dimension, OutputType};
+//! use druid_io::query::group_by::GroupByBuilder;
+//! use druid_io::query::DataSource;
+//! use druid_io::typed::TypedGroupBy;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug)]
+//! struct Edits {
+//!     page: String,
+//!     count: usize,
+//! }
+//!
+//! # async fn example() {
+//! let client = DruidClient::new(vec!["localhost:8082".to_string()]);
+//! let query = GroupByBuilder::new(DataSource::table("wikipedia"))
+//!     .dimensions(vec![Dimension::Default {
+//!         dimension: "page".into(),
+//!         output_name: "page".into(),
+//!         output_type: OutputType::STRING,
+//!     }])
+//!     .aggregations(vec![Aggregation::count("count")])
+//!     .intervals(vec!["-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z"])
+//!     .build();
+//! let rows = client
+//!     .execute_checked(&TypedGroupBy::<Edits>::new(query))
+//!     .await
+//!     .unwrap();
+//! for row in rows {
+//!     println!("{}: {}", row.event.page, row.event.count);
+//! }
+//! # }
+//! ```
+use crate::client::{ClientResult, DruidClient, DruidClientError};
+use crate::query::definitions::{Aggregation, Dimension};
+use crate::query::group_by::GroupBy;
+use crate::query::response::{GroupByResponse, ScanResponse, TimeseriesResponse, TopNResponse};
+use crate::query::scan::Scan;
+use crate::query::timeseries::Timeseries;
+use crate::query::top_n::TopN;
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::marker::PhantomData;
+
+/// Usually derived with `#[derive(DruidResult)]`, see the field attributes in
+/// [`druid_io_derive::DruidResult`](derive@crate::DruidResult).
+pub trait DruidResult {
+    /// Aggregations producing the metric fields.
+    fn aggregations() -> Vec<Aggregation>;
+    /// Dimensions producing the dimension fields, for GroupBy.
+    fn dimensions() -> Vec<Dimension>;
+    /// Output names of all the fields but skipped ones, for Scan.
+    fn columns() -> Vec<String>;
+}
+
+/// Query whose result rows deserialize into `R`.
+pub struct Typed<Q, R> {
+    query: Q,
+    row: PhantomData<fn() -> R>,
+}
+
+pub type TypedGroupBy<R> = Typed<GroupBy, R>;
+pub type TypedScan<R> = Typed<Scan, R>;
+pub type TypedTimeseries<R> = Typed<Timeseries, R>;
+pub type TypedTopN<R> = Typed<TopN, R>;
+
+impl<Q, R> Typed<Q, R> {
+    pub fn new(query: Q) -> Self {
+        Typed {
+            query,
+            row: PhantomData,
+        }
+    }
+
+    pub fn query(&self) -> &Q {
+        &self.query
+    }
+
+    pub fn into_query(self) -> Q {
+        self.query
+    }
+}
+
+/// Query paired with the shape of its response, implemented by the [`Typed`] queries.
+pub trait TypedQuery {
+    type Query: Serialize;
+    /// Type of a single result row
+    type Row: DeserializeOwned;
+    /// Whole response, result rows wrapped the way the query type returns them
+    type Response: DeserializeOwned;
+
+    fn query(&self) -> &Self::Query;
+
+    /// Output names every row carries, see [`Query::output_names`](crate::query::Query::output_names).
+    fn output_names(&self) -> Vec<String>;
+
+    /// Fails when some output of the query has no field in the row type. Row types deserialized
+    /// without a fixed list of fields, e.g. maps or structs with flattened fields, always pass.
+    fn check_row_type(&self) -> ClientResult<()> {
+        let fields = match field_names::<Self::Row>() {
+            Some(fields) => fields,
+            None => return Ok(()),
+        };
+        let missing: Vec<String> = self
+            .output_names()
+            .into_iter()
+            .filter(|name| !fields.contains(&name.as_str()))
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(DruidClientError::RowTypeMismatch {
+                row_type: std::any::type_name::<Self::Row>(),
+                missing,
+            })
+        }
+    }
+}
+
+impl<R: DeserializeOwned> TypedQuery for TypedGroupBy<R> {
+    type Query = GroupBy;
+    type Row = R;
+    type Response = Vec<GroupByResponse<R>>;
+
+    fn query(&self) -> &GroupBy {
+        &self.query
+    }
+
+    fn output_names(&self) -> Vec<String> {
+        self.query.output_names()
+    }
+}
+
+impl<R: DeserializeOwned> TypedQuery for TypedScan<R> {
+    type Query = Scan;
+    type Row = R;
+    type Response = Vec<ScanResponse<R>>;
+
+    fn query(&self) -> &Scan {
+        &self.query
+    }
+
+    fn output_names(&self) -> Vec<String> {
+        self.query.output_names()
+    }
+}
+
+impl<R: DeserializeOwned> TypedQuery for TypedTimeseries<R> {
+    type Query = Timeseries;
+    type Row = R;
+    type Response = Vec<TimeseriesResponse<R>>;
+
+    fn query(&self) -> &Timeseries {
+        &self.query
+    }
+
+    fn output_names(&self) -> Vec<String> {
+        self.query.output_names()
+    }
+}
+
+impl<R: DeserializeOwned> TypedQuery for TypedTopN<R> {
+    type Query = TopN;
+    type Row = R;
+    type Response = Vec<TopNResponse<R>>;
+
+    fn query(&self) -> &TopN {
+        &self.query
+    }
+
+    fn output_names(&self) -> Vec<String> {
+        self.query.output_names()
+    }
+}
+
+impl DruidClient {
+    /// Runs the query, deserializing its rows into the query's row type.
+    pub async fn execute<Q: TypedQuery>(&self, query: &Q) -> ClientResult<Q::Response> {
+        self._query(query.query()).await
+    }
+
+    /// Like [`execute`](Self::execute), but checks the row type first, see
+    /// [`TypedQuery::check_row_type`].
+    pub async fn execute_checked<Q: TypedQuery>(&self, query: &Q) -> ClientResult<Q::Response> {
+        query.check_row_type()?;
+        self.execute(query).await
+    }
+}
+
+/// Serialized field names of a struct, as serde's derive passes them to the deserializer.
+fn field_names<'de, T: Deserialize<'de>>() -> Option<&'static [&'static str]> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldNames {
+        fields: &mut fields,
+    });
+    fields
+}
+
+// deserializer that records the fields requested by `deserialize_struct` and fails otherwise
+struct FieldNames<'a> {
+    fields: &'a mut Option<&'static [&'static str]>,
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldNames<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.fields = Some(fields);
+        Err(de::Error::custom("fields recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::definitions::OutputType;
+    use crate::query::group_by::GroupByBuilder;
+    use crate::query::DataSource;
+    use crate::DruidResult;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize, DruidResult)]
+    #[serde(rename_all = "camelCase")]
+    #[allow(dead_code)]
+    struct PageEdits {
+        #[druid(timestamp)]
+        #[serde(rename = "__time")]
+        time: i64,
+        #[druid(dimension = "page")]
+        page_name: String,
+        #[druid(dimension)]
+        delta_bucket: Option<i64>,
+        #[druid(count)]
+        edits: usize,
+        #[druid(string_last = "user", max_string_bytes = 256)]
+        last_user: String,
+        #[druid(skip)]
+        ratio: f64,
+    }
+
+    #[test]
+    fn test_derive() {
+        let aggregations = serde_json::to_value(PageEdits::aggregations()).unwrap();
+        assert_eq!(
+            aggregations,
+            serde_json::json!([
+                {"type": "count", "name": "edits"},
+                {"type": "stringLast", "name": "lastUser", "fieldName": "user", "maxStringBytes": 256}
+            ])
+        );
+        let dimensions = serde_json::to_value(PageEdits::dimensions()).unwrap();
+        assert_eq!(
+            dimensions,
+            serde_json::json!([
+                {"type": "default", "dimension": "page", "outputName": "pageName", "outputType": "STRING"},
+                {"type": "default", "dimension": "deltaBucket", "outputName": "deltaBucket", "outputType": "LONG"}
+            ])
+        );
+        assert_eq!(
+            PageEdits::columns(),
+            vec!["__time", "pageName", "deltaBucket", "edits", "lastUser"]
+        );
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(rename_all = "camelCase")]
+    #[allow(dead_code)]
+    struct Edits {
+        page_name: String,
+        edits: usize,
+    }
+
+    fn edits_by_page(aggregations: Vec<Aggregation>) -> GroupBy {
+        GroupByBuilder::new(DataSource::table("wikipedia"))
+            .dimensions(vec![Dimension::Default {
+                dimension: "page".into(),
+                output_name: "pageName".into(),
+                output_type: OutputType::STRING,
+            }])
+            .aggregations(aggregations)
+            .build()
+    }
+
+    #[test]
+    fn test_field_names() {
+        assert_eq!(field_names::<Edits>(), Some(&["pageName", "edits"][..]));
+        assert_eq!(field_names::<HashMap<String, i64>>(), None);
+    }
+
+    #[test]
+    fn test_check_row_type() {
+        let query = TypedGroupBy::<Edits>::new(edits_by_page(vec![Aggregation::count("edits")]));
+        assert!(query.check_row_type().is_ok());
+
+        let query = TypedGroupBy::<Edits>::new(edits_by_page(vec![
+            Aggregation::count("edits"),
+            Aggregation::long_sum("added", "added"),
+        ]));
+        match query.check_row_type() {
+            Err(DruidClientError::RowTypeMismatch { missing, .. }) => {
+                assert_eq!(missing, vec!["added"])
+            }
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+
+        let query = TypedGroupBy::<HashMap<String, serde_json::Value>>::new(edits_by_page(vec![
+            Aggregation::long_sum("added", "added"),
+        ]));
+        assert!(query.check_row_type().is_ok());
+    }
+}
@@ -3,6 +3,11 @@ extern crate tokio;
 
 use druid_io::{
     client::DruidClient,
+    coordinator::CoordinatorClient,
+    deletion::SegmentSelection,
+    ingestion::IngestionTask,
+    lookup::{LookupSpec, DEFAULT_LOOKUP_TIER},
+    overlord::{OverlordClient, WaitOptions},
     query::search::Search,
     query::timeseries::Timeseries,
     query::top_n::TopN,
@@ -16,10 +21,17 @@ use druid_io::{
         scan::{ResultFormat, Scan},
         search::SearchQuerySpec,
         segment_metadata::{AnalysisType, SegmentMetadata, ToInclude},
+        sql::{SqlQuery, SqlResultFormat},
         time_boundary::{TimeBoundType, TimeBoundary},
         DataSource, JoinType,
     },
+    retention::{CompactionConfig, DatasourceLifecycle, Rule},
+    schema::DRUID_SCHEMA,
+    system::SysFilter,
+    typed::TypedTopN,
+    DruidResult,
 };
+use futures::StreamExt;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::HashMap;
@@ -47,7 +59,9 @@ fn test_top_n_query() {
                 max_string_bytes: 1024,
             },
         ],
+        post_aggregations: vec![],
         intervals: vec!["-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z".into()],
+        filter: None,
         granularity: Granularity::All,
         context: context,
     };
@@ -314,3 +328,229 @@ fn test_segment_metadata() {
     let result = tokio_test::block_on(druid_client.segment_metadata(&segment_query));
     println!("{:?}", result.unwrap());
 }
+
+#[derive(Serialize, Deserialize, DruidResult, Debug)]
+pub struct DerivedAggr {
+    #[druid(count)]
+    count: usize,
+    #[druid(long_sum = "added")]
+    added: i64,
+    #[druid(string_first = "user")]
+    user: String,
+}
+
+#[test]
+fn test_derived_aggregations() {
+    let timeseries = Timeseries {
+        data_source: DataSource::table("wikipedia"),
+        limit: Some(10),
+        descending: false,
+        granularity: Granularity::All,
+        filter: Some(Filter::selector("user", "Taffe316")),
+        aggregations: DerivedAggr::aggregations(),
+        post_aggregations: vec![],
+        intervals: vec!["-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z".into()],
+        context: Default::default(),
+    };
+    let druid_client = DruidClient::new(vec!["localhost:8082".to_string()]);
+    let result = tokio_test::block_on(druid_client.timeseries::<DerivedAggr>(&timeseries));
+    println!("{:?}", result.unwrap());
+}
+
+#[test]
+fn test_typed_query() {
+    let top_n = TopN {
+        data_source: DataSource::table("wikipedia"),
+        dimension: Dimension::default("page"),
+        threshold: 10,
+        metric: "count".into(),
+        aggregations: vec![
+            Aggregation::count("count"),
+            Aggregation::StringFirst {
+                name: "user".into(),
+                field_name: "user".into(),
+                max_string_bytes: 1024,
+            },
+        ],
+        post_aggregations: vec![],
+        intervals: vec!["-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z".into()],
+        filter: None,
+        granularity: Granularity::All,
+        context: Default::default(),
+    };
+    let druid_client = DruidClient::new(vec!["localhost:8082".to_string()]);
+    let query = TypedTopN::<WikiPage>::new(top_n);
+    let result = tokio_test::block_on(druid_client.execute_checked(&query));
+    println!("{:?}", result.unwrap());
+}
+
+#[test]
+fn test_sql() {
+    let query =
+        SqlQuery::new("SELECT page, COUNT(*) AS \"count\" FROM wikipedia GROUP BY page LIMIT 10")
+            .types_header(true)
+            .sql_types_header(true);
+    let druid_client = DruidClient::new(vec!["localhost:8082".to_string()]);
+    let result = tokio_test::block_on(druid_client.sql::<WikiPage>(&query));
+    println!("{:?}", result.unwrap());
+}
+
+#[test]
+fn test_sql_stream() {
+    let query =
+        SqlQuery::new("SELECT page, COUNT(*) AS \"count\" FROM wikipedia GROUP BY page LIMIT 10")
+            .result_format(SqlResultFormat::ArrayLines)
+            .types_header(true);
+    let druid_client = DruidClient::new(vec!["localhost:8082".to_string()]);
+    let result = tokio_test::block_on(async {
+        let stream = druid_client
+            .sql_stream::<(String, usize)>(&query)
+            .await
+            .unwrap();
+        println!("{:?}", stream.columns);
+        stream.collect::<Vec<_>>().await
+    });
+    println!("{:?}", result);
+}
+
+#[test]
+fn test_explain() {
+    let query =
+        SqlQuery::new("SELECT page, COUNT(*) FROM wikipedia WHERE \"user\" = ? GROUP BY page")
+            .bind("Taffe316");
+    let druid_client = DruidClient::new(vec!["localhost:8082".to_string()]);
+    let result = tokio_test::block_on(druid_client.explain(&query));
+    println!("{:?}", result.unwrap());
+}
+
+#[test]
+fn test_sys_tables() {
+    let druid_client = DruidClient::new(vec!["localhost:8082".to_string()]);
+    let filter = SysFilter::new().eq("datasource", "wikipedia").limit(10);
+    let segments = tokio_test::block_on(druid_client.sys_segments(filter));
+    println!("{:?}", segments.unwrap());
+    let servers = tokio_test::block_on(druid_client.sys_servers(SysFilter::new()));
+    println!("{:?}", servers.unwrap());
+    let tasks =
+        tokio_test::block_on(druid_client.sys_tasks(SysFilter::new().eq("status", "FAILED")));
+    println!("{:?}", tasks.unwrap());
+}
+
+#[test]
+fn test_schema() {
+    let druid_client = DruidClient::new(vec!["localhost:8082".to_string()]);
+    let tables = tokio_test::block_on(druid_client.tables(Some(DRUID_SCHEMA)));
+    println!("{:?}", tables.unwrap());
+    let schema = tokio_test::block_on(druid_client.table_schema("wikipedia")).unwrap();
+    println!("{}", schema.to_rust_struct());
+}
+
+#[test]
+fn test_coordinator() {
+    let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+    let datasources = tokio_test::block_on(coordinator.datasources_summary());
+    println!("{:?}", datasources.unwrap());
+    let segments = tokio_test::block_on(coordinator.segments("wikipedia"));
+    println!("{:?}", segments.unwrap());
+    let load_status = tokio_test::block_on(coordinator.load_status());
+    println!("{:?}", load_status.unwrap());
+    let servers = tokio_test::block_on(coordinator.servers());
+    println!("{:?}", servers.unwrap());
+}
+
+#[test]
+fn test_lifecycle() {
+    let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+    let lifecycle = DatasourceLifecycle::new("wikipedia")
+        .rules(vec![Rule::load_forever(1)])
+        .compaction(CompactionConfig::new("wikipedia").skip_offset_from_latest("P1D"));
+    let applied = tokio_test::block_on(coordinator.apply_lifecycle(&lifecycle));
+    println!("{:?}", applied.unwrap());
+    let changes = tokio_test::block_on(coordinator.diff_lifecycle(&lifecycle)).unwrap();
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn test_mark_unused() {
+    let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+    let selection = SegmentSelection::Interval("2015-09-12T00:00:00Z/2015-09-12T01:00:00Z".into());
+    let plan =
+        tokio_test::block_on(coordinator.plan_mark_unused("wikipedia", selection.clone())).unwrap();
+    println!("{:?}", plan.segments);
+    let result = tokio_test::block_on(coordinator.mark_unused(&plan, "wikipedia"));
+    assert!(result.is_err());
+    let token = plan.confirmation_token();
+    let marked = tokio_test::block_on(coordinator.mark_unused(&plan, &token));
+    println!("{:?}", marked.unwrap());
+    let restored = tokio_test::block_on(coordinator.mark_used("wikipedia", selection));
+    println!("{:?}", restored.unwrap());
+}
+
+#[test]
+fn test_lookups() {
+    let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+    let mut map = HashMap::new();
+    map.insert("EE".to_string(), "Estonia".to_string());
+    let spec = LookupSpec::map("v1", map);
+    let tiers = tokio_test::block_on(coordinator.lookup_tiers()).unwrap();
+    if tiers.is_empty() {
+        tokio_test::block_on(coordinator.init_lookups()).unwrap();
+    }
+    tokio_test::block_on(coordinator.update_lookup(DEFAULT_LOOKUP_TIER, "country_names", &spec))
+        .unwrap();
+    let status =
+        tokio_test::block_on(coordinator.lookup_status(DEFAULT_LOOKUP_TIER, "country_names"));
+    println!("{:?}", status.unwrap());
+    tokio_test::block_on(coordinator.delete_lookup(DEFAULT_LOOKUP_TIER, "country_names")).unwrap();
+}
+
+#[test]
+fn test_overlord() {
+    let overlord = OverlordClient::new(vec!["localhost:8090".to_string()]);
+    let file = std::fs::read_to_string("docker/druid/config/countries-index.json").unwrap();
+    let task = serde_json::from_str::<IngestionTask>(&file).unwrap();
+    let task_id = tokio_test::block_on(overlord.submit_task(&task)).unwrap();
+    let status = tokio_test::block_on(overlord.task_status(&task_id));
+    println!("{:?}", status.unwrap());
+    let tasks = tokio_test::block_on(overlord.tasks(None, Some("countries")));
+    println!("{:?}", tasks.unwrap());
+    let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+    let completion = tokio_test::block_on(overlord.wait_for_task(
+        &task_id,
+        &coordinator,
+        WaitOptions::default(),
+    ));
+    println!("{:?}", completion.unwrap());
+}
+
+#[derive(Serialize)]
+struct Currency {
+    code: String,
+    name: String,
+    minor_unit: i32,
+}
+
+#[test]
+fn test_ingest_rows() {
+    let overlord = OverlordClient::new(vec!["localhost:8090".to_string()]);
+    let coordinator = CoordinatorClient::new(vec!["localhost:8081".to_string()]);
+    let rows = vec![
+        Currency {
+            code: "EUR".into(),
+            name: "Euro".into(),
+            minor_unit: 2,
+        },
+        Currency {
+            code: "JPY".into(),
+            name: "Yen".into(),
+            minor_unit: 0,
+        },
+    ];
+    let completion = tokio_test::block_on(overlord.ingest_rows(
+        "currencies",
+        &rows,
+        &coordinator,
+        WaitOptions::default(),
+    ));
+    println!("{:?}", completion.unwrap());
+}
@@ -0,0 +1,45 @@
+extern crate druid_io;
+
+use druid_io::ingestion::{
+    DimensionSchema, IngestionTask, InputFormat, InputSource, PartitionsSpec, TaskType,
+};
+use druid_io::query::definitions::{Aggregation, Granularity};
+
+#[test]
+fn test_countries_index_round_trip() {
+    let file = std::fs::read_to_string("docker/druid/config/countries-index.json").unwrap();
+    let task = serde_json::from_str::<IngestionTask>(&file).unwrap();
+
+    assert_eq!(task.task_type, TaskType::IndexParallel);
+    let schema = &task.spec.data_schema;
+    assert_eq!(schema.data_source, "countries");
+    assert!(matches!(
+        schema.granularity_spec.segment_granularity,
+        Granularity::Hour
+    ));
+    assert!(
+        matches!(&schema.dimensions_spec.dimensions[0], DimensionSchema::Name(name) if name == "Capital")
+    );
+    assert!(matches!(
+        &schema.metrics_spec[1],
+        Aggregation::LongSum { field_name, .. } if field_name == "PhoneCode"
+    ));
+    assert!(matches!(
+        &task.spec.io_config.input_source,
+        InputSource::Local { base_dir, .. } if base_dir == "quickstart/tutorial/"
+    ));
+    assert!(matches!(
+        task.spec.io_config.input_format,
+        Some(InputFormat::Csv {
+            find_columns_from_header: Some(true),
+            ..
+        })
+    ));
+    assert!(matches!(
+        task.spec.tuning_config.as_ref().unwrap().partitions_spec,
+        Some(PartitionsSpec::Dynamic { .. })
+    ));
+
+    let expected = serde_json::from_str::<serde_json::Value>(&file).unwrap();
+    assert_eq!(serde_json::to_value(&task).unwrap(), expected);
+}
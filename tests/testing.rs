@@ -0,0 +1,388 @@
+#![cfg(feature = "testing")]
+extern crate druid_io;
+
+use druid_io::client::{DruidClient, DruidClientError};
+use druid_io::ingestion::TimestampSpec;
+use druid_io::query::definitions::{Aggregation, Dimension, Filter, Granularity, Ordering};
+use druid_io::query::group_by::{GroupByBuilder, HavingSpec, LimitSpec, OrderByColumnSpec};
+use druid_io::query::scan::{ResultFormat, Scan};
+use druid_io::query::search::{Search, SearchQuerySpec};
+use druid_io::query::sql::SqlQuery;
+use druid_io::query::top_n::{TopN, TopNMetricSpec};
+use druid_io::query::{DataSource, Query};
+use druid_io::testing::mock::{Endpoint, MockDruid, MockResponse, QueryMatcher};
+use druid_io::testing::InMemoryDruid;
+use druid_io::transport::fixtures::{RecordingTransport, ReplayTransport};
+use druid_io::transport::ReqwestTransport;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::time::{Duration, Instant};
+
+const ETERNITY: &str = "-146136543-09-08T08:23:32.096Z/146140482-04-24T15:36:27.903Z";
+
+// loaded like docker/druid/config/countries-index.json does
+fn countries() -> InMemoryDruid {
+    let timestamp = TimestampSpec {
+        column: "!!!_no_such_column_!!!".into(),
+        format: None,
+        missing_value: Some("2010-01-01T00:00:00Z".into()),
+    };
+    let file = std::fs::File::open("docker/druid/config/countries.csv").unwrap();
+    let mut druid = InMemoryDruid::new();
+    druid.load_csv("countries", &timestamp, file).unwrap();
+    druid
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Continent {
+    code: String,
+    count: usize,
+}
+
+#[test]
+fn test_top_n() {
+    let top_n = TopN {
+        data_source: DataSource::table("countries"),
+        dimension: Dimension::default("Code"),
+        threshold: 3,
+        metric: "Count".into(),
+        aggregations: vec![Aggregation::count("Count")],
+        post_aggregations: vec![],
+        intervals: vec![ETERNITY.into()],
+        filter: None,
+        granularity: Granularity::All,
+        context: Default::default(),
+    };
+    let result = countries().top_n::<Continent>(&top_n).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].timestamp, "2010-01-01T00:00:00.000Z");
+    let continents: Vec<_> = result[0]
+        .result
+        .iter()
+        .map(|continent| (continent.code.as_str(), continent.count))
+        .collect();
+    assert_eq!(continents, vec![("AF", 58), ("EU", 53), ("AS", 52)]);
+
+    let top_n = TopN {
+        metric: TopNMetricSpec::inverted("Count".into()),
+        ..top_n
+    };
+    let result = countries().top_n::<Continent>(&top_n).unwrap();
+    let continents: Vec<_> = result[0]
+        .result
+        .iter()
+        .map(|continent| (continent.code.as_str(), continent.count))
+        .collect();
+    assert_eq!(continents, vec![("AN", 5), ("SA", 14), ("OC", 27)]);
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Currency {
+    currency: String,
+    count: usize,
+}
+
+#[test]
+fn test_group_by() {
+    let group_by = GroupByBuilder::new(DataSource::table("countries"))
+        .dimensions(vec![Dimension::default("Currency")])
+        .aggregations(vec![Aggregation::count("Count")])
+        .filter(Filter::in_values("Code", vec!["EU", "NA"]))
+        .having(HavingSpec::greater_than("Count", 3.into()))
+        .limit(LimitSpec {
+            limit: 2,
+            columns: vec![OrderByColumnSpec::new(
+                "Count",
+                Ordering::Descending,
+                Default::default(),
+            )],
+        })
+        .granularity(Granularity::All)
+        .intervals(vec![ETERNITY])
+        .build();
+    let result = countries().group_by::<Currency>(&group_by).unwrap();
+    let currencies: Vec<_> = result
+        .iter()
+        .map(|row| (row.event.currency.as_str(), row.event.count))
+        .collect();
+    assert_eq!(currencies, vec![("EUR", 31), ("XCD", 8)]);
+}
+
+#[test]
+fn test_search() {
+    let search = Search {
+        data_source: DataSource::table("countries"),
+        granularity: Granularity::All,
+        filter: None,
+        limit: 10,
+        intervals: vec![ETERNITY.into()],
+        search_dimensions: vec!["Name".into(), "Capital".into()],
+        query: SearchQuerySpec::contains_insensitive("estonia"),
+        sort: None,
+        context: Default::default(),
+    };
+    let result = countries().search(&search).unwrap();
+    assert_eq!(result[0].result.len(), 1);
+    assert_eq!(result[0].result[0].dimension, "Name");
+    assert_eq!(result[0].result[0].count, 1);
+}
+
+#[test]
+fn test_scan() {
+    let scan = Scan {
+        data_source: DataSource::table("countries"),
+        intervals: vec![ETERNITY.into()],
+        result_format: ResultFormat::List,
+        filter: Some(Filter::selector("Code", "AN")),
+        columns: vec!["Name".into(), "Capital".into()],
+        batch_size: 20480,
+        limit: Some(2),
+        ordering: None,
+        context: Default::default(),
+    };
+    let result = countries().run(&Query::Scan(scan)).unwrap();
+    assert_eq!(
+        result[0]["events"],
+        serde_json::json!([
+            {"Name": "Antarctica", "Capital": null},
+            {"Name": "Bouvet Island", "Capital": null}
+        ])
+    );
+}
+
+fn top_continents() -> TopN {
+    TopN {
+        data_source: DataSource::table("countries"),
+        dimension: Dimension::default("Code"),
+        threshold: 1,
+        metric: "Count".into(),
+        aggregations: vec![Aggregation::count("Count")],
+        post_aggregations: vec![],
+        intervals: vec![ETERNITY.into()],
+        filter: None,
+        granularity: Granularity::All,
+        context: Default::default(),
+    }
+}
+
+#[test]
+fn test_mock_responses() {
+    let druid = MockDruid::start().unwrap();
+    druid.respond_times(
+        QueryMatcher::native().query_type("topN"),
+        MockResponse::query_capacity_exceeded(),
+        1,
+    );
+    druid.respond(
+        QueryMatcher::native()
+            .query_type("topN")
+            .data_source("countries"),
+        MockResponse::json(json!([{
+            "timestamp": "2010-01-01T00:00:00.000Z",
+            "result": [{"Code": "AF", "Count": 58}]
+        }])),
+    );
+    let client = druid.client();
+
+    let result = tokio_test::block_on(client.top_n::<Continent>(&top_continents()));
+    match result {
+        Err(DruidClientError::ServerError { response }) => {
+            assert!(response.contains("Query capacity exceeded"))
+        }
+        result => panic!("expected a server error, got {:?}", result),
+    }
+    let result = tokio_test::block_on(client.top_n::<Continent>(&top_continents())).unwrap();
+    assert_eq!(result[0].result[0].code, "AF");
+
+    let received = druid.received();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0].endpoint, Endpoint::Native);
+    assert_eq!(received[1].json().unwrap()["threshold"], 1);
+}
+
+#[test]
+fn test_mock_failures() {
+    let druid = MockDruid::start().unwrap();
+    druid.respond(
+        QueryMatcher::sql().sql_contains("countries"),
+        MockResponse::json(json!([{"Code": "AF"}])).delay(Duration::from_millis(200)),
+    );
+    druid.respond(QueryMatcher::sql(), MockResponse::drop_connection());
+    let client = druid.client();
+
+    #[derive(Deserialize, Debug)]
+    struct Code {
+        #[serde(rename = "Code")]
+        code: String,
+    }
+    let started = Instant::now();
+    let query = SqlQuery::new("SELECT Code FROM countries LIMIT 1");
+    let result = tokio_test::block_on(client.sql::<Code>(&query)).unwrap();
+    assert_eq!(result.rows[0].code, "AF");
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    let query = SqlQuery::new("SELECT 1");
+    match tokio_test::block_on(client.sql::<Code>(&query)) {
+        Err(DruidClientError::HttpConnection { .. }) => {}
+        result => panic!("expected a connection error, got {:?}", result),
+    }
+    assert_eq!(druid.received().len(), 2);
+}
+
+#[test]
+fn test_record_and_replay() {
+    let path = std::env::temp_dir().join(format!(
+        "druid-io-testing-{}-fixtures.json",
+        std::process::id()
+    ));
+    let druid = MockDruid::start().unwrap();
+    druid.respond(
+        QueryMatcher::native().query_type("topN"),
+        MockResponse::json(json!([{
+            "timestamp": "2010-01-01T00:00:00.000Z",
+            "result": [{"Code": "AF", "Count": 58}]
+        }])),
+    );
+    let recording = RecordingTransport::new(&path, ReqwestTransport::default()).unwrap();
+    let client = druid.client().with_transport(recording);
+    let recorded = tokio_test::block_on(client.top_n::<Continent>(&top_continents())).unwrap();
+    drop(client);
+    drop(druid);
+
+    let replay = ReplayTransport::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let client = DruidClient::new(vec!["localhost:1".into()]).with_transport(replay);
+    let replayed = tokio_test::block_on(client.top_n::<Continent>(&top_continents())).unwrap();
+    assert_eq!(replayed[0].result[0].code, recorded[0].result[0].code);
+
+    let mut other = top_continents();
+    other.threshold = 2;
+    match tokio_test::block_on(client.top_n::<Continent>(&other)) {
+        Err(DruidClientError::UnmatchedFixture { body, .. }) => {
+            assert!(body.contains("\"threshold\":2"))
+        }
+        result => panic!("expected an unmatched query, got {:?}", result),
+    }
+}
+
+// the countries datasource as the docker cluster ingests it, rolled up into one row per
+// country with the `count` and `sum_PhoneCode` metrics and sorted by the dimensions
+fn ingested_countries() -> InMemoryDruid {
+    let timestamp = TimestampSpec {
+        column: "!!!_no_such_column_!!!".into(),
+        format: None,
+        missing_value: Some("2010-01-01T00:00:00Z".into()),
+    };
+    let mut reader = csv::Reader::from_path("docker/druid/config/countries.csv").unwrap();
+    let headers = reader.headers().unwrap().clone();
+    let mut rows: Vec<Map<String, Value>> = reader
+        .records()
+        .map(|record| {
+            let record = record.unwrap();
+            let mut row: Map<String, Value> = headers
+                .iter()
+                .zip(record.iter())
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(column, value)| (column.to_string(), Value::from(value)))
+                .collect();
+            // druid stores 0 for values that don't parse, like `1809,1829,1849`
+            let phone_code = row.remove("PhoneCode").unwrap();
+            let phone_code: i64 = phone_code.as_str().unwrap().parse().unwrap_or(0);
+            row.insert("count".into(), 1.into());
+            row.insert("sum_PhoneCode".into(), phone_code.into());
+            row
+        })
+        .collect();
+    let dimensions = ["Capital", "Code", "Currency", "languages", "Name"];
+    rows.sort_by_cached_key(|row| {
+        dimensions
+            .iter()
+            .map(|dimension| {
+                row.get(*dimension)
+                    .and_then(Value::as_str)
+                    .map(String::from)
+            })
+            .collect::<Vec<_>>()
+    });
+    let mut druid = InMemoryDruid::new();
+    druid.insert_rows("countries", &timestamp, &rows).unwrap();
+    druid
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedQuery {
+    feature: String,
+    query: Value,
+    response: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tolerance: Option<f64>,
+}
+
+// compares every query of the fixtures, one per query type, filter and aggregator the engine
+// implements, with druid's response, `DRUID_RECORD=1` first replaces the responses with the
+// ones of the docker cluster
+#[test]
+fn test_in_memory_matches_druid() {
+    let path = "tests/fixtures/countries.json";
+    let json = std::fs::read_to_string(path).unwrap();
+    let mut recorded: Vec<RecordedQuery> = serde_json::from_str(&json).unwrap();
+    if std::env::var_os("DRUID_RECORD").is_some() {
+        let client = DruidClient::new(vec!["localhost:8082".to_string()]);
+        for fixture in &mut recorded {
+            let query: Query = serde_json::from_value(fixture.query.clone()).unwrap();
+            let response = tokio_test::block_on(client.query::<Value>(&query)).unwrap();
+            fixture.response = Value::from(response);
+        }
+        let json = serde_json::to_string_pretty(&recorded).unwrap();
+        std::fs::write(path, json + "\n").unwrap();
+    }
+
+    let druid = ingested_countries();
+    for fixture in &recorded {
+        let query: Query = serde_json::from_value(fixture.query.clone())
+            .unwrap_or_else(|error| panic!("{}: {}", fixture.feature, error));
+        let result = druid
+            .run(&query)
+            .unwrap_or_else(|error| panic!("{}: {}", fixture.feature, error));
+        let tolerance = fixture.tolerance.unwrap_or_default();
+        assert!(
+            same_response(&fixture.response, &result, tolerance),
+            "{}: druid responded {}, the in-memory engine {}",
+            fixture.feature,
+            fixture.response,
+            result
+        );
+    }
+}
+
+// equal json except for segment ids, which are druid's own, numbers are compared as doubles
+// within the relative tolerance
+fn same_response(expected: &Value, actual: &Value, tolerance: f64) -> bool {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            fn keys(object: &Map<String, Value>) -> Vec<&String> {
+                let mut keys: Vec<_> = object.keys().filter(|key| *key != "segmentId").collect();
+                keys.sort();
+                keys
+            }
+            keys(expected) == keys(actual)
+                && keys(expected)
+                    .into_iter()
+                    .all(|key| same_response(&expected[key], &actual[key], tolerance))
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() == actual.len()
+                && expected
+                    .iter()
+                    .zip(actual)
+                    .all(|(expected, actual)| same_response(expected, actual, tolerance))
+        }
+        (Value::Number(expected), Value::Number(actual)) => {
+            let (expected, actual) = (expected.as_f64().unwrap(), actual.as_f64().unwrap());
+            (expected - actual).abs() <= expected.abs() * tolerance
+        }
+        (expected, actual) => expected == actual,
+    }
+}